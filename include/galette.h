@@ -0,0 +1,42 @@
+/*
+ * galette.h: C API for the galette GAL assembler.
+ *
+ * Link against the galette cdylib. See src/interop.rs.
+ */
+
+#ifndef GALETTE_H
+#define GALETTE_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Return codes for galette_assemble. */
+#define GALETTE_OK             0
+#define GALETTE_ERROR          1
+#define GALETTE_BAD_ARGUMENT   2
+#define GALETTE_INTERNAL_ERROR 3
+
+/* Output options. Pass NULL to galette_assemble for the defaults
+ * (all outputs, security bit clear). */
+typedef struct {
+    bool gen_fuse;
+    bool gen_chip;
+    bool gen_pin;
+    bool jedec_sec_bit;
+} GaletteConfig;
+
+/* Assemble the .pld file at path, writing the outputs alongside it. */
+int galette_assemble(const char *path, const GaletteConfig *config);
+
+/* The message for the last failed galette_assemble on this thread, or
+ * NULL. Valid until the next call to galette_assemble. */
+const char *galette_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GALETTE_H */
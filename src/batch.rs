@@ -0,0 +1,274 @@
+//
+// batch.rs: Batch builds from a manifest
+//
+// A build manifest lists several source files to assemble in one go,
+// each with its own overrides on top of the command-line options. It
+// uses a small subset of TOML:
+//
+//   [[file]]
+//   input = "decoder.pld"
+//   chip = "GAL16V8"
+//   signature = "DEC 1.2"
+//   secure = true
+//   output_dir = "build"
+//
+// Only 'input' is required. Paths are relative to the manifest. A
+// 'chip' stands in for a missing chip line, as with
+// '--assume-chip-from-filename', and must match one that's present.
+//
+
+use std::path::Path;
+
+use crate::{
+    chips::Chip,
+    errors::{read_failed, Error, ErrorCode, FileError, LineNum, Warning},
+    writer::{DirSink, FileSink, Sink},
+    Config, Profile,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    pub chip: Option<Chip>,
+    // Replaces the source's signature, like '--ues'.
+    pub signature: Option<String>,
+    pub secure: Option<bool>,
+    pub output_dir: Option<String>,
+}
+
+impl Entry {
+    // Apply the entry's overrides to the base config.
+    pub fn config(&self, base: &Config) -> Config {
+        let mut config = base.clone();
+        if let Some(chip) = self.chip {
+            config.parser.assume_chip = Some(chip);
+        }
+        if let Some(sig) = &self.signature {
+            config.ues = Some(sig.as_bytes().to_vec());
+        }
+        if let Some(secure) = self.secure {
+            config.writer.jedec_sec_bit = secure;
+        }
+        config
+    }
+}
+
+fn err<T>(line: LineNum, code: ErrorCode) -> Result<T, Error> {
+    Err(Error { code, line })
+}
+
+// Parse a quoted string, with backslash escapes for quotes and
+// backslashes.
+fn parse_string(s: &str) -> Option<String> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut res = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => res.push(chars.next()?),
+            c => res.push(c),
+        }
+    }
+    // Nothing but a comment may follow.
+    match chars.as_str().trim_start() {
+        rest if rest.is_empty() || rest.starts_with('#') => Some(res),
+        _ => None,
+    }
+}
+
+pub fn parse_manifest(data: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    // Line numbers of each entry's header, for reporting a missing input.
+    let mut starts = Vec::new();
+
+    for (line_num, line) in (1..).zip(data.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[file]]" {
+            entries.push(Entry::default());
+            starts.push(line_num);
+            continue;
+        }
+
+        let ((key, value), entry) = match (line.split_once('='), entries.last_mut()) {
+            (Some(key_value), Some(entry)) => (key_value, entry),
+            _ => return err(line_num, ErrorCode::BadManifestLine),
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let string = || {
+            parse_string(value).ok_or(Error {
+                code: ErrorCode::BadManifestValue {
+                    key: key.to_string(),
+                    expected: "a quoted string",
+                },
+                line: line_num,
+            })
+        };
+        match key {
+            "input" => entry.input = string()?,
+            "chip" => {
+                entry.chip = Some(Chip::from_name(&string()?).map_err(|code| Error {
+                    code,
+                    line: line_num,
+                })?)
+            }
+            "signature" => entry.signature = Some(string()?),
+            "output_dir" => entry.output_dir = Some(string()?),
+            "secure" => {
+                entry.secure = Some(match value.split('#').next().unwrap().trim() {
+                    "true" => true,
+                    "false" => false,
+                    _ => {
+                        return err(
+                            line_num,
+                            ErrorCode::BadManifestValue {
+                                key: key.to_string(),
+                                expected: "true or false",
+                            },
+                        )
+                    }
+                })
+            }
+            _ => {
+                return err(
+                    line_num,
+                    ErrorCode::BadManifestKey {
+                        key: key.to_string(),
+                    },
+                )
+            }
+        }
+    }
+
+    for (entry, line_num) in entries.iter().zip(starts) {
+        if entry.input.is_empty() {
+            return err(line_num, ErrorCode::NoManifestInput);
+        }
+    }
+    Ok(entries)
+}
+
+// The input file's name, and the result of assembling it.
+pub type Outcome = (String, Result<Vec<Warning>, FileError>);
+
+// Assemble every file in the manifest, adding to the profile. An
+// error is only returned if the manifest itself is bad.
+pub fn run(
+    manifest: &str,
+    config: &Config,
+    profile: &mut Profile,
+) -> Result<Vec<Outcome>, FileError> {
+    let entries = std::fs::read_to_string(manifest)
+        .map_err(read_failed)
+        .and_then(|data| parse_manifest(&data))
+        .map_err(|err| FileError {
+            file: manifest.into(),
+            err,
+        })?;
+    let base = Path::new(manifest)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let mut results = Vec::new();
+    for entry in entries.iter() {
+        // Both parts are strings, so the joined path is too.
+        let input = base.join(&entry.input).to_string_lossy().into_owned();
+        let mut sink: Box<dyn Sink> = match &entry.output_dir {
+            Some(dir) => {
+                let dir = base.join(dir);
+                if let Err(e) = std::fs::create_dir_all(&dir) {
+                    let err = Error {
+                        code: ErrorCode::CreateDirFailed {
+                            dir: dir.to_string_lossy().into_owned(),
+                            error: e.to_string(),
+                        },
+                        line: 0,
+                    };
+                    results.push((input.clone(), Err(FileError { file: input, err })));
+                    continue;
+                }
+                Box::new(DirSink { dir })
+            }
+            None => Box::new(FileSink),
+        };
+        let result =
+            crate::assemble_profiled(&input, &entry.config(config), sink.as_mut(), profile);
+        results.push((input, result));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_entries() {
+        let manifest = "# Both boards\n\
+                        [[file]]\n\
+                        input = \"a.pld\"\n\
+                        chip = \"GAL22V10\"\n\
+                        secure = true # lock it\n\
+                        \n\
+                        [[file]]\n\
+                        input = \"b \\\"quoted\\\".pld\"\n\
+                        signature = \"REV2\"\n\
+                        output_dir = \"build\"\n";
+        let entries = parse_manifest(manifest).unwrap();
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    input: String::from("a.pld"),
+                    chip: Some(Chip::GAL22V10),
+                    secure: Some(true),
+                    ..Entry::default()
+                },
+                Entry {
+                    input: String::from("b \"quoted\".pld"),
+                    signature: Some(String::from("REV2")),
+                    output_dir: Some(String::from("build")),
+                    ..Entry::default()
+                },
+            ]
+        );
+
+        let base = Config::default();
+        let (a, b) = (entries[0].config(&base), entries[1].config(&base));
+        assert!(a.writer.jedec_sec_bit);
+        assert_eq!(a.parser.assume_chip, Some(Chip::GAL22V10));
+        assert_eq!(a.ues, None);
+        assert_eq!(b.parser.assume_chip, None);
+        assert!(!b.writer.jedec_sec_bit);
+        assert_eq!(b.ues.as_deref(), Some(&b"REV2"[..]));
+    }
+
+    #[test]
+    fn bad_manifests() {
+        let parse_err = |manifest: &str| parse_manifest(manifest).unwrap_err();
+
+        let e = parse_err("input = \"a.pld\"\n");
+        assert!(matches!(e.code, ErrorCode::BadManifestLine));
+        assert_eq!(e.line, 1);
+
+        let e = parse_err("[[file]]\ninput = \"a.pld\"\nsize = 3\n");
+        assert!(matches!(e.code, ErrorCode::BadManifestKey { key } if key == "size"));
+        assert_eq!(e.line, 3);
+
+        let e = parse_err("[[file]]\ninput = \"a.pld\"\nchip = \"GAL99V9\"\n");
+        assert!(matches!(e.code, ErrorCode::BadGALType { gal } if gal == "GAL99V9"));
+        assert_eq!(e.line, 3);
+
+        let e = parse_err("[[file]]\ninput = a.pld\n");
+        assert!(matches!(e.code, ErrorCode::BadManifestValue { .. }));
+
+        let e = parse_err("[[file]]\ninput = \"a.pld\"\nsecure = yes\n");
+        assert!(matches!(e.code, ErrorCode::BadManifestValue { .. }));
+
+        let e = parse_err("[[file]]\ninput = \"a.pld\"\n[[file]]\nsecure = true\n");
+        assert!(matches!(e.code, ErrorCode::NoManifestInput));
+        assert_eq!(e.line, 3);
+    }
+}
@@ -38,7 +38,9 @@ impl Blueprint {
                 clock: None,
                 arst: None,
                 aprst: None,
+                polarity: None,
                 feedback: false,
+                init: None,
             };
             chip.num_olmcs()
         ];
@@ -64,9 +66,46 @@ impl Blueprint {
             errors::at_line(eqn.line_num, blueprint.add_equation(eqn))?;
         }
 
+        // An init is written in the equation's sense, and stored as
+        // the pin's level.
+        for init in content.inits.iter() {
+            if let Some(i) = blueprint.chip.pin_to_olmc(init.pin.pin) {
+                blueprint.olmcs[i].init = Some(init.level != init.pin.neg);
+            }
+        }
+
         Ok(blueprint)
     }
 
+    // Replace the signature with raw user electronic signature data.
+    pub fn set_ues(&mut self, ues: &[u8]) -> Result<(), ErrorCode> {
+        let max = self.chip.ues_size();
+        if ues.len() > max {
+            return Err(ErrorCode::TooMuchUES {
+                max,
+                seen: ues.len(),
+            });
+        }
+        self.sig = ues.to_vec();
+        Ok(())
+    }
+
+    // Use the given signature if the source's is blank.
+    pub fn default_sig(&mut self, sig: &[u8]) {
+        if self.sig.iter().all(u8::is_ascii_whitespace) {
+            self.sig = sig.to_vec();
+        }
+    }
+
+    // Right-pad the signature to its full width, for tools that expect
+    // every byte to be set.
+    pub fn pad_sig(&mut self, pad: u8) {
+        let size = self.chip.ues_size();
+        if self.sig.len() < size {
+            self.sig.resize(size, pad);
+        }
+    }
+
     // Add an equation to the blueprint, steering it to the appropriate OLMC.
     pub fn add_equation(&mut self, eqn: &Equation) -> Result<(), ErrorCode> {
         let olmcs = &mut self.olmcs;
@@ -81,6 +120,30 @@ impl Blueprint {
 
         let term = eqn_to_term(self.chip, eqn)?;
 
+        // Control terms each get a single row of the fuse array, so
+        // can't be sums. Catch that here, where we can say which
+        // control is at fault.
+        if term.pins.len() > 1 {
+            let control_sum = |suffix| Err(ErrorCode::ControlSum { suffix });
+            match eqn.lhs {
+                LHS::Ar => {
+                    return Err(ErrorCode::SpecialSum {
+                        term: SpecialProductTerm::AR,
+                    })
+                }
+                LHS::Sp => {
+                    return Err(ErrorCode::SpecialSum {
+                        term: SpecialProductTerm::SP,
+                    })
+                }
+                LHS::Pin((_, Suffix::E)) => return control_sum(OutputSuffix::E),
+                LHS::Pin((_, Suffix::CLK)) => return control_sum(OutputSuffix::CLK),
+                LHS::Pin((_, Suffix::ARST)) => return control_sum(OutputSuffix::ARST),
+                LHS::Pin((_, Suffix::APRST)) => return control_sum(OutputSuffix::APRST),
+                LHS::Pin(_) => (),
+            }
+        }
+
         // AR/SP special cases:
         match eqn.lhs {
             LHS::Ar => {
@@ -100,6 +163,15 @@ impl Blueprint {
                 self.sp = Some(term);
             }
             LHS::Pin((pin, suffix)) => {
+                // Power pins get their own error, as 'not an output'
+                // doesn't explain much.
+                if pin.pin == self.chip.num_pins() {
+                    return Err(ErrorCode::AssignedPower { name: "VCC" });
+                }
+                if pin.pin == self.chip.num_pins() / 2 {
+                    return Err(ErrorCode::AssignedPower { name: "GND" });
+                }
+
                 // Only pins with OLMCs may be outputs.
                 let olmc_num = self
                     .chip
@@ -108,24 +180,42 @@ impl Blueprint {
                 let pins = &self.pins;
                 let olmc = &mut olmcs[olmc_num];
 
-                let repeated_err = || ErrorCode::RepeatedOutput {
-                    name: pins[pin.pin - 1].clone(),
+                // A second definition in a different mode gets its own
+                // error, naming both modes, as the fix is different.
+                let previous = olmc
+                    .output
+                    .as_ref()
+                    .map(|(mode, term)| (mode.clone(), term.line_num));
+                let repeated_err = |mode: PinMode| {
+                    let name = pins[pin.pin - 1].clone();
+                    move || match previous {
+                        Some((previous, previous_line)) if previous != mode => {
+                            ErrorCode::ConflictingOutputMode {
+                                name,
+                                mode: mode.description(),
+                                previous: previous.description(),
+                                previous_line,
+                            }
+                        }
+                        _ => ErrorCode::RepeatedOutput { name },
+                    }
                 };
 
                 match suffix {
                     Suffix::R => olmc
                         .set_base(&pin, term, PinMode::Registered)
-                        .ok_or_else(repeated_err),
+                        .ok_or_else(repeated_err(PinMode::Registered)),
                     Suffix::None => olmc
                         .set_base(&pin, term, PinMode::Combinatorial)
-                        .ok_or_else(repeated_err),
+                        .ok_or_else(repeated_err(PinMode::Combinatorial)),
                     Suffix::T => olmc
                         .set_base(&pin, term, PinMode::Tristate)
-                        .ok_or_else(repeated_err),
+                        .ok_or_else(repeated_err(PinMode::Tristate)),
                     Suffix::E => olmc.set_enable(&pin, term),
                     Suffix::CLK => olmc.set_clock(&pin, term),
                     Suffix::ARST => olmc.set_arst(&pin, term),
                     Suffix::APRST => olmc.set_aprst(&pin, term),
+                    Suffix::POL => olmc.set_polarity(&pin, term),
                 }?;
             }
         }
@@ -134,9 +224,19 @@ impl Blueprint {
     }
 }
 
+// A signature identifying the source, for traceability. It's the
+// 32-bit FNV-1a hash of the source file's bytes, as 8 upper-case hex
+// digits, so it can be recomputed by other tools.
+pub fn auto_signature(source: &[u8]) -> Vec<u8> {
+    let hash = source.iter().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    });
+    format!("{:08X}", hash).into_bytes()
+}
+
 // Convert an Equation, which is close to the input syntax, into a
 // Term, which is close to the fuse map representation.
-fn eqn_to_term(chip: Chip, eqn: &Equation) -> Result<Term, ErrorCode> {
+pub(crate) fn eqn_to_term(chip: Chip, eqn: &Equation) -> Result<Term, ErrorCode> {
     // Special case for constant true or false.
     if eqn.rhs.len() == 1 {
         let pin = &eqn.rhs[0];
@@ -161,7 +261,19 @@ fn eqn_to_term(chip: Chip, eqn: &Equation) -> Result<Term, ErrorCode> {
         }
     }
 
-    // Create a list of OR'd terms, each team being a group of AND'd terms.
+    // As in GALasm, a constant mixed with other inputs is an error,
+    // rather than being folded away: 'A * GND' is more likely a
+    // mistake than a long way of writing GND. The fuse array has no
+    // columns for the power pins, so this would fail later anyway.
+    let is_power = |pin: &Pin| pin.pin == chip.num_pins() || pin.pin == chip.num_pins() / 2;
+    if eqn.rhs.iter().any(is_power) {
+        return Err(ErrorCode::BadPower);
+    }
+
+    // Create a list of OR'd terms, each team being a group of AND'd
+    // terms. That is, AND binds tighter than OR, so "A + B * C" means
+    // "A + (B * C)", as in GALasm. There are no parentheses or XOR, as
+    // the equations map directly onto the sum-of-products array.
     let mut ors = Vec::new();
     let mut ands = Vec::new();
 
@@ -192,7 +304,12 @@ pub struct OLMC {
     pub clock: Option<gal::Term>,
     pub arst: Option<gal::Term>,
     pub aprst: Option<gal::Term>,
+    // Overrides the polarity from the equation when set, being VCC
+    // for active high or GND for active low.
+    pub polarity: Option<gal::Term>,
     pub feedback: bool,
+    // The pin's level at power-on, if declared, for the simulator.
+    pub init: Option<bool>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -208,6 +325,17 @@ pub enum PinMode {
     Registered,
 }
 
+impl PinMode {
+    // How the mode is written, for errors.
+    pub fn description(&self) -> &'static str {
+        match self {
+            PinMode::Combinatorial => "combinatorial",
+            PinMode::Tristate => "tristate (.T)",
+            PinMode::Registered => "registered (.R)",
+        }
+    }
+}
+
 impl OLMC {
     pub fn set_base(&mut self, pin: &Pin, term: Term, pin_mode: PinMode) -> Option<()> {
         if self.output.is_some() {
@@ -228,7 +356,7 @@ impl OLMC {
             });
         }
 
-        if self.tri_con != None {
+        if self.tri_con.is_some() {
             return Err(ErrorCode::RepeatedControl {
                 suffix: OutputSuffix::E,
             });
@@ -288,4 +416,305 @@ impl OLMC {
 
         Ok(())
     }
+
+    pub fn set_polarity(&mut self, pin: &Pin, term: Term) -> Result<(), ErrorCode> {
+        if pin.neg {
+            return Err(ErrorCode::InvertedControl {
+                suffix: OutputSuffix::POL,
+            });
+        }
+
+        // Only constant terms make sense, as it's a single fuse.
+        let is_constant = term.pins.is_empty() || term.pins == [Vec::new()];
+        if !is_constant {
+            return Err(ErrorCode::BadPolarity);
+        }
+
+        if self.polarity.is_some() {
+            return Err(ErrorCode::RepeatedControl {
+                suffix: OutputSuffix::POL,
+            });
+        }
+        self.polarity = Some(term);
+
+        Ok(())
+    }
+
+    // The output's polarity, taking any override into account.
+    pub fn get_active(&self) -> Active {
+        match &self.polarity {
+            Some(term) if term.pins.is_empty() => Active::Low,
+            Some(_) => Active::High,
+            None => self.active.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn blueprint(source: &str) -> Blueprint {
+        let content = parser::parse_str(source, &parser::Config::default()).unwrap();
+        Blueprint::from(&content).unwrap()
+    }
+
+    #[test]
+    fn declared_negated_pins() {
+        let source = "GAL16V8\nNeg\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 /O0 /O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n/O1 = I1\n/O2 = I2\nO3 = I3\n\
+                      O0.E = I4\n";
+        let blueprint = blueprint(source);
+        // Declared '/O0' used as 'O0' is active-low, and vice versa.
+        assert_eq!(blueprint.olmcs[0].active, Active::Low);
+        assert_eq!(blueprint.olmcs[1].active, Active::High);
+        assert_eq!(blueprint.olmcs[2].active, Active::Low);
+        assert_eq!(blueprint.olmcs[3].active, Active::High);
+        assert!(blueprint.olmcs[0].tri_con.is_some());
+    }
+
+    #[test]
+    fn declared_negated_registers() {
+        let source = "GAL16V8\nNeg\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC GND\n\
+                      /OE /R0 /R1 /T0 /T1 NC NC NC NC VCC\n\
+                      R0.R = I0\n/R1.R = I1\n\
+                      T0.T = I2\nT0.E = I3\n/T1.T = I2\n";
+        let blueprint = blueprint(source);
+        // The declared and written negations cancel out, whatever
+        // the output's type, and the enable is left alone.
+        let active = blueprint
+            .olmcs
+            .iter()
+            .take(4)
+            .map(|olmc| olmc.active.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            active,
+            [Active::Low, Active::High, Active::Low, Active::High]
+        );
+        assert!(blueprint.olmcs[2].tri_con.is_some());
+    }
+
+    #[test]
+    fn control_sums() {
+        let header = "GAL20RA10\nSums\n\
+                      /PL Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      /OE O0 NC NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0\n";
+        for suffix in ["E", "CLK", "ARST", "APRST"].iter() {
+            let source = format!("{}O0.{} = I1 * I2 + I3\n", header, suffix);
+            let content = parser::parse_str(&source, &parser::Config::default()).unwrap();
+            let e = Blueprint::from(&content).err().unwrap();
+            assert_eq!(e.line, 6);
+            assert_eq!(
+                e.code.to_string(),
+                format!(
+                    ".{} must be a single product term, so can't use OR ('+' or '#')",
+                    suffix
+                )
+            );
+        }
+
+        let header = "GAL22V10\nSums\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 NC NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0\n";
+        for term in ["AR", "SP"].iter() {
+            let source = format!("{}{} = I1 + I2\n", header, term);
+            let content = parser::parse_str(&source, &parser::Config::default()).unwrap();
+            let e = Blueprint::from(&content).err().unwrap();
+            assert!(matches!(e.code, ErrorCode::SpecialSum { .. }));
+            assert!(e.code.to_string().starts_with(term));
+        }
+    }
+
+    #[test]
+    fn repeated_outputs() {
+        let header = "GAL16V8\nRepeat\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 GND\n\
+                      /OE O0 O1 O2 O3 O4 O5 O6 O7 VCC\n";
+        let build = |eqns: &str| {
+            let source = format!("{}{}", header, eqns);
+            let content = parser::parse_str(&source, &parser::Config::default()).unwrap();
+            Blueprint::from(&content).err().unwrap()
+        };
+
+        let e = build("O0.R = I0\nO0.T = I1\n");
+        assert_eq!(e.line, 6);
+        assert_eq!(
+            e.code.to_string(),
+            "output O0 is defined as tristate (.T), \
+             but line 5 defines it as registered (.R)"
+        );
+
+        let e = build("O0 = I0\n/O0 = I1\n");
+        assert_eq!(e.line, 6);
+        assert!(matches!(e.code, ErrorCode::RepeatedOutput { .. }));
+    }
+
+    #[test]
+    fn power_as_output() {
+        let header = "GAL16V8\nPower\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n";
+        for (lhs, power) in [
+            ("GND", "GND"),
+            ("VCC", "VCC"),
+            ("/GND", "GND"),
+            ("VCC.T", "VCC"),
+        ]
+        .iter()
+        {
+            let source = format!("{}O0 = I0\n{} = I1\n", header, lhs);
+            let content = parser::parse_str(&source, &parser::Config::default()).unwrap();
+            let e = Blueprint::from(&content).err().unwrap();
+            assert!(
+                matches!(e.code, ErrorCode::AssignedPower { name } if name == *power),
+                "{}",
+                lhs
+            );
+            assert_eq!(e.line, 6);
+        }
+    }
+
+    #[test]
+    fn power_mid_product() {
+        let header = "GAL16V8\nPower\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n";
+        for rhs in [
+            "I0 * GND",
+            "GND * I0",
+            "I0 * VCC",
+            "I0 + GND",
+            "VCC + I0",
+            "/I0 * /VCC",
+        ]
+        .iter()
+        {
+            let source = format!("{}O0 = I0\nO1 = {}\n", header, rhs);
+            let content = parser::parse_str(&source, &parser::Config::default()).unwrap();
+            let e = Blueprint::from(&content).err().unwrap();
+            assert!(matches!(e.code, ErrorCode::BadPower), "{}", rhs);
+            assert_eq!(e.line, 6);
+        }
+        // Alone, they're constants, even for control terms.
+        blueprint(&format!("{}O0 = GND\nO1.T = VCC\nO1.E = GND\n", header));
+    }
+
+    #[test]
+    fn constants() {
+        let header = "GAL16V8\nConst\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n";
+        let blueprint = blueprint(&format!(
+            "{}O0.T = I0\nO0.E = TRUE\nO1 = FALSE\nO2.T = I1\nO2.E = VCC\nO3 = GND\n",
+            header
+        ));
+        let olmcs = &blueprint.olmcs;
+        assert_eq!(
+            olmcs[0].tri_con.as_ref().unwrap().pins,
+            gal::true_term(0).pins
+        );
+        assert_eq!(
+            olmcs[1].output.as_ref().unwrap().1.pins,
+            gal::false_term(0).pins
+        );
+        // The same as spelling them VCC and GND.
+        assert_eq!(
+            olmcs[0].tri_con.as_ref().unwrap().pins,
+            olmcs[2].tri_con.as_ref().unwrap().pins
+        );
+        assert_eq!(
+            olmcs[1].output.as_ref().unwrap().1.pins,
+            olmcs[3].output.as_ref().unwrap().1.pins
+        );
+
+        // Like VCC and GND, they can't be mixed with other inputs, and
+        // can't be declared as pins.
+        let compile = |source: String| crate::compile(&source).map(|_| ()).unwrap_err().code;
+        assert!(matches!(
+            compile(format!("{}O0 = I0 * TRUE\n", header)),
+            ErrorCode::BadPower
+        ));
+        assert!(matches!(
+            compile(format!("{}O0 = I0 + FALSE\n", header)),
+            ErrorCode::BadPower
+        ));
+        assert!(matches!(
+            compile(header.replace("I9", "TRUE")),
+            ErrorCode::ReservedConstantName { .. }
+        ));
+    }
+
+    #[test]
+    fn auto_signatures() {
+        // Reference values for FNV-1a.
+        assert_eq!(auto_signature(b""), b"811C9DC5");
+        assert_eq!(auto_signature(b"a"), b"E40C292C");
+
+        let source = "GAL16V8\n\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n";
+        let other = source.replace("O0 = I0", "O0 = /I0");
+        assert_eq!(
+            auto_signature(source.as_bytes()),
+            auto_signature(source.as_bytes())
+        );
+        assert_ne!(
+            auto_signature(source.as_bytes()),
+            auto_signature(other.as_bytes())
+        );
+
+        // Only a blank signature is replaced.
+        let sig = auto_signature(source.as_bytes());
+        let mut blank = blueprint(source);
+        blank.default_sig(&sig);
+        assert_eq!(blank.sig, sig);
+        let mut named = blueprint(&source.replace("GAL16V8\n\n", "GAL16V8\nNamed\n"));
+        named.default_sig(&sig);
+        assert_eq!(named.sig, b"Named");
+    }
+
+    #[test]
+    fn precedence() {
+        let header = "GAL16V8\nPrec\n\
+                      A B C D E NC NC NC NC GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC VCC\n";
+        let blueprint = blueprint(&format!(
+            "{}O0 = A + B * C\nO1 = A * B + C\nO2 = A & /B # C & D & E # /A\n",
+            header
+        ));
+        let pin = |pin, neg| Pin { pin, neg };
+        let term = |olmc: usize| blueprint.olmcs[olmc].output.clone().unwrap().1.pins;
+        assert_eq!(
+            term(0),
+            vec![vec![pin(1, false)], vec![pin(2, false), pin(3, false)]]
+        );
+        assert_eq!(
+            term(1),
+            vec![vec![pin(1, false), pin(2, false)], vec![pin(3, false)]]
+        );
+        assert_eq!(
+            term(2),
+            vec![
+                vec![pin(1, false), pin(2, true)],
+                vec![pin(3, false), pin(4, false), pin(5, false)],
+                vec![pin(1, true)]
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_negated_input() {
+        let negated = blueprint("GAL16V8\nNeg\nI0 /I1 NC NC NC NC NC NC NC GND\nNC O0 NC NC NC NC NC NC NC VCC\nO0 = I0 * I1\n");
+        let plain = blueprint("GAL16V8\nNeg\nI0 I1 NC NC NC NC NC NC NC GND\nNC O0 NC NC NC NC NC NC NC VCC\nO0 = I0 * /I1\n");
+        let term = |blueprint: &Blueprint| blueprint.olmcs[0].output.clone().unwrap().1.pins;
+        assert_eq!(term(&negated), term(&plain));
+    }
 }
@@ -42,14 +42,16 @@ struct ChipData {
     // Number of columns per row. Each column represents an element of
     // the AND term - an input, or its negation.
     num_cols: usize,
-    // Total size of the bitstream.
-    total_size: usize,
+    // Size of the user electronic signature (UES) area, in bytes.
+    ues_size: usize,
     // Range of pins that are backed by OLMCs (and can act as
     // programmable outputs).
     min_olmc_pin: usize,
     max_olmc_pin: usize,
     // Mapping from OLMC number to starting row number in the fuse grid.
     olmc_map: &'static [i32],
+    // Human-readable summary of the chip's special features.
+    features: &'static str,
     // NB: Number of rows per OLMC depends on the GAL type, and isn't in
     // ChipData.
 }
@@ -59,10 +61,11 @@ const GAL16V8_DATA: ChipData = ChipData {
     num_pins: 20,
     num_rows: 64,
     num_cols: 32,
-    total_size: 2194,
+    ues_size: 8,
     min_olmc_pin: 12,
     max_olmc_pin: 19,
     olmc_map: &OLMC_ROWS_XXV8,
+    features: "simple/complex/registered modes",
 };
 
 const GAL20V8_DATA: ChipData = ChipData {
@@ -70,10 +73,11 @@ const GAL20V8_DATA: ChipData = ChipData {
     num_pins: 24,
     num_rows: 64,
     num_cols: 40,
-    total_size: 2706,
+    ues_size: 8,
     min_olmc_pin: 15,
     max_olmc_pin: 22,
     olmc_map: &OLMC_ROWS_XXV8,
+    features: "simple/complex/registered modes",
 };
 
 const GAL22V10_DATA: ChipData = ChipData {
@@ -81,10 +85,11 @@ const GAL22V10_DATA: ChipData = ChipData {
     num_pins: 24,
     num_rows: 132,
     num_cols: 44,
-    total_size: 5892,
+    ues_size: 8,
     min_olmc_pin: 14,
     max_olmc_pin: 23,
     olmc_map: &OLMC_ROWS_22V10,
+    features: "AR/SP terms, variable-size OLMCs",
 };
 
 const GAL20RA10_DATA: ChipData = ChipData {
@@ -92,10 +97,11 @@ const GAL20RA10_DATA: ChipData = ChipData {
     num_pins: 24,
     num_rows: 80,
     num_cols: 40,
-    total_size: 3274,
+    ues_size: 8,
     min_olmc_pin: 14,
     max_olmc_pin: 23,
     olmc_map: &OLMC_ROWS_20RA10,
+    features: "per-output .CLK, .ARST and .APRST",
 };
 
 // These constants are used to get the fuse row bounds associated with
@@ -112,6 +118,14 @@ const OLMC_ROWS_XXV8: [i32; 8] = [56, 48, 40, 32, 24, 16, 8, 0];
 const OLMC_ROWS_22V10: [i32; 10] = [122, 111, 98, 83, 66, 49, 34, 21, 10, 1];
 const OLMC_ROWS_20RA10: [i32; 10] = [72, 64, 56, 48, 40, 32, 24, 16, 8, 0];
 
+// All the supported chips, for listing.
+pub const CHIPS: [Chip; 4] = [
+    Chip::GAL16V8,
+    Chip::GAL20V8,
+    Chip::GAL22V10,
+    Chip::GAL20RA10,
+];
+
 impl Chip {
     pub fn from_name(name: &str) -> Result<Chip, ErrorCode> {
         match name {
@@ -142,6 +156,10 @@ impl Chip {
         self.get_chip_data().num_pins
     }
 
+    pub fn num_rows(&self) -> usize {
+        self.get_chip_data().num_rows
+    }
+
     pub fn num_cols(&self) -> usize {
         self.get_chip_data().num_cols
     }
@@ -151,8 +169,41 @@ impl Chip {
         data.num_rows * data.num_cols
     }
 
+    // Total size of the bitstream.
     pub fn total_size(&self) -> usize {
-        self.get_chip_data().total_size
+        self.fuse_sections().iter().map(|(_, size)| size).sum()
+    }
+
+    // Names and sizes of each section of the bitstream, in the order
+    // they're laid out in the JEDEC file.
+    pub fn fuse_sections(&self) -> Vec<(&'static str, usize)> {
+        let data = self.get_chip_data();
+        let num_olmcs = self.num_olmcs();
+        let ues = ("UES", data.ues_size * 8);
+        let mut sections = vec![("array", self.logic_size())];
+        match self {
+            Chip::GAL16V8 | Chip::GAL20V8 => sections.extend([
+                ("XOR", num_olmcs),
+                ues,
+                ("AC1", num_olmcs),
+                // One product term disable bit per row.
+                ("PT", data.num_rows),
+                ("SYN", 1),
+                ("AC0", 1),
+            ]),
+            // S0 and S1 are interleaved in the JEDEC file.
+            Chip::GAL22V10 => sections.extend([("S0", num_olmcs), ("S1", num_olmcs), ues]),
+            Chip::GAL20RA10 => sections.extend([("S0", num_olmcs), ues]),
+        }
+        sections
+    }
+
+    pub fn features(&self) -> &str {
+        self.get_chip_data().features
+    }
+
+    pub fn ues_size(&self) -> usize {
+        self.get_chip_data().ues_size
     }
 
     pub fn pin_to_olmc(&self, pin: usize) -> Option<usize> {
@@ -193,3 +244,27 @@ impl Chip {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_sizes() {
+        // The sizes from the datasheets.
+        assert_eq!(Chip::GAL16V8.total_size(), 2194);
+        assert_eq!(Chip::GAL20V8.total_size(), 2706);
+        assert_eq!(Chip::GAL22V10.total_size(), 5892);
+        assert_eq!(Chip::GAL20RA10.total_size(), 3274);
+    }
+
+    #[test]
+    fn sections_match_gal() {
+        for chip in CHIPS.iter() {
+            let gal = crate::gal::GAL::new(*chip);
+            let sections = chip.fuse_sections();
+            assert_eq!(sections[0], ("array", gal.fuses.len()));
+            assert!(sections.contains(&("UES", gal.sig.len())));
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//
+// compare.rs: Comparison against GALasm
+//
+// Assembles a file with both galette and a GALasm executable, and
+// reports where the .jed, .fus, .pin and .chp files first differ, to
+// check compatibility. GALasm writes its outputs next to its input,
+// so it's run on a copy of the file in a scratch directory. Galette
+// writes GALasm's JEDEC header for the comparison, so that only real
+// differences show up.
+//
+
+use std::{path::Path, process::Command};
+
+use thiserror::Error;
+
+use crate::{
+    errors::FileError,
+    writer::{JedecHeader, MemorySink},
+    Config,
+};
+
+const EXTENSIONS: [&str; 4] = ["jed", "fus", "pin", "chp"];
+
+#[derive(Debug, Error)]
+pub enum CompareError {
+    #[error("unable to run GALasm '{path}': {err}")]
+    Run { path: String, err: std::io::Error },
+    #[error("unable to {action} '{path}': {err}")]
+    Io {
+        action: &'static str,
+        path: String,
+        err: std::io::Error,
+    },
+    #[error("'{path}' isn't a usable file name")]
+    BadPath { path: String },
+    #[error("GALasm failed to assemble the file:\n{output}")]
+    Failed { output: String },
+    #[error("{0}")]
+    Galette(FileError),
+}
+
+// The first line where the two files differ, with each file's line,
+// or None if they're the same.
+pub fn first_difference(ours: &str, theirs: &str) -> Option<(usize, String, String)> {
+    let mut ours = ours.lines();
+    let mut theirs = theirs.lines();
+    for line_num in 1.. {
+        match (ours.next(), theirs.next()) {
+            (None, None) => return None,
+            (a, b) if a == b => (),
+            (a, b) => {
+                let line = |s: Option<&str>| s.map_or(String::from("(end of file)"), String::from);
+                return Some((line_num, line(a), line(b)));
+            }
+        }
+    }
+    unreachable!()
+}
+
+// Assemble the file with galette and with GALasm, returning a
+// description of each output file that differs.
+pub fn compare_with_galasm(
+    file_name: &str,
+    galasm: &Path,
+    config: &Config,
+) -> Result<Vec<String>, CompareError> {
+    let dir = std::env::temp_dir().join(format!("galette-compare-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|err| CompareError::Io {
+        action: "create directory",
+        path: dir.display().to_string(),
+        err,
+    })?;
+    let result = compare_in(&dir, file_name, galasm, config);
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+// Galette works with UTF-8 file names.
+fn path_str(path: &Path) -> Result<&str, CompareError> {
+    path.to_str().ok_or_else(|| CompareError::BadPath {
+        path: path.display().to_string(),
+    })
+}
+
+fn compare_in(
+    dir: &Path,
+    file_name: &str,
+    galasm: &Path,
+    config: &Config,
+) -> Result<Vec<String>, CompareError> {
+    let base_name = Path::new(file_name)
+        .file_name()
+        .ok_or_else(|| CompareError::BadPath {
+            path: file_name.to_string(),
+        })?;
+    let copy = dir.join(base_name);
+    std::fs::copy(file_name, &copy).map_err(|err| CompareError::Io {
+        action: "copy",
+        path: file_name.to_string(),
+        err,
+    })?;
+
+    let output = Command::new(galasm)
+        .arg(base_name)
+        .current_dir(dir)
+        .output()
+        .map_err(|err| CompareError::Run {
+            path: galasm.display().to_string(),
+            err,
+        })?;
+    if !output.status.success() || !copy.with_extension("jed").exists() {
+        let output = String::from_utf8_lossy(&output.stdout).to_string()
+            + &String::from_utf8_lossy(&output.stderr);
+        return Err(CompareError::Failed { output });
+    }
+
+    let mut config = config.clone();
+    config.writer.gen_fuse = true;
+    config.writer.gen_pin = true;
+    config.writer.gen_chip = true;
+    config.writer.jedec_header = JedecHeader::GALasm;
+    // GALasm has no 'OPTIONS' lines, and every output is compared.
+    config.parser.ignore_options = true;
+    let mut sink = MemorySink::default();
+    let copy_name = path_str(&copy)?;
+    crate::assemble_to_sink(copy_name, &config, &mut sink).map_err(CompareError::Galette)?;
+
+    let mut differences = Vec::new();
+    for ext in EXTENSIONS.iter() {
+        let path = copy.with_extension(ext);
+        let name = Path::new(base_name)
+            .with_extension(ext)
+            .display()
+            .to_string();
+        let ours = String::from_utf8_lossy(&sink.files[path_str(&path)?]).to_string();
+        let theirs = match std::fs::read(&path) {
+            Ok(data) => String::from_utf8_lossy(&data).to_string(),
+            Err(_) => {
+                differences.push(format!("{}: not written by GALasm", name));
+                continue;
+            }
+        };
+        if let Some((line, a, b)) = first_difference(&ours, &theirs) {
+            differences.push(format!(
+                "{}: first difference in line {}\n  galette: {}\n  GALasm:  {}",
+                name, line, a, b
+            ));
+        }
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_difference_lines() {
+        assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+        assert_eq!(
+            first_difference("a\nb\nc\n", "a\nB\nc\n"),
+            Some((2, String::from("b"), String::from("B")))
+        );
+        assert_eq!(
+            first_difference("a\n", "a\nb\n"),
+            Some((2, String::from("(end of file)"), String::from("b")))
+        );
+    }
+
+    #[test]
+    fn missing_input() {
+        let result =
+            compare_with_galasm("no-such-file.pld", Path::new("galasm"), &Config::default());
+        assert!(matches!(
+            result,
+            Err(CompareError::Io { action: "copy", path, .. }) if path == "no-such-file.pld"
+        ));
+        let result = compare_with_galasm("..", Path::new("galasm"), &Config::default());
+        assert!(matches!(result, Err(CompareError::BadPath { path }) if path == ".."));
+    }
+}
@@ -20,35 +20,95 @@ pub struct FileError {
     pub err: Error,
 }
 
+// Errors that don't come from a source line, such as bad options,
+// use line 0.
 #[derive(Clone, Debug, Error)]
-#[error("Error in line {}: {}", line, code)]
 pub struct Error {
     pub code: ErrorCode,
     pub line: LineNum,
 }
 
-#[derive(Clone, Debug, Error)]
-pub enum ErrorCode {
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "Error: {}", self.code),
+            line => write!(f, "Error in line {}: {}", line, self.code),
+        }
+    }
+}
+
+// Declares ErrorCode along with a name and message template for
+// each variant, so that code_name and catalogue can't drift apart
+// from the enum.
+macro_rules! error_codes {
+    ($(#[error($template:literal)] $name:ident $({ $($field:ident: $ty:ty),* $(,)? })?,)*) => {
+        #[derive(Clone, Debug, Error)]
+        pub enum ErrorCode {
+            $(#[error($template)] $name $({ $($field: $ty),* })?,)*
+        }
+
+        impl ErrorCode {
+            // The variant's name, e.g. "BadNC", which tools can rely on
+            // staying the same when the message is reworded.
+            pub fn code_name(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name { .. } => stringify!($name),)*
+                }
+            }
+        }
+
+        // Every ErrorCode's name and message template, in the order
+        // they're declared.
+        pub fn catalogue() -> &'static [(&'static str, &'static str)] {
+            &[$((stringify!($name), $template),)*]
+        }
+
+        // The fields each variant's template may refer to.
+        #[cfg(test)]
+        const FIELDS: &[(&str, &[&str])] = &[$((stringify!($name), &[$($(stringify!($field)),*)?]),)*];
+    };
+}
+
+error_codes! {
     #[error("GAL22V10: {term} is not allowed as pinname")]
     ReservedPinName { term: SpecialProductTerm },
+    #[error("{name} is reserved as a constant, so can't be used as a pin name")]
+    ReservedConstantName { name: String },
     #[error("no suffix is allowed for {term}")]
     SpecialSuffix { term: SpecialProductTerm },
-    #[error("internal error: analyse_mode should never let you use this pin as an input")]
-    BadAnalysis,
     #[error("use of {term} is not allowed in equations")]
     BadSpecial { term: SpecialProductTerm },
     #[error("unexpected character in input: '{c}'")]
     BadChar { c: char },
     #[error("expected right-hand side of equation, found end of file")]
     BadEquationEOF,
+    #[error("expected continuation of equation after trailing operator, found end of file")]
+    ContinuationEOF,
+    #[error("{directive} without a matching #ifdef")]
+    UnmatchedDirective { directive: &'static str },
+    #[error("#ifdef without a matching #endif")]
+    UnterminatedIfdef,
+    #[error("multiple #else directives for the same #ifdef")]
+    RepeatedElse,
+    #[error("bad {directive} directive: expected {expected}")]
+    BadDirective {
+        directive: &'static str,
+        expected: &'static str,
+    },
     #[error("expected pin name, found end of line")]
     BadEOL,
     #[error("unexpected GAL type found: '{gal}'")]
     BadGALType { gal: String },
+    #[error("the source is for the {found}, but the {expected} was expected")]
+    ChipMismatch { found: String, expected: String },
     #[error("NC (Not Connected) is not allowed in logic equations")]
     BadNC,
     #[error("wrong number of pins on pin definition line - expected {expected}, found {found}")]
     BadPinCount { found: usize, expected: usize },
+    #[error("wrong number of pins on pin definition line - expected {expected}, found {found} (tabs separate pin names, so check for a tab inside a name)")]
+    BadPinCountTab { found: usize, expected: usize },
+    #[error("expected pin definitions, found an equation; the two rows of pin names must come before any equations")]
+    EquationBeforePins,
     #[error("expected pin definitions, found end of file")]
     BadPinEOF,
     #[error("expected plain pin name, found pin with suffix")]
@@ -57,24 +117,44 @@ pub enum ErrorCode {
     BadPower,
     #[error("expected signature, found end of file")]
     BadSigEOF,
-    #[error("unknown suffix found: '{suffix}'")]
-    BadSuffix { suffix: String },
+    #[error("the signature is blank, but a signature is required")]
+    BlankSignature,
+    #[error("unknown suffix found: '{suffix}'; the valid suffixes are {valid}")]
+    BadSuffix { suffix: String, valid: &'static str },
     #[error("expected {expected}, found other token")]
     BadToken { expected: &'static str },
+    #[error("only pins with an OLMC can have a second name, but pin {pin} has no OLMC")]
+    SecondNameNotOutput { pin: usize },
+    #[error("pin {pin} can't be NC and have a second name")]
+    SecondNameNC { pin: usize },
+    #[error("unknown option '{name}'; the valid options are secure, nochip, nofuse and nopin")]
+    UnknownOption { name: String },
     #[error("pin {pin} must be named {name}")]
     InvalidPowerPinName { pin: usize, name: &'static str },
+    #[error("pin {pin} must be named {expected}, not {name}; are VCC and GND swapped?")]
+    SwappedPowerPins {
+        pin: usize,
+        name: &'static str,
+        expected: &'static str,
+    },
     #[error(
-        "pin {pin} cannot be named {name}, because the name is reserved for pin {expected_pin}"
+        "{name} is a reserved name for the power pin, pin {expected_pin}, so pin {pin} can't use it as a signal name"
     )]
     InvalidPowerPinLocation {
         pin: usize,
         name: &'static str,
         expected_pin: usize,
     },
+    #[error(".CLK is not allowed on the {chip}, as registers are always clocked by pin 1 (a registered output may only give pin 1 as its .CLK, to document this)")]
+    FixedClock { chip: String },
     #[error(".{suffix} is not allowed when this type of GAL is used")]
     DisallowedControl { suffix: OutputSuffix },
     #[error("use of .{suffix} is only allowed for registered outputs")]
     InvalidControl { suffix: OutputSuffix },
+    #[error("{name} is negated with both a leading and a trailing '/'")]
+    InvertedTwice { name: String },
+    #[error("a trailing '/' is only allowed on the left-hand side of an equation")]
+    PostfixNegation,
     #[error("negation of {term} is not allowed")]
     InvertedSpecial { term: SpecialProductTerm },
     #[error("negation of .{suffix} is not allowed")]
@@ -84,10 +164,22 @@ pub enum ErrorCode {
         name: &'static str,
         hint: &'static str,
     },
+    #[error("{chip} has no product term disable bits, so unused rows must be cleared")]
+    NoProductTermBits { chip: String },
+    #[error("{name} is a power pin, so can't be assigned an equation")]
+    AssignedPower { name: &'static str },
     #[error("only one product term allowed (no OR)")]
     MoreThanOneProduct,
+    #[error(".{suffix} must be a single product term, so can't use OR ('+' or '#')")]
+    ControlSum { suffix: OutputSuffix },
+    #[error("{term} must be a single product term, so can't use OR ('+' or '#')")]
+    SpecialSum { term: SpecialProductTerm },
     #[error("missing clock definition (.CLK) of registered output")]
     NoCLK,
+    #[error("expected +, #, * or & before '{name}' (tabs and spaces always separate pin names)")]
+    MissingOperator { name: String },
+    #[error("expected DESCRIPTION section, found end of file")]
+    NoDescription,
     #[error("'=' expected")]
     NoEquals,
     #[error("pin name expected after '/', found non-alphabetic character '{c}'")]
@@ -98,10 +190,28 @@ pub enum ErrorCode {
         "pin {pin} is reserved for '{name}' on GAL20RA10 devices and can't be used in equations"
     )]
     ReservedInputGAL20RA10 { pin: usize, name: &'static str },
+    #[error("':=' already means registered, so the pin can't also have a suffix")]
+    RegEqualsSuffix,
+    #[error("':=' can only be used to assign output pins")]
+    RegEqualsSpecial,
+    #[error("the outputs in a comma-separated list must all be pins with the same suffix")]
+    MixedOutputList,
+    #[error("polarity must be VCC (active high) or GND (active low)")]
+    BadPolarity,
+    #[error("pin name too long (max: {max} characters)")]
+    NameTooLong { max: usize },
+    #[error("too many terms in equation (max: {max})")]
+    TooManyTerms { max: usize },
+    #[error("equation continues over too many lines (max: {max})")]
+    TooManyContinuationLines { max: usize },
+    #[error("pin {pin} does not exist on this chip")]
+    PinOutOfRange { pin: usize },
     #[error("pin {pin} is reserved for '{name}' in registered mode")]
     ReservedRegisteredInput { pin: usize, name: &'static str },
-    #[error("pin {pin} can't be used as input in complex mode")]
+    #[error("pin {pin} can't be used as input in complex mode, which is selected by tristate outputs or outputs used as inputs; only simple and registered modes allow it")]
     NotAnComplexModeInput { pin: usize },
+    #[error("pin {pin} can't be used as input in simple mode; only complex and registered modes allow it")]
+    NotASimpleModeInput { pin: usize },
     #[error("this pin can't be used as output")]
     NotAnOutput,
     #[error("{term} is defined twice")]
@@ -110,18 +220,121 @@ pub enum ErrorCode {
     RepeatedControl { suffix: OutputSuffix },
     #[error("output {name} is defined multiple times")]
     RepeatedOutput { name: String },
+    #[error("INIT must be 0 or 1, not '{value}'")]
+    BadInit { value: String },
+    #[error("INIT only applies to registered (.R) outputs")]
+    InitNotRegistered,
+    #[error(
+        "output {name} is defined as {mode}, but line {previous_line} defines it as {previous}"
+    )]
+    ConflictingOutputMode {
+        name: String,
+        mode: &'static str,
+        previous: &'static str,
+        previous_line: LineNum,
+    },
     #[error("pinname {name} is defined twice")]
     RepeatedPinName { name: String },
+    #[error("{name} names several paralleled pins, so can only be an input")]
+    GroupAsOutput { name: String },
+    #[error("the pins of paralleled input {name} must all have the same negation")]
+    InconsistentGroup { name: String },
     #[error("the output must be defined to use .{suffix}")]
     UndefinedOutput { suffix: OutputSuffix },
+    #[error("tab found inside pin name (tabs always separate pin names)")]
+    TabInName,
     #[error("too many product terms in sum for pin (max: {max}, saw: {seen})")]
     TooManyProducts { max: usize, seen: usize },
+    #[error("too much user electronic signature data (max: {max} bytes, saw: {seen})")]
+    TooMuchUES { max: usize, seen: usize },
     #[error("GAL16V8/20V8: tri. control for reg. output is not allowed")]
     TristateReg,
     #[error("unknown pinname '{name}'")]
     UnknownPin { name: String },
     #[error("tristate control without previous '.T'")]
     UnmatchedTristate,
+    #[error("ASSERT can only check combinatorial outputs, which {name} isn't")]
+    AssertNotCombinatorial { name: String },
+    #[error("ASSERT depends on too many inputs to check every combination (max: {max})")]
+    AssertTooManyInputs { max: usize },
+    #[error("ASSERT for {name} fails with {inputs}, where {name} is {level}")]
+    AssertionFailed {
+        name: String,
+        inputs: String,
+        level: u8,
+    },
+    #[error("pin {pin} {reason}, which needs complex mode, but the mode is fixed")]
+    ComplexModeNeeded { pin: usize, reason: &'static str },
+    #[error("internal error: generated {found} fuses, but the chip has {expected}")]
+    FuseCountMismatch { expected: usize, found: usize },
+    #[error("expected '[[file]]' or 'key = value' in a file entry")]
+    BadManifestLine,
+    #[error("unknown key '{key}'; expected input, chip, signature, secure or output_dir")]
+    BadManifestKey { key: String },
+    #[error("expected {expected} for '{key}'")]
+    BadManifestValue { key: String, expected: &'static str },
+    #[error("file entry has no input")]
+    NoManifestInput,
+    #[error("no fuse count ('*QF') found in the JEDEC file")]
+    NoJedecFuseCount,
+    #[error("no supported chip has {size} fuses")]
+    UnknownJedecSize { size: usize },
+    #[error("badly formed '*{field}' field in the JEDEC file")]
+    BadJedecField { field: &'static str },
+    #[error("the fuses listed from {address} run past the end of the chip's {size} fuses")]
+    JedecFusesOutOfRange { address: usize, size: usize },
+    #[error("the {chip}'s SYN and AC0 fuses are both clear, which isn't a valid mode")]
+    BadJedecMode { chip: String },
+    #[error("expected {expected} pin names for the {chip}, found {found}")]
+    BadPinNameCount {
+        chip: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("unable to read the file: {error}")]
+    ReadFailed { error: String },
+    #[error("unable to write the file: {error}")]
+    WriteFailed { error: String },
+    #[error("unable to create directory {dir}: {error}")]
+    CreateDirFailed { dir: String, error: String },
+}
+
+// Warnings are like errors, but don't stop assembly.
+#[derive(Clone, Debug, Error)]
+#[error("Warning in line {}: {}", line, code)]
+pub struct Warning {
+    pub code: WarningCode,
+    pub line: LineNum,
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum WarningCode {
+    #[error("{name} is an output, so using it as an input reads {reads}")]
+    OutputFeedback { name: String, reads: &'static str },
+    #[error("{name} has the same equation as {other} (line {other_line})")]
+    DuplicateEquation {
+        name: String,
+        other: String,
+        other_line: LineNum,
+    },
+    #[error("{name} has product '{product}', which is covered by '{covering}' and can be removed")]
+    RedundantProduct {
+        name: String,
+        product: String,
+        covering: String,
+    },
+    #[error("{name} (pin {pin}) is an output pin with no equation, and isn't used as an input; name it NC if it's meant to be unused")]
+    UnusedOLMC { name: String, pin: usize },
+    #[error("the signature is blank; consider a label such as the design's name and revision")]
+    EmptySignature,
+    #[error("no chip type on the first line, so assuming {chip} from the file name")]
+    AssumedChip { chip: String },
+    #[error(
+        "the column numbers in the comment above count {numbers} pins, but the row has {pins}"
+    )]
+    PinNumberMismatch { numbers: usize, pins: usize },
+    #[error("line is {len} characters long, over the limit of {max}; consider continuing it on the next line after a '+' or '*'")]
+    LongLine { len: usize, max: usize },
 }
 
 // Adapt an ErrorCode to an Error.
@@ -129,12 +342,32 @@ pub fn at_line<Val>(line: LineNum, res: Result<Val, ErrorCode>) -> Result<Val, E
     res.map_err(|e| Error { code: e, line })
 }
 
+// File system errors aren't tied to a source line.
+pub fn read_failed(e: std::io::Error) -> Error {
+    Error {
+        code: ErrorCode::ReadFailed {
+            error: e.to_string(),
+        },
+        line: 0,
+    }
+}
+
+pub fn write_failed(e: std::io::Error) -> Error {
+    Error {
+        code: ErrorCode::WriteFailed {
+            error: e.to_string(),
+        },
+        line: 0,
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum OutputSuffix {
     APRST,
     ARST,
     CLK,
     E,
+    POL,
 }
 
 impl fmt::Display for OutputSuffix {
@@ -144,6 +377,7 @@ impl fmt::Display for OutputSuffix {
             Self::ARST => "ARST",
             Self::CLK => "CLK",
             Self::E => "E",
+            Self::POL => "POL",
         })
     }
 }
@@ -174,3 +408,48 @@ impl fmt::Display for SpecialProductTerm {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalogue() {
+        let entries = super::catalogue();
+        assert!(entries.contains(&(
+            "BadNC",
+            "NC (Not Connected) is not allowed in logic equations"
+        )));
+        assert!(entries.contains(&("BadChar", "unexpected character in input: '{c}'")));
+        assert!(entries.contains(&(
+            "InvalidPowerPinLocation",
+            "{name} is a reserved name for the power pin, pin {expected_pin}, so pin {pin} can't use it as a signal name"
+        )));
+
+        let mut names = entries.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), entries.len());
+
+        // Every variant's template only refers to its own fields.
+        assert_eq!(entries.len(), FIELDS.len());
+        for ((name, template), (field_name, fields)) in entries.iter().zip(FIELDS) {
+            assert_eq!(name, field_name);
+            let mut rest = *template;
+            while let Some(i) = rest.find('{') {
+                let end = rest[i..].find('}').unwrap() + i;
+                let field = &rest[i + 1..end];
+                assert!(
+                    fields.contains(&field),
+                    "{}: unknown field '{}'",
+                    name,
+                    field
+                );
+                rest = &rest[end + 1..];
+            }
+        }
+
+        assert_eq!(ErrorCode::BadNC.code_name(), "BadNC");
+        assert_eq!(ErrorCode::BadChar { c: '!' }.code_name(), "BadChar");
+    }
+}
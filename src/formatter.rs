@@ -0,0 +1,288 @@
+//
+// formatter.rs: Source reformatter
+//
+// Re-emit a .pld file in a canonical layout: pin rows are aligned
+// into columns, and equations get consistent operator spacing. Unlike
+// the parser, we keep the comments, blank lines and the DESCRIPTION
+// section, so the result can replace the original file.
+//
+
+use crate::{
+    errors::{Error, LineNum},
+    parser::{self, Token},
+};
+
+// A line before the equations, either a pin row or something we pass
+// through.
+enum HeaderLine<'a> {
+    Pins(Vec<String>, &'a str),
+    Other(&'a str),
+}
+
+pub fn format_str(data: &str) -> Result<String, Error> {
+    // Only reformat sources that parse, so a broken file is reported
+    // rather than mangled.
+    let config = parser::Config::default();
+    let chip = parser::parse_str(data, &config)?.chip;
+    let limits = config.limits;
+    let data = parser::normalise_line_endings(data);
+
+    let mut buf = String::new();
+    let mut lines = (1..).zip(data.lines());
+
+    // Chip and signature lines just get their whitespace tidied.
+    for (_, line) in lines.by_ref().take(2) {
+        let (code, comment) = split_comment(line);
+        push_line(&mut buf, 0, code.trim(), comment);
+    }
+
+    // Gather everything up to the second pin row, so the two rows can
+    // be aligned with each other.
+    let mut header = Vec::new();
+    let mut num_rows = 0;
+    while num_rows < 2 {
+        let (line_num, line) = lines.next().unwrap();
+        let (code, comment) = split_comment(line);
+        if code.trim().is_empty() || parser::is_options(code) {
+            header.push(HeaderLine::Other(line));
+        } else {
+            // A pin's second name stays in its column, as 'A,B', and
+            // group markers are put back on their pins.
+            let (grouped, stripped) = parser::strip_group_markers(code);
+            let mut names: Vec<String> = Vec::new();
+            let mut joined = false;
+            for (_, token) in parser::tokenise(&limits, chip, (line_num, &stripped))?.iter() {
+                let name = token.to_string();
+                match names.last_mut() {
+                    Some(last) if joined || matches!(token, Token::Comma) => last.push_str(&name),
+                    _ => names.push(name),
+                }
+                joined = matches!(token, Token::Comma);
+            }
+            for (name, grouped) in names.iter_mut().zip(grouped.iter()) {
+                if *grouped {
+                    name.insert(0, '@');
+                }
+            }
+            header.push(HeaderLine::Pins(names, comment));
+            num_rows += 1;
+        }
+    }
+
+    let mut widths = Vec::new();
+    for line in header.iter() {
+        if let HeaderLine::Pins(names, _) = line {
+            widths.resize(names.len(), 0);
+            for (width, name) in widths.iter_mut().zip(names.iter()) {
+                *width = usize::max(*width, name.len());
+            }
+        }
+    }
+
+    for line in header.iter() {
+        match line {
+            HeaderLine::Pins(names, comment) => {
+                let row = names
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(name, width)| format!("{:width$}", name, width = width))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                push_line(&mut buf, 0, row.trim_end(), comment);
+            }
+            HeaderLine::Other(line) => push_line(&mut buf, 0, "", line.trim_end()),
+        }
+    }
+
+    // Equations. Continuation lines are indented to line up with the
+    // start of the right-hand side.
+    let mut indent = 0;
+    let mut continues = false;
+    for (line_num, line) in lines.by_ref() {
+        let (code, comment) = split_comment(line);
+        if code.trim() == "DESCRIPTION" {
+            push_line(&mut buf, 0, code.trim(), comment);
+            break;
+        }
+
+        // Directives don't take part in the equation layout.
+        if parser::is_directive(code) {
+            let words = code.split_whitespace().collect::<Vec<_>>();
+            push_line(&mut buf, 0, &words.join(" "), comment);
+            continue;
+        }
+
+        let tokens = parser::tokenise(&limits, chip, (line_num, code))?;
+        if tokens.is_empty() {
+            push_line(&mut buf, 0, "", comment.trim_end());
+            continue;
+        }
+
+        let starts_with_op = matches!(tokens.first(), Some((_, Token::And | Token::Or)));
+        let starts_with_eq = matches!(tokens.first(), Some((_, Token::Equals | Token::RegEquals)));
+        let line_indent = if starts_with_eq {
+            // The LHS is on the line above, so indent the RHS a little.
+            indent = 6;
+            4
+        } else if continues || starts_with_op {
+            indent
+        } else {
+            indent = equals_column(&tokens);
+            0
+        };
+        continues = matches!(tokens.last(), Some((_, Token::And | Token::Or)));
+
+        push_line(&mut buf, line_indent, &join_tokens(&tokens), comment);
+    }
+
+    // Anything after DESCRIPTION is kept verbatim.
+    for (_, line) in lines {
+        buf.push_str(line);
+        buf.push('\n');
+    }
+
+    Ok(buf)
+}
+
+// Split a line into the code and the (possibly empty) comment.
+fn split_comment(line: &str) -> (&str, &str) {
+    let code = parser::remove_comment(line);
+    (code, &line[code.len()..])
+}
+
+fn push_line(buf: &mut String, indent: usize, code: &str, comment: &str) {
+    let comment = comment.trim_end();
+    buf.push_str(&" ".repeat(indent));
+    buf.push_str(code);
+    if !code.is_empty() && !comment.is_empty() {
+        buf.push(' ');
+    }
+    buf.push_str(comment);
+    buf.push('\n');
+}
+
+// Column at which the right-hand side of an equation starts, for
+// aligning continuation lines.
+fn equals_column(tokens: &[(LineNum, Token)]) -> usize {
+    tokens
+        .iter()
+        .position(|(_, token)| matches!(token, Token::Equals | Token::RegEquals))
+        .map_or(0, |i| join_tokens(&tokens[..=i]).len() + 1)
+}
+
+// Tokens separated by spaces, except before a comma.
+fn join_tokens(tokens: &[(LineNum, Token)]) -> String {
+    let mut text = String::new();
+    for (_, token) in tokens.iter() {
+        if !text.is_empty() && *token != Token::Comma {
+            text.push(' ');
+        }
+        text.push_str(&token.to_string());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "GAL16V8   ; Chip\n\
+                         Messy\n\
+                         \n\
+                         ; Pins\n\
+                         Clock I0 I1 I2 I3 I4 I5 NC NC GND ; Row 1\n\
+                         /OE O0 O1\tO2 O3 O4 NC NC NC VCC\n\
+                         \n\
+                         O0=I0&I1 ; And\n\
+                         O1 = I2#I3\n\
+                         O2.T = I4 * /I5 +\n\
+                         /I4*I5\n\
+                         O2.E=I0\n\
+                         O3\n\
+                         =I1 * I2 +\n\
+                         I3\n\
+                         \n\
+                         DESCRIPTION\n\
+                         \n\
+                         Left   alone.\n";
+
+    #[test]
+    fn format_layout() {
+        let expected = "GAL16V8 ; Chip\n\
+                        Messy\n\
+                        \n\
+                        ; Pins\n\
+                        Clock I0 I1 I2 I3 I4 I5 NC NC GND ; Row 1\n\
+                        /OE   O0 O1 O2 O3 O4 NC NC NC VCC\n\
+                        \n\
+                        O0 = I0 * I1 ; And\n\
+                        O1 = I2 + I3\n\
+                        O2.T = I4 * /I5 +\n       \
+                        /I4 * I5\n\
+                        O2.E = I0\n\
+                        O3\n    \
+                        = I1 * I2 +\n      \
+                        I3\n\
+                        \n\
+                        DESCRIPTION\n\
+                        \n\
+                        Left   alone.\n";
+        assert_eq!(format_str(MESSY).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_second_names() {
+        let source = "GAL16V8\nNames\n\
+                      I0 I1 NC NC NC NC NC NC NC GND\n\
+                      NC NC NC DQOUT , DQIN O0 NC NC NC NC VCC\n\
+                      O0 = DQIN\n";
+        let expected = "GAL16V8\nNames\n\
+                        I0 I1 NC NC         NC NC NC NC NC GND\n\
+                        NC NC NC DQOUT,DQIN O0 NC NC NC NC VCC\n\
+                        O0 = DQIN\n";
+        assert_eq!(format_str(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_groups() {
+        let source = "GAL16V8\nGroups\n\
+                      @DATA   @DATA I0 NC NC NC NC NC NC GND\n\
+                      NC NC O0 O1 NC NC NC NC NC VCC\n\
+                      O0 = DATA * I0\n";
+        let expected = "GAL16V8\nGroups\n\
+                        @DATA @DATA I0 NC NC NC NC NC NC GND\n\
+                        NC    NC    O0 O1 NC NC NC NC NC VCC\n\
+                        O0 = DATA * I0\n";
+        assert_eq!(format_str(source).unwrap(), expected);
+        assert_eq!(format_str(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_output_list() {
+        let source = "GAL16V8\nList\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 ,O1,O2=I0*I1+\n\
+                      I2\n";
+        let expected = "GAL16V8\nList\n\
+                        I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                        I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                        O0, O1, O2 = I0 * I1 +\n\
+                        \x20            I2\n";
+        assert_eq!(format_str(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn format_idempotent() {
+        let once = format_str(MESSY).unwrap();
+        let twice = format_str(&once).unwrap();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn format_preserves_logic() {
+        let before = crate::compile(MESSY).unwrap();
+        let after = crate::compile(&format_str(MESSY).unwrap()).unwrap();
+        assert_eq!(before.gal.fuses, after.gal.fuses);
+    }
+}
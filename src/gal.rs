@@ -15,7 +15,7 @@ pub use crate::chips::Bounds;
 
 // A 'Pin' represents an input to an equation - a potentially negated
 // pin (represented by pin number).
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Pin {
     pub pin: usize,
     pub neg: bool,
@@ -34,12 +34,27 @@ pub struct Term {
     pub pins: Vec<Vec<Pin>>,
 }
 
+// One row of the fuse array, as read back by 'GAL::decoded_matrix'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowDecode {
+    pub row: usize,
+    // The OLMC whose rows this is in, or None for rows shared by the
+    // whole chip, such as the GAL22V10's AR and SP.
+    pub olmc: Option<usize>,
+    // The inputs ANDed together on the row, or None if the row can
+    // never be true.
+    pub inputs: Option<Vec<Pin>>,
+}
+
 // The 'GAL' struct represents the fuse state of the GAL that we're
 // going to program.
 pub struct GAL {
     pub chip: Chip,
     pub fuses: Vec<bool>,
     pub xor: Vec<bool>,
+    // The signature holds 'ues_size' bytes, in order, each stored
+    // MSB-first. e.g. "A" (0x41) is stored as 0, 1, 0, 0, 0, 0, 0, 1.
+    // Unused bytes are left as zero.
     pub sig: Vec<bool>,
     pub ac1: Vec<bool>,
     pub pt: Vec<bool>,
@@ -64,7 +79,6 @@ pub enum Mode {
 // depend on the mode settings for the GALxxV8s, so they're here rather
 // than in chips.rs.
 
-const BAD: Result<i32, ErrorCode> = Err(ErrorCode::BadAnalysis);
 const PWR: Result<i32, ErrorCode> = Err(ErrorCode::BadPower);
 
 const REG_P1: Result<i32, ErrorCode> = Err(ErrorCode::ReservedRegisteredInput {
@@ -80,6 +94,13 @@ const REG_P13: Result<i32, ErrorCode> = Err(ErrorCode::ReservedRegisteredInput {
     name: "/OE",
 });
 
+// 'analyse_mode' picks complex mode when these are used as inputs,
+// but a GAL built by hand may not.
+const SMPL_P15: Result<i32, ErrorCode> = Err(ErrorCode::NotASimpleModeInput { pin: 15 });
+const SMPL_P16: Result<i32, ErrorCode> = Err(ErrorCode::NotASimpleModeInput { pin: 16 });
+const SMPL_P18: Result<i32, ErrorCode> = Err(ErrorCode::NotASimpleModeInput { pin: 18 });
+const SMPL_P19: Result<i32, ErrorCode> = Err(ErrorCode::NotASimpleModeInput { pin: 19 });
+
 const CPLX_P12: Result<i32, ErrorCode> = Err(ErrorCode::NotAnComplexModeInput { pin: 12 });
 const CPLX_P15: Result<i32, ErrorCode> = Err(ErrorCode::NotAnComplexModeInput { pin: 15 });
 const CPLX_P19: Result<i32, ErrorCode> = Err(ErrorCode::NotAnComplexModeInput { pin: 19 });
@@ -97,8 +118,8 @@ const P13_20RA10: Result<i32, ErrorCode> = Err(ErrorCode::ReservedInputGAL20RA10
 // GAL16V8
 #[rustfmt::skip]
 const PIN_TO_COL_16_SIMPLE: [Result<i32, ErrorCode>; 20] = [
-    Ok(2),  Ok(0),  Ok(4),  Ok(8),  Ok(12), Ok(16), Ok(20), Ok(24), Ok(28), PWR,
-    Ok(30), Ok(26), Ok(22), Ok(18), BAD,    BAD,    Ok(14), Ok(10), Ok(6),  PWR,
+    Ok(2),  Ok(0),  Ok(4),  Ok(8),  Ok(12),   Ok(16),   Ok(20), Ok(24), Ok(28), PWR,
+    Ok(30), Ok(26), Ok(22), Ok(18), SMPL_P15, SMPL_P16, Ok(14), Ok(10), Ok(6),  PWR,
 ];
 #[rustfmt::skip]
 const PIN_TO_COL_16_COMPLEX: [Result<i32, ErrorCode>; 20] = [
@@ -114,8 +135,8 @@ const PIN_TO_COL_16_REGISTERED: [Result<i32, ErrorCode>; 20] = [
 // GAL20V8
 #[rustfmt::skip]
 const PIN_TO_COL_20_SIMPLE: [Result<i32, ErrorCode>; 24] = [
-    Ok(2),  Ok(0),  Ok(4),  Ok(8),  Ok(12), Ok(16), Ok(20), Ok(24), Ok(28), Ok(32), Ok(36), PWR,
-    Ok(38), Ok(34), Ok(30), Ok(26), Ok(22), BAD,    BAD,    Ok(18), Ok(14), Ok(10), Ok(6),  PWR,
+    Ok(2),  Ok(0),  Ok(4),  Ok(8),  Ok(12), Ok(16),   Ok(20),   Ok(24), Ok(28), Ok(32), Ok(36), PWR,
+    Ok(38), Ok(34), Ok(30), Ok(26), Ok(22), SMPL_P18, SMPL_P19, Ok(18), Ok(14), Ok(10), Ok(6),  PWR,
 ];
 #[rustfmt::skip]
 const PIN_TO_COL_20_COMPLEX: [Result<i32, ErrorCode>; 24] = [
@@ -153,7 +174,7 @@ impl GAL {
             fuses: vec![true; fuse_size],
             // One xor bit per OLMC.
             xor: vec![false; num_olmcs],
-            sig: vec![false; 64],
+            sig: vec![false; chip.ues_size() * 8],
             ac1: vec![false; num_olmcs],
             pt: vec![false; 64],
             syn: false,
@@ -191,6 +212,59 @@ impl GAL {
         }
     }
 
+    // The number of fuses a JEDEC file lists for this GAL.
+    pub fn jedec_size(&self) -> usize {
+        self.jedec_bits().len()
+    }
+
+    // Every fuse, in the order a JEDEC file lists them.
+    fn jedec_bits(&self) -> Vec<bool> {
+        let mut bits = self.fuses.clone();
+        // The GAL22V10's S1 bits are interleaved with the XOR bits.
+        if self.chip == Chip::GAL22V10 {
+            bits.extend(itertools::interleave(self.xor.iter(), self.ac1.iter()));
+        } else {
+            bits.extend(self.xor.iter());
+        }
+        bits.extend(self.sig.iter());
+        if self.chip == Chip::GAL16V8 || self.chip == Chip::GAL20V8 {
+            bits.extend(self.ac1.iter());
+            bits.extend(self.pt.iter());
+            bits.push(self.syn);
+            bits.push(self.ac0);
+        }
+        bits
+    }
+
+    // A one-line digest of every fuse, for eyeballing whether two
+    // builds match. The fuses are taken in JEDEC order and packed
+    // eight to a byte, least significant bit first, as for the JEDEC
+    // fuse checksum. A short final byte is padded with zeros. Each
+    // byte is written as two lowercase hex digits.
+    pub fn fuse_hex(&self) -> String {
+        self.jedec_bits()
+            .chunks(8)
+            .map(|byte| {
+                let value = byte
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |acc, (i, bit)| acc | ((*bit as u8) << i));
+                format!("{:02x}", value)
+            })
+            .collect()
+    }
+
+    // Check the fuses add up to the chip's fuse count, as a guard
+    // against layout mistakes, particularly when adding chips.
+    pub fn check_size(&self) -> Result<(), ErrorCode> {
+        let found = self.jedec_size();
+        let expected = self.chip.total_size();
+        if found != expected {
+            return Err(ErrorCode::FuseCountMismatch { expected, found });
+        }
+        Ok(())
+    }
+
     // Horrible special-case test for registered outputs on the GAL22V10:
     //
     // For all other chips and modes, the output and feedback lines
@@ -274,8 +348,111 @@ impl GAL {
         }
     }
 
+    // Read back the products programmed for an output pin, the
+    // inverse of 'add_term'. Rows that can never be true, with both
+    // an input and its negation connected (as left by 'clear_rows'),
+    // are skipped. Pins without an OLMC have no products. The mode
+    // and AC1 bits must already be set, as the layout depends on them.
+    pub fn product_terms(&self, pin_num: usize) -> Vec<Vec<Pin>> {
+        let olmc_num = match self.chip.pin_to_olmc(pin_num) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        let bounds = self.main_bounds(olmc_num);
+        let columns = self.input_columns();
+        (bounds.start_row + bounds.row_offset..bounds.start_row + bounds.max_row)
+            .filter_map(|row| self.decode_row(&columns, row))
+            .collect()
+    }
+
+    // Read back every row of the fuse array, labelled with its OLMC.
+    // As with 'product_terms', the mode and AC1 bits must be set.
+    pub fn decoded_matrix(&self) -> Vec<RowDecode> {
+        let columns = self.input_columns();
+        (0..self.chip.num_rows())
+            .map(|row| RowDecode {
+                row,
+                olmc: (0..self.chip.num_olmcs()).find(|&i| {
+                    let bounds = self.chip.get_bounds(i);
+                    bounds.start_row <= row && row < bounds.start_row + bounds.max_row
+                }),
+                inputs: self.decode_row(&columns, row),
+            })
+            .collect()
+    }
+
+    // The pins that can be inputs to the array, with their columns.
+    fn input_columns(&self) -> Vec<(usize, usize)> {
+        (1..=self.chip.num_pins())
+            .filter_map(|pin| self.pin_to_column(pin).ok().map(|col| (pin, col)))
+            .collect()
+    }
+
+    // Whether a row contributes to its term. Only the GALxxV8s can
+    // disable rows, with their product term bits.
+    pub fn row_enabled(&self, row: usize) -> bool {
+        match self.chip {
+            Chip::GAL16V8 | Chip::GAL20V8 => self.pt[row],
+            Chip::GAL22V10 | Chip::GAL20RA10 => true,
+        }
+    }
+
+    // Whether a row is left at the JEDEC file's default. The file
+    // declares '*F0', so any fuse not listed is 0 (connected), and
+    // 'make_jedec' leaves out rows that are all 0 unless asked for
+    // full rows. A row with every input connected can never be true,
+    // so these are exactly the unused rows.
+    pub fn is_default_row(&self, row: usize) -> bool {
+        let num_cols = self.chip.num_cols();
+        self.fuses[row * num_cols..(row + 1) * num_cols]
+            .iter()
+            .all(|fuse| !*fuse)
+    }
+
+    // The product on a row, or None if it can never be true.
+    fn decode_row(&self, columns: &[(usize, usize)], row: usize) -> Option<Vec<Pin>> {
+        if !self.row_enabled(row) {
+            return None;
+        }
+        let num_cols = self.chip.num_cols();
+        let fuses = &self.fuses[row * num_cols..(row + 1) * num_cols];
+        let mut product = Vec::new();
+        for &(pin, col) in columns.iter() {
+            match (fuses[col], fuses[col + 1]) {
+                (true, true) => (),
+                (false, false) => return None,
+                (pos_fuse, _) => product.push(Pin {
+                    pin,
+                    neg: pos_fuse ^ self.needs_flip(pin),
+                }),
+            }
+        }
+        Some(product)
+    }
+
+    // The rows holding an OLMC's main term, after any tristate enable
+    // or other control terms.
+    fn main_bounds(&self, olmc_num: usize) -> Bounds {
+        let row_offset = match self.chip {
+            Chip::GAL16V8 | Chip::GAL20V8 => {
+                let registered = !self.ac1[self.chip.num_olmcs() - 1 - olmc_num];
+                match self.get_mode() {
+                    Mode::Simple => 0,
+                    Mode::Registered if registered => 0,
+                    _ => 1,
+                }
+            }
+            Chip::GAL22V10 => 1,
+            Chip::GAL20RA10 => 4,
+        };
+        Bounds {
+            row_offset,
+            ..self.chip.get_bounds(olmc_num)
+        }
+    }
+
     // Map the input pin number to the fuse column number.
-    fn pin_to_column(&self, pin_num: usize) -> Result<usize, ErrorCode> {
+    pub(crate) fn pin_to_column(&self, pin_num: usize) -> Result<usize, ErrorCode> {
         let column_lookup: &[Result<i32, ErrorCode>] = match self.chip {
             Chip::GAL16V8 => match self.get_mode() {
                 Mode::Simple => &PIN_TO_COL_16_SIMPLE,
@@ -291,7 +468,12 @@ impl GAL {
             Chip::GAL20RA10 => &PIN_TO_COL_20RA10,
         };
 
-        let column = column_lookup[pin_num - 1].clone()?;
+        // Terms built by hand may refer to pins that don't exist.
+        let column = pin_num
+            .checked_sub(1)
+            .and_then(|idx| column_lookup.get(idx))
+            .ok_or(ErrorCode::PinOutOfRange { pin: pin_num })?
+            .clone()?;
 
         Ok(column as usize)
     }
@@ -307,6 +489,44 @@ impl GAL {
     }
 }
 
+impl Term {
+    // Put the term in a canonical form with the same meaning, so that
+    // equivalent terms compare equal: each product's pins are sorted
+    // and deduplicated, and products that repeat another or are
+    // absorbed by a more general one (a subset of their pins) are
+    // dropped, leaving the rest sorted.
+    pub fn normalise(&self) -> Term {
+        let mut products = self
+            .pins
+            .iter()
+            .map(|ands| {
+                let mut ands = ands.clone();
+                ands.sort_unstable();
+                ands.dedup();
+                ands
+            })
+            .collect::<Vec<_>>();
+        products.sort();
+        products.dedup();
+
+        let absorbed = |product: &Vec<Pin>| {
+            products.iter().any(|other| {
+                other.len() < product.len() && other.iter().all(|pin| product.contains(pin))
+            })
+        };
+        let pins = products
+            .iter()
+            .filter(|product| !absorbed(product))
+            .cloned()
+            .collect();
+
+        Term {
+            line_num: self.line_num,
+            pins,
+        }
+    }
+}
+
 // Basic terms
 pub fn true_term(line_num: LineNum) -> Term {
     // Empty row is always true (being the AND of nothing).
@@ -323,3 +543,241 @@ pub fn false_term(line_num: LineNum) -> Term {
         pins: Vec::new(),
     }
 }
+
+// Recover the signature from the fuses, the inverse of the MSB-first
+// encoding described on 'GAL::sig'. Trailing zero bytes are treated as
+// unused, and dropped.
+pub fn decode_sig(gal: &GAL) -> String {
+    let mut bytes = gal
+        .sig
+        .chunks(8)
+        .map(|bits| bits.iter().fold(0u8, |byte, bit| byte << 1 | *bit as u8))
+        .collect::<Vec<u8>>();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_term_out_of_range() {
+        let mut gal = GAL::new(Chip::GAL22V10);
+        let bounds = gal.chip.get_bounds(0);
+
+        for pin in [0, 25] {
+            let term = Term {
+                line_num: 7,
+                pins: vec![vec![Pin { pin, neg: false }]],
+            };
+            let err = gal.add_term(&term, &bounds).unwrap_err();
+            assert_eq!(err.line, 7);
+            assert!(matches!(err.code, ErrorCode::PinOutOfRange { pin: p } if p == pin));
+        }
+    }
+
+    fn term(products: &[&[(usize, bool)]]) -> Term {
+        Term {
+            line_num: 1,
+            pins: products
+                .iter()
+                .map(|ands| ands.iter().map(|&(pin, neg)| Pin { pin, neg }).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn normalise_duplicate_pins() {
+        let t = term(&[&[(3, false), (2, true), (3, false)]]);
+        assert_eq!(t.normalise(), term(&[&[(2, true), (3, false)]]));
+        // The same pin with both polarities is a different input.
+        let t = term(&[&[(2, false), (2, true)]]);
+        assert_eq!(t.normalise(), t);
+    }
+
+    #[test]
+    fn normalise_absorption() {
+        let t = term(&[&[(2, false), (3, false)], &[(4, true)], &[(3, false)]]);
+        assert_eq!(t.normalise(), term(&[&[(3, false)], &[(4, true)]]));
+        // A true product absorbs everything.
+        let t = term(&[&[(2, false)], &[]]);
+        assert_eq!(t.normalise(), true_term(1));
+        assert_eq!(false_term(1).normalise(), false_term(1));
+    }
+
+    #[test]
+    fn normalise_duplicate_products() {
+        let t = term(&[
+            &[(3, false), (2, false)],
+            &[(5, true)],
+            &[(2, false), (3, false)],
+        ]);
+        assert_eq!(
+            t.normalise(),
+            term(&[&[(2, false), (3, false)], &[(5, true)]])
+        );
+    }
+
+    #[test]
+    fn fuse_hex() {
+        let build = |eqns: &str| {
+            let source = format!(
+                "GAL16V8\nHex\n\
+                 I0 I1 I2 NC NC NC NC NC NC GND\n\
+                 NC NC O0 O1 NC NC NC NC NC VCC\n{}",
+                eqns
+            );
+            crate::compile(&source).unwrap().gal.fuse_hex()
+        };
+        let hex = build("O0 = I0 * I1\nO1 = I2\n");
+        assert_eq!(hex.len(), (Chip::GAL16V8.total_size() + 7) / 8 * 2);
+        assert!(hex
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)));
+        assert_eq!(hex, build("O0 = I0 * I1\nO1 = I2\n"));
+        assert_ne!(hex, build("O0 = I0 * I1\nO1 = /I2\n"));
+    }
+
+    #[test]
+    fn check_size() {
+        for chip in crate::chips::CHIPS.iter() {
+            let gal = GAL::new(*chip);
+            assert!(gal.check_size().is_ok(), "{}", chip.name());
+        }
+
+        let mut gal = GAL::new(Chip::GAL22V10);
+        gal.fuses.pop();
+        assert!(matches!(
+            gal.check_size(),
+            Err(ErrorCode::FuseCountMismatch {
+                expected: 5892,
+                found: 5891
+            })
+        ));
+    }
+
+    #[test]
+    fn simple_mode_inputs() {
+        // OLMCs 3 and 4 have no input path in simple mode.
+        for (chip, pins) in [(Chip::GAL16V8, [15, 16]), (Chip::GAL20V8, [18, 19])] {
+            let mut gal = GAL::new(chip);
+            gal.set_mode(Mode::Simple);
+            let bounds = gal.chip.get_bounds(0);
+            for pin in pins {
+                let term = Term {
+                    line_num: 3,
+                    pins: vec![vec![Pin { pin, neg: false }]],
+                };
+                let err = gal.add_term(&term, &bounds).unwrap_err();
+                assert!(matches!(err.code, ErrorCode::NotASimpleModeInput { pin: p } if p == pin));
+            }
+        }
+
+        // So using them as inputs selects complex mode instead...
+        let source = "GAL16V8\nInputs\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 NC O0 O1 O2 I10 I11 O3 O4 VCC\n\
+                      O0 = I10 * I11\n";
+        let gal = crate::compile(source).unwrap().gal;
+        assert_eq!(gal.get_mode(), Mode::Complex);
+
+        // ... where pins 12 and 19 can't be inputs.
+        let source = source
+            .replace("I9 NC", "I9 I12")
+            .replace("I10 * I11", "I10 * I12");
+        let err = crate::compile(&source).map(|_| ()).unwrap_err();
+        assert_eq!(err.line, 5);
+        assert!(matches!(
+            err.code,
+            ErrorCode::NotAnComplexModeInput { pin: 12 }
+        ));
+    }
+
+    fn pins(product: &[(usize, bool)]) -> Vec<Pin> {
+        product.iter().map(|&(pin, neg)| Pin { pin, neg }).collect()
+    }
+
+    #[test]
+    fn decoded_matrix() {
+        let source = "GAL16V8\nMatrix\n\
+                      I0 I1 NC NC NC NC NC NC NC GND\n\
+                      NC NC NC NC NC NC NC NC O0 VCC\n\
+                      O0 = I0 * /I1\n";
+        let gal = crate::compile(source).unwrap().gal;
+        let matrix = gal.decoded_matrix();
+        assert_eq!(matrix.len(), gal.chip.num_rows());
+
+        // Pin 19's OLMC has the first rows.
+        assert_eq!(
+            matrix[0],
+            RowDecode {
+                row: 0,
+                olmc: gal.chip.pin_to_olmc(19),
+                inputs: Some(pins(&[(1, false), (2, true)])),
+            }
+        );
+        assert!(matrix[1..].iter().all(|row| row.inputs.is_none()));
+        assert_eq!(matrix[8].olmc, gal.chip.pin_to_olmc(18));
+        assert_eq!(matrix[63].olmc, gal.chip.pin_to_olmc(12));
+    }
+
+    #[test]
+    fn default_rows() {
+        let source = "GAL16V8\nSparse\n\
+                      I0 I1 NC NC NC NC NC NC NC GND\n\
+                      NC NC NC NC NC NC NC O1 O0 VCC\n\
+                      O0 = I0 * /I1 + I1\n\
+                      O1 = I0\n";
+        let gal = crate::compile(source).unwrap().gal;
+        let used = (0..gal.chip.num_rows())
+            .filter(|row| !gal.is_default_row(*row))
+            .collect::<Vec<_>>();
+        // Pin 19's OLMC has rows 0 to 7, and pin 18's rows 8 to 15.
+        assert_eq!(used, [0, 1, 8]);
+
+        // A true product, with nothing connected, isn't a default.
+        let source = source.replace("O1 = I0", "O1 = VCC");
+        let gal = crate::compile(&source).unwrap().gal;
+        assert!(!gal.is_default_row(8));
+        assert!(gal.is_default_row(9));
+    }
+
+    #[test]
+    fn product_terms_round_trip() {
+        // Registered mode, so the combinatorial output on pin 13 has
+        // a tristate enable row to skip, and the register doesn't.
+        let source = "GAL16V8\nProducts\n\
+                      Clock I0 I1 I2 NC NC NC NC NC GND\n\
+                      /OE R0 O0 NC NC NC NC NC NC VCC\n\
+                      R0.R = I0 * /I1\n\
+                      O0 = I0 * I1 + /I2 * R0\n";
+        let gal = crate::compile(source).unwrap().gal;
+        assert_eq!(gal.product_terms(12), vec![pins(&[(2, false), (3, true)])]);
+        assert_eq!(
+            gal.product_terms(13),
+            vec![
+                pins(&[(2, false), (3, false)]),
+                pins(&[(4, true), (12, false)])
+            ]
+        );
+        assert_eq!(gal.product_terms(14), Vec::<Vec<Pin>>::new());
+        assert_eq!(gal.product_terms(2), Vec::<Vec<Pin>>::new());
+
+        // Active-high register feedback on the GAL22V10 is stored
+        // flipped, which must be undone.
+        let source = "GAL22V10\nProducts\n\
+                      Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+                      NC NC NC NC NC NC NC NC NC O0 R0 VCC\n\
+                      R0.R = I0 + R0 * /I1\n\
+                      O0 = R0\n";
+        let gal = crate::compile(source).unwrap().gal;
+        assert_eq!(
+            gal.product_terms(23),
+            vec![pins(&[(2, false)]), pins(&[(3, true), (23, false)])]
+        );
+        assert_eq!(gal.product_terms(22), vec![pins(&[(23, false)])]);
+    }
+}
@@ -10,35 +10,145 @@ use crate::{
     chips::Chip,
     errors::{at_line, Error, ErrorCode, OutputSuffix},
     gal::{self, Bounds, Mode, GAL},
+    parser::Content,
 };
 
-pub fn build(blueprint: &Blueprint) -> Result<GAL, Error> {
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // On the GALxxV8s, error out rather than silently moving to
+    // complex mode because of feedback, as that costs a product term
+    // per output for the tristate control.
+    pub fixed_mode: bool,
+    // Leave unused rows unprogrammed, disabling them with their
+    // product term bits, rather than clearing their fuses. Only the
+    // GALxxV8s have those bits.
+    pub keep_unused_rows: bool,
+}
+
+pub fn build(blueprint: &Blueprint, config: &Config) -> Result<GAL, Error> {
     let mut gal = GAL::new(blueprint.chip);
 
+    if config.keep_unused_rows && !matches!(gal.chip, Chip::GAL16V8 | Chip::GAL20V8) {
+        // The option is at fault, not any line of the source.
+        let chip = gal.chip.name().to_string();
+        return at_line(0, Err(ErrorCode::NoProductTermBits { chip }));
+    }
+
     match gal.chip {
-        Chip::GAL16V8 | Chip::GAL20V8 => build_galxv8(&mut gal, blueprint)?,
+        Chip::GAL16V8 | Chip::GAL20V8 => build_galxv8(&mut gal, blueprint, config)?,
         Chip::GAL22V10 => build_gal22v10(&mut gal, blueprint)?,
         Chip::GAL20RA10 => build_gal20ra10(&mut gal, blueprint)?,
     }
 
+    // A mismatch is an internal error, so blame the chip line.
+    at_line(1, gal.check_size())?;
+
     Ok(gal)
 }
 
+////////////////////////////////////////////////////////////////////////
+// Checking a design fits a chip, without building it.
+//
+
+impl Content {
+    // A cheap pre-flight check that the design fits the given chip:
+    // the pin count, each output's product-term budget, and that
+    // inputs and controls are available in the chip's mode. Unlike a
+    // full build, every problem found is reported, not just the first.
+    pub fn check_fits(&self, chip: Chip) -> Result<(), Vec<ErrorCode>> {
+        if self.pins.len() != chip.num_pins() {
+            return Err(vec![ErrorCode::BadPinCount {
+                found: self.pins.len() / 2,
+                expected: chip.num_pins() / 2,
+            }]);
+        }
+
+        let mut errors = Vec::new();
+        let mut blueprint = Blueprint::new(chip);
+        blueprint.pins = self.pins.clone();
+        for eqn in self.eqns.iter() {
+            if let Err(e) = blueprint.add_equation(eqn) {
+                errors.push(e);
+            }
+        }
+
+        // An empty GAL, just to find the mode-dependent layout.
+        let mut gal = GAL::new(chip);
+        if chip == Chip::GAL16V8 || chip == Chip::GAL20V8 {
+            gal.set_mode(analyse_mode(&blueprint.olmcs));
+        }
+        if chip != Chip::GAL20RA10 {
+            if let Err(e) = check_not_gal20ra10(&blueprint) {
+                errors.push(e.code);
+            }
+        }
+
+        for (olmc, i) in blueprint.olmcs.iter().zip(0..) {
+            if let Some((_, term)) = &olmc.output {
+                let bounds = adjust_main_bounds(&gal, &olmc.output, &chip.get_bounds(i));
+                let rows = bounds.max_row - bounds.row_offset;
+                if term.pins.len() > rows {
+                    errors.push(if rows == 1 {
+                        ErrorCode::MoreThanOneProduct
+                    } else {
+                        ErrorCode::TooManyProducts {
+                            max: rows,
+                            seen: term.pins.len(),
+                        }
+                    });
+                }
+            }
+
+            if olmc.tri_con.is_some() {
+                if let Err(e) = check_tristate(chip, olmc) {
+                    errors.push(e);
+                }
+            }
+
+            let terms = [
+                olmc.output.as_ref().map(|(_, term)| term),
+                olmc.tri_con.as_ref(),
+                olmc.clock.as_ref(),
+                olmc.arst.as_ref(),
+                olmc.aprst.as_ref(),
+            ];
+            for term in terms.iter().flatten() {
+                // One error per term is plenty.
+                let bad_input = term
+                    .pins
+                    .iter()
+                    .flatten()
+                    .find_map(|pin| gal.pin_to_column(pin.pin).err());
+                errors.extend(bad_input);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 // Chip-specific GAL-building algorithms.
 //
 
-fn build_galxv8(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
+fn build_galxv8(gal: &mut GAL, blueprint: &Blueprint, config: &Config) -> Result<(), Error> {
     check_not_gal20ra10(blueprint)?;
     set_sig(gal, blueprint);
-    set_mode(gal, blueprint);
+    set_mode(gal, blueprint, config)?;
     // Are we implementing combinatorial expressions as tristate?
     // Pure combinatorial is only available in simple mode.
     let com_is_tri = gal.get_mode() != Mode::Simple;
     set_tristate(gal, blueprint, com_is_tri);
-    set_xors(gal, blueprint);
+    set_xors(gal, blueprint)?;
     set_core_eqns(gal, blueprint)?;
     set_pts(gal);
+    if config.keep_unused_rows {
+        keep_unused_rows(gal);
+    }
     Ok(())
 }
 
@@ -51,7 +161,7 @@ fn build_gal22v10(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
     // For the 22V10, we always implement combintorial expressions as tristate.
     set_tristate(gal, blueprint, true);
     // Must come before core_eqns, for "needs_flip".
-    set_xors(gal, blueprint);
+    set_xors(gal, blueprint)?;
     set_core_eqns(gal, blueprint)?;
     set_arsp_eqns(gal, blueprint)?;
     Ok(())
@@ -59,7 +169,7 @@ fn build_gal22v10(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
 
 fn build_gal20ra10(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
     set_sig(gal, blueprint);
-    set_xors(gal, blueprint);
+    set_xors(gal, blueprint)?;
     set_core_eqns(gal, blueprint)?;
     set_aux_eqns(gal, blueprint)?;
     Ok(())
@@ -71,8 +181,8 @@ fn build_gal20ra10(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
 
 // Write out the signature.
 fn set_sig(gal: &mut GAL, blueprint: &Blueprint) {
-    // Signature has space for 8 bytes.
-    for i in 0..usize::min(blueprint.sig.len(), 8) {
+    // Signature has space for 'ues_size' (8) bytes.
+    for i in 0..usize::min(blueprint.sig.len(), gal.chip.ues_size()) {
         let c = blueprint.sig[i];
         for j in 0..8 {
             gal.sig[i * 8 + j] = (c << j) & 0x80 != 0;
@@ -195,13 +305,22 @@ fn set_aux_eqns(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
 }
 
 // Set the XOR bits for inverting outputs, if necessary.
-fn set_xors(gal: &mut GAL, blueprint: &Blueprint) {
+fn set_xors(gal: &mut GAL, blueprint: &Blueprint) -> Result<(), Error> {
     let num_olmcs = blueprint.olmcs.len();
     for (olmc, i) in blueprint.olmcs.iter().zip(0..) {
-        if olmc.output.is_some() && olmc.active == Active::High {
+        if let (Some(term), None) = (&olmc.polarity, &olmc.output) {
+            return at_line(
+                term.line_num,
+                Err(ErrorCode::UndefinedOutput {
+                    suffix: OutputSuffix::POL,
+                }),
+            );
+        }
+        if olmc.output.is_some() && olmc.get_active() == Active::High {
             gal.xor[num_olmcs - 1 - i] = true;
         }
     }
+    Ok(())
 }
 
 // We don't do anything with the PT bits in the GALxxV8s.
@@ -211,6 +330,18 @@ fn set_pts(gal: &mut GAL) {
     }
 }
 
+// Swap cleared rows, which can never be true, for unprogrammed ones
+// with their product term disabled.
+fn keep_unused_rows(gal: &mut GAL) {
+    let num_cols = gal.chip.num_cols();
+    for (row, fuses) in gal.fuses.chunks_mut(num_cols).enumerate() {
+        if fuses.iter().all(|fuse| !*fuse) {
+            fuses.iter_mut().for_each(|fuse| *fuse = true);
+            gal.pt[row] = false;
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 // Other helper functions.
 //
@@ -250,12 +381,26 @@ fn adjust_main_bounds(gal: &GAL, output: &Option<(PinMode, gal::Term)>, bounds:
 fn check_not_gal20ra10(blueprint: &Blueprint) -> Result<(), Error> {
     for olmc in blueprint.olmcs.iter() {
         if let Some(term) = &olmc.clock {
-            return at_line(
-                term.line_num,
-                Err(ErrorCode::DisallowedControl {
-                    suffix: OutputSuffix::CLK,
-                }),
-            );
+            // Registers are always clocked by pin 1, but a registered
+            // output may say so, as documentation.
+            let is_pin1 = term.pins == [vec![gal::Pin { pin: 1, neg: false }]];
+            if is_pin1 && olmc.output.is_none() {
+                return at_line(
+                    term.line_num,
+                    Err(ErrorCode::UndefinedOutput {
+                        suffix: OutputSuffix::CLK,
+                    }),
+                );
+            }
+            let is_registered = matches!(olmc.output, Some((PinMode::Registered, _)));
+            if !(is_pin1 && is_registered) {
+                return at_line(
+                    term.line_num,
+                    Err(ErrorCode::FixedClock {
+                        chip: blueprint.chip.name().to_string(),
+                    }),
+                );
+            }
         }
         if let Some(term) = &olmc.arst {
             return at_line(
@@ -309,8 +454,36 @@ fn check_aux(field: &Option<gal::Term>, olmc: &OLMC, suffix: OutputSuffix) -> Re
 ////////////////////////////////////////////////////////////////////////
 // GALxV8 analysis - determine which mode to run the chip in.
 
-fn set_mode(gal: &mut GAL, blueprint: &Blueprint) {
-    gal.set_mode(analyse_mode(&blueprint.olmcs));
+fn set_mode(gal: &mut GAL, blueprint: &Blueprint, config: &Config) -> Result<(), Error> {
+    let olmcs = &blueprint.olmcs;
+    let mode = analyse_mode(olmcs);
+
+    // Tristate outputs ask for complex mode, but feedback just
+    // happens to need it.
+    let tristate = olmcs
+        .iter()
+        .any(|olmc| matches!(olmc.output, Some((PinMode::Tristate, _))));
+    if let (true, Mode::Complex, false, Some((n, reason))) = (
+        config.fixed_mode,
+        mode,
+        tristate,
+        simple_mode_conflict(olmcs),
+    ) {
+        let chip = blueprint.chip;
+        let pin = (1..=chip.num_pins())
+            .find(|pin| chip.pin_to_olmc(*pin) == Some(n))
+            .unwrap();
+        // Report it where the pin is read.
+        let line = olmcs
+            .iter()
+            .flat_map(|olmc| &olmc.output)
+            .find(|(_, term)| term.pins.iter().flatten().any(|input| input.pin == pin))
+            .map_or(0, |(_, term)| term.line_num);
+        return at_line(line, Err(ErrorCode::ComplexModeNeeded { pin, reason }));
+    }
+
+    gal.set_mode(mode);
+    Ok(())
 }
 
 fn analyse_mode(olmcs: &[OLMC]) -> Mode {
@@ -337,21 +510,32 @@ fn analyse_mode(olmcs: &[OLMC]) -> Mode {
     }
 
     // If we can't use simple mode, use complex mode.
+    if simple_mode_conflict(olmcs).is_some() {
+        return Mode::Complex;
+    }
+
+    // If there is still no mode defined, use simple mode.
+    Mode::Simple
+}
+
+// Find the first OLMC whose feedback rules out simple mode, and why.
+fn simple_mode_conflict(olmcs: &[OLMC]) -> Option<(usize, &'static str)> {
     for (n, olmc) in olmcs.iter().enumerate().filter(|(_, olmc)| olmc.feedback) {
         match olmc.output {
             // Some OLMCs cannot be configured as pure inputs in simple mode.
             None => {
                 if n == 3 || n == 4 {
-                    return Mode::Complex;
+                    return Some((
+                        n,
+                        "is used as an input, which simple mode doesn't allow for this pin",
+                    ));
                 }
             }
             // OLMC pins cannot be used as combinatorial feedback in simple mode.
-            Some(_) => return Mode::Complex,
+            Some(_) => return Some((n, "is an output used as an input")),
         }
     }
-
-    // If there is still no mode defined, use simple mode.
-    Mode::Simple
+    None
 }
 
 #[cfg(test)]
@@ -374,7 +558,9 @@ mod tests {
             clock: None,
             arst: None,
             aprst: None,
+            polarity: None,
             feedback: false,
+            init: None,
         }
     }
 
@@ -386,7 +572,9 @@ mod tests {
             clock: None,
             arst: None,
             aprst: None,
+            polarity: None,
             feedback: true,
+            init: None,
         }
     }
 
@@ -397,6 +585,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sig_bit_order() {
+        let mut blueprint = Blueprint::new(Chip::GAL16V8);
+        blueprint.sig = b"A\x80".to_vec();
+        let mut gal = GAL::new(Chip::GAL16V8);
+        set_sig(&mut gal, &blueprint);
+
+        #[rustfmt::skip]
+        let expected = [
+            false, true,  false, false, false, false, false, true,
+            true,  false, false, false, false, false, false, false,
+        ];
+        assert_eq!(&gal.sig[..16], &expected);
+        assert!(gal.sig[16..].iter().all(|bit| !bit));
+    }
+
+    #[test]
+    fn sig_round_trip() {
+        let mut blueprint = Blueprint::new(Chip::GAL22V10);
+        blueprint.sig = b"Sig12345".to_vec();
+        let mut gal = GAL::new(Chip::GAL22V10);
+        set_sig(&mut gal, &blueprint);
+
+        assert_eq!(gal::decode_sig(&gal), "Sig12345");
+    }
+
+    #[test]
+    fn polarity_override() {
+        let source = "GAL16V8\nPolarity\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\nO0.POL = GND\n\
+                      /O1 = I1\nO1.POL = VCC\n\
+                      O2 = I2\n/O3 = I3\n";
+        let gal = crate::compile(source).unwrap().gal;
+        // XOR bits are stored in reverse OLMC order.
+        let xor = |olmc: usize| gal.xor[gal.xor.len() - 1 - olmc];
+        assert!(!xor(0));
+        assert!(xor(1));
+        assert!(xor(2));
+        assert!(!xor(3));
+
+        // The override changes nothing but the XOR bit.
+        let plain = crate::compile(&source.replace(".POL = GND", ".POL = VCC"))
+            .unwrap()
+            .gal;
+        assert_eq!(gal.fuses, plain.fuses);
+        assert_ne!(gal.xor, plain.xor);
+    }
+
     #[test]
     fn mode1() {
         let olmcs = [
@@ -472,6 +710,214 @@ mod tests {
         assert_eq!(analyse_mode(&olmcs), Mode::Complex);
     }
 
+    #[test]
+    fn keep_unused_rows() {
+        let source = "GAL16V8\nFill\n\
+                      I0 I1 NC NC NC NC NC NC NC GND\n\
+                      NC NC O0 O1 NC NC NC NC NC VCC\n\
+                      O0 = I0 * I1\nO1 = I0 + I1\n";
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        let blueprint = Blueprint::from(&content).unwrap();
+        let cleared = build(&blueprint, &Config::default()).unwrap();
+        let config = Config {
+            keep_unused_rows: true,
+            ..Config::default()
+        };
+        let kept = build(&blueprint, &config).unwrap();
+
+        // Only the three used rows are left enabled, and the rest
+        // are unprogrammed rather than cleared.
+        let num_cols = cleared.chip.num_cols();
+        assert!(cleared.pt.iter().all(|bit| *bit));
+        assert_eq!(kept.pt.iter().filter(|bit| **bit).count(), 3);
+        for (row, (a, b)) in cleared
+            .fuses
+            .chunks(num_cols)
+            .zip(kept.fuses.chunks(num_cols))
+            .enumerate()
+        {
+            if kept.pt[row] {
+                assert_eq!(a, b);
+            } else {
+                assert!(a.iter().all(|fuse| !*fuse));
+                assert!(b.iter().all(|fuse| *fuse));
+            }
+        }
+
+        // The logic is the same, but the files show the difference.
+        assert!(crate::sim::verify_equivalence(&cleared, &kept, 1, 64));
+        let writer_config = crate::writer::Config::default();
+        assert_ne!(
+            crate::writer::make_jedec(&writer_config, &cleared),
+            crate::writer::make_jedec(&writer_config, &kept)
+        );
+        let fuse = |gal: &GAL| crate::writer::make_fuse(('-', 'x'), false, &blueprint.pins, gal);
+        assert_ne!(fuse(&cleared), fuse(&kept));
+
+        // Other chips must clear their unused rows.
+        let source = "GAL22V10\nFill\n\
+                      I0 I1 NC NC NC NC NC NC NC NC NC GND\n\
+                      NC NC NC NC NC NC NC NC NC NC O0 VCC\n\
+                      O0 = I0 * I1\n";
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        let blueprint = Blueprint::from(&content).unwrap();
+        let e = build(&blueprint, &config).err().unwrap();
+        assert!(matches!(e.code, ErrorCode::NoProductTermBits { .. }));
+        assert_eq!(e.line, 0);
+    }
+
+    #[test]
+    fn fixed_mode() {
+        let source = "GAL16V8\nFixed\n\
+                      I0 I1 NC NC NC NC NC NC NC GND\n\
+                      NC NC O0 O1 NC NC NC NC NC VCC\n\
+                      O0 = I0\nO1 = O0 * I1\n";
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        let blueprint = Blueprint::from(&content).unwrap();
+
+        let gal = build(&blueprint, &Config::default()).unwrap();
+        assert_eq!(gal.get_mode(), Mode::Complex);
+
+        let config = Config {
+            fixed_mode: true,
+            ..Config::default()
+        };
+        match build(&blueprint, &config) {
+            Err(Error {
+                code: ErrorCode::ComplexModeNeeded { pin: 13, .. },
+                line: 6,
+            }) => (),
+            _ => panic!("expected ComplexModeNeeded"),
+        }
+
+        // Asking for complex mode with a tristate output is fine.
+        let tristate = source.replace("O0 = I0", "O0.T = I0\nO0.E = I1");
+        let content = crate::parser::parse_str(&tristate, &Default::default()).unwrap();
+        let blueprint = Blueprint::from(&content).unwrap();
+        let gal = build(&blueprint, &config).unwrap();
+        assert_eq!(gal.get_mode(), Mode::Complex);
+    }
+
+    #[test]
+    fn check_fits() {
+        let source = "GAL16V8\nFits\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0 + I1 + I2 + I3 + I4 + I5 + I6 + I7\n\
+                      O1 = I0 * I1\n";
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        assert!(content.check_fits(Chip::GAL16V8).is_ok());
+        // The pin rows are for a 20-pin chip.
+        let errors = content.check_fits(Chip::GAL22V10).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [ErrorCode::BadPinCount {
+                found: 10,
+                expected: 12
+            }]
+        ));
+
+        // Making O1 tristate moves to complex mode, where O0 loses a
+        // row to its enable. Both problems get reported.
+        let overflow = source.replace("O1 = I0 * I1", "O1.T = I0 * I1\nO2.E = I1");
+        let content = crate::parser::parse_str(&overflow, &Default::default()).unwrap();
+        let errors = content.check_fits(Chip::GAL16V8).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [
+                ErrorCode::TooManyProducts { max: 7, seen: 8 },
+                ErrorCode::UndefinedOutput { .. }
+            ]
+        ));
+        // The full build agrees about the first.
+        let built = crate::compile(&overflow).map(|_| ()).unwrap_err();
+        assert!(matches!(
+            built.code,
+            ErrorCode::TooManyProducts { seen: 8, .. }
+        ));
+    }
+
+    #[test]
+    fn control_without_output() {
+        let headers = [
+            (
+                "GAL16V8\nControl\n\
+                 Clock I0 I1 NC NC NC NC NC NC GND\n\
+                 /OE NC O0 NC NC NC NC NC NC VCC\n",
+                &["E", "POL", "CLK"][..],
+            ),
+            (
+                "GAL20V8\nControl\n\
+                 Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+                 /OE NC O0 NC NC NC NC NC NC NC NC VCC\n",
+                &["E", "POL", "CLK"][..],
+            ),
+            (
+                "GAL22V10\nControl\n\
+                 Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+                 NC NC O0 NC NC NC NC NC NC NC NC VCC\n",
+                &["E", "POL", "CLK"][..],
+            ),
+            (
+                "GAL20RA10\nControl\n\
+                 /PL Clock I0 I1 NC NC NC NC NC NC NC GND\n\
+                 /OE NC O0 NC NC NC NC NC NC NC NC VCC\n",
+                &["E", "POL", "CLK", "ARST", "APRST"][..],
+            ),
+        ];
+        for (header, suffixes) in headers.iter() {
+            for suffix in suffixes.iter() {
+                // The only clock the fixed-clock chips allow is pin 1.
+                let rhs = match *suffix {
+                    "POL" => "VCC",
+                    "CLK" => "Clock",
+                    _ => "I0",
+                };
+                let source = format!("{}O0.{} = {}\n", header, suffix, rhs);
+                let e = crate::compile(&source).err().unwrap();
+                assert_eq!(
+                    e.code.to_string(),
+                    format!("the output must be defined to use .{}", suffix),
+                    "{}",
+                    source
+                );
+                assert_eq!(e.line, 5);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_clock_acknowledged() {
+        let source = |clk: &str| {
+            format!(
+                "GAL22V10\nClock\n\
+                 Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                 I10 O0 O1 O2 O3 O4 O5 O6 O7 O8 O9 VCC\n\
+                 O0.R = I0 * I1\n{}",
+                clk
+            )
+        };
+        let plain = crate::compile(&source("")).unwrap();
+        let acked = crate::compile(&source("O0.CLK = Clock\n")).unwrap();
+        assert_eq!(plain.gal.fuses, acked.gal.fuses);
+
+        for clk in [
+            "O0.CLK = I0\n",
+            "O0.CLK = /Clock\n",
+            "O1 = I0\nO1.CLK = Clock\n",
+        ]
+        .iter()
+        {
+            match crate::compile(&source(clk)) {
+                Err(Error {
+                    code: ErrorCode::FixedClock { chip },
+                    ..
+                }) => assert_eq!(chip, "GAL22V10"),
+                _ => panic!("expected a fixed clock error for {:?}", clk),
+            }
+        }
+    }
+
     #[test]
     fn mode3_all_registered() {
         let olmcs = [
@@ -0,0 +1,184 @@
+//
+// interop.rs: C API
+//
+// A small 'extern "C"' surface over 'assemble', so that galette can
+// be linked into C programs. The matching declarations are in
+// include/galette.h.
+//
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic, ptr,
+};
+
+use crate::{writer, Config};
+
+// Return codes for 'galette_assemble'.
+pub const GALETTE_OK: c_int = 0;
+pub const GALETTE_ERROR: c_int = 1;
+pub const GALETTE_BAD_ARGUMENT: c_int = 2;
+pub const GALETTE_INTERNAL_ERROR: c_int = 3;
+
+// C mirror of the output options in 'writer::Config'.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GaletteConfig {
+    pub gen_fuse: bool,
+    pub gen_chip: bool,
+    pub gen_pin: bool,
+    pub jedec_sec_bit: bool,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages can quote the source, which may contain NULs, so escape
+    // them rather than truncating the C string.
+    let message = CString::new(message.replace('\0', "\\0")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Assemble the .pld file at 'path', writing the outputs alongside it.
+///
+/// Returns GALETTE_OK on success. On failure, the message is available
+/// from 'galette_last_error'.
+///
+/// # Safety
+///
+/// 'path' must be a valid NUL-terminated string. 'config' must be
+/// NULL (for the defaults) or point to a valid GaletteConfig.
+#[no_mangle]
+pub unsafe extern "C" fn galette_assemble(
+    path: *const c_char,
+    config: *const GaletteConfig,
+) -> c_int {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+
+    if path.is_null() {
+        set_last_error(String::from("path is NULL"));
+        return GALETTE_BAD_ARGUMENT;
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path.to_string(),
+        Err(_) => {
+            set_last_error(String::from("path is not valid UTF-8"));
+            return GALETTE_BAD_ARGUMENT;
+        }
+    };
+
+    let mut full_config = Config::default();
+    if let Some(config) = config.as_ref() {
+        full_config.writer = writer::Config {
+            gen_fuse: config.gen_fuse,
+            gen_chip: config.gen_chip,
+            gen_pin: config.gen_pin,
+            jedec_sec_bit: config.jedec_sec_bit,
+            ..writer::Config::default()
+        };
+    }
+
+    // Unwinding into C is undefined, so catch any panics (such as
+    // failing to write the outputs) here.
+    match panic::catch_unwind(|| crate::assemble(&path, &full_config)) {
+        Ok(Ok(_)) => GALETTE_OK,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            GALETTE_ERROR
+        }
+        Err(_) => {
+            set_last_error(format!("{}: internal error", path));
+            GALETTE_INTERNAL_ERROR
+        }
+    }
+}
+
+/// The message for the last failed 'galette_assemble' on this
+/// thread, or NULL if it succeeded. The string stays valid until the
+/// next call to 'galette_assemble'.
+#[no_mangle]
+pub extern "C" fn galette_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn last_error() -> Option<String> {
+        let message = galette_last_error();
+        if message.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(message) }
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            )
+        }
+    }
+
+    #[test]
+    fn assemble_via_ffi() {
+        let dir = std::env::temp_dir().join(format!("galette_interop_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pld = dir.join("ffi.pld");
+        fs::write(
+            &pld,
+            "GAL16V8\nFFI\n\
+             I0 I1 NC NC NC NC NC NC NC GND\n\
+             NC O0 NC NC NC NC NC NC NC VCC\n\
+             O0 = I0 * I1\n",
+        )
+        .unwrap();
+        let path = CString::new(pld.to_str().unwrap()).unwrap();
+
+        let config = GaletteConfig {
+            gen_fuse: false,
+            gen_chip: false,
+            gen_pin: false,
+            jedec_sec_bit: false,
+        };
+        let res = unsafe { galette_assemble(path.as_ptr(), &config) };
+        assert_eq!(res, GALETTE_OK);
+        assert_eq!(last_error(), None);
+        assert!(dir.join("ffi.jed").exists());
+        assert!(!dir.join("ffi.pin").exists());
+
+        fs::write(&pld, "GAL16V9\n").unwrap();
+        let res = unsafe { galette_assemble(path.as_ptr(), ptr::null()) };
+        assert_eq!(res, GALETTE_ERROR);
+        let message = last_error().unwrap();
+        assert!(message.ends_with("Error in line 1: unexpected GAL type found: 'GAL16V9'"));
+
+        fs::write(
+            &pld,
+            "GAL16V8\nFFI\n\
+             I0 I1 NC NC NC NC NC NC NC GND\n\
+             NC O0 NC NC NC NC NC NC NC VCC\n\
+             O0 = I0 \0 I1\n",
+        )
+        .unwrap();
+        let res = unsafe { galette_assemble(path.as_ptr(), ptr::null()) };
+        assert_eq!(res, GALETTE_ERROR);
+        let message = last_error().unwrap();
+        assert!(message.ends_with("Error in line 5: unexpected character in input: '\\0'"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn null_path() {
+        let res = unsafe { galette_assemble(ptr::null(), ptr::null()) };
+        assert_eq!(res, GALETTE_BAD_ARGUMENT);
+        assert_eq!(last_error().unwrap(), "path is NULL");
+    }
+}
@@ -13,21 +13,460 @@
 // you want to start with.
 //
 
+use std::{
+    fmt,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+pub mod batch;
 pub mod blueprint;
 pub mod chips;
+pub mod compare;
 pub mod errors;
+pub mod formatter;
 pub mod gal;
 pub mod gal_builder;
+pub mod interop;
+pub mod lint;
 pub mod parser;
+pub mod reader;
+pub mod recommend;
+pub mod repl;
+pub mod sim;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 pub mod writer;
 
-pub fn assemble(file_name: &str, config: &writer::Config) -> Result<(), errors::FileError> {
+// 'Config' holds the options for the whole pipeline, with the
+// output-specific options in 'writer::Config'.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Raw user electronic signature data, overriding the signature
+    // given in the source.
+    pub ues: Option<Vec<u8>>,
+    // Signature to use if the source's is blank.
+    pub default_sig: Option<Vec<u8>>,
+    // Byte to right-pad a short signature with.
+    pub pad_sig: Option<u8>,
+    pub parser: parser::Config,
+    pub lint: lint::Config,
+    pub builder: gal_builder::Config,
+    pub writer: writer::Config,
+}
+
+// The time spent in each stage of assembly, summed over every file
+// assembled with it.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub files: usize,
+    pub parse: Duration,
+    pub blueprint: Duration,
+    pub build: Duration,
+    pub write: Duration,
+}
+
+// Run 'f', adding the time it takes to 'total'.
+fn timed<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let res = f();
+    *total += start.elapsed();
+    res
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Profile of {} file(s):", self.files)?;
+        let stages = [
+            ("parse", self.parse),
+            ("blueprint", self.blueprint),
+            ("build", self.build),
+            ("write", self.write),
+        ];
+        for (name, time) in stages.iter() {
+            writeln!(f, "  {:<10} {:>10.3}ms", name, time.as_secs_f64() * 1000.0)?;
+        }
+        let total = stages.iter().map(|(_, time)| *time).sum::<Duration>();
+        writeln!(
+            f,
+            "  {:<10} {:>10.3}ms",
+            "total",
+            total.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+// 'Compiled' holds the output of every stage of the pipeline, for
+// library users who want to inspect more than the final fuses.
+pub struct Compiled {
+    pub content: parser::Content,
+    pub blueprint: blueprint::Blueprint,
+    pub gal: gal::GAL,
+    pub warnings: Vec<errors::Warning>,
+}
+
+/// Run the whole pipeline over an in-memory source, returning the
+/// results of each stage.
+///
+/// ```
+/// let source = "GAL16V8\nSig\n\
+///               I0 I1 NC NC NC NC NC NC NC GND\n\
+///               NC O0 NC NC NC NC NC NC NC VCC\n\
+///               O0 = I0 * I1\n";
+/// let compiled = galette::compile(source).unwrap();
+///
+/// assert_eq!(compiled.content.eqns.len(), 1);
+/// assert!(compiled.blueprint.olmcs[0].output.is_some());
+/// assert_eq!(compiled.gal.chip, galette::chips::Chip::GAL16V8);
+/// ```
+pub fn compile(source: &str) -> Result<Compiled, errors::Error> {
+    let config = Config::default();
+    build_stages(parser::parse_str(source, &config.parser)?, &config)
+}
+
+/// Like 'compile', but starting from parsed content, and passing its
+/// equations through 'transform' before the blueprint is built. This
+/// lets a library user rewrite the equations, e.g. to expand their
+/// own macros, while the rest of the pipeline runs as usual.
+///
+/// ```
+/// use galette::{gal::Pin, parser::{self, Equation, Suffix, LHS}};
+///
+/// let source = "GAL16V8\nSig\n\
+///               I0 I1 NC NC NC NC NC NC NC GND\n\
+///               NC O0 O1 NC NC NC NC NC NC VCC\n\
+///               O0 = I0 * I1\n";
+/// let config = galette::Config::default();
+/// let content = parser::parse_str(source, &config.parser).unwrap();
+///
+/// // Add 'O1 = /I0'.
+/// let compiled = galette::compile_with(content, &config, |mut eqns| {
+///     eqns.push(Equation {
+///         line_num: 0,
+///         lhs: LHS::Pin((Pin { pin: 13, neg: false }, Suffix::None)),
+///         rhs: vec![Pin { pin: 1, neg: true }],
+///         is_or: vec![false],
+///     });
+///     eqns
+/// })
+/// .unwrap();
+///
+/// assert_eq!(compiled.content.eqns.len(), 2);
+/// assert_eq!(
+///     galette::writer::make_equations(&compiled.blueprint),
+///     "O0 = I0 & I1\nO1 = !I0\n"
+/// );
+/// ```
+pub fn compile_with(
+    mut content: parser::Content,
+    config: &Config,
+    transform: impl FnOnce(Vec<parser::Equation>) -> Vec<parser::Equation>,
+) -> Result<Compiled, errors::Error> {
+    content.eqns = transform(std::mem::take(&mut content.eqns));
+    build_stages(content, config)
+}
+
+// Assemble an in-memory source straight to a JEDEC file, for callers
+// without a filesystem.
+pub fn assemble_to_jedec(source: &str, config: &Config) -> Result<String, errors::Error> {
+    let compiled = build_stages(parser::parse_str(source, &config.parser)?, config)?;
+    Ok(writer::make_jedec(
+        &writer_config(config, &compiled.content),
+        &compiled.gal,
+    ))
+}
+
+// The output options, with any that the source's 'OPTIONS' lines set.
+fn writer_config(config: &Config, content: &parser::Content) -> writer::Config {
+    let mut writer = config.writer.clone();
+    content.options.apply(&mut writer);
+    writer
+}
+
+fn build_stages(content: parser::Content, config: &Config) -> Result<Compiled, errors::Error> {
+    build_stages_timed(content, config, &mut Profile::default())
+}
+
+fn build_stages_timed(
+    content: parser::Content,
+    config: &Config,
+    profile: &mut Profile,
+) -> Result<Compiled, errors::Error> {
+    let (blueprint, warnings) = timed(&mut profile.blueprint, || {
+        let mut blueprint = blueprint::Blueprint::from(&content)?;
+        if let Some(ues) = &config.ues {
+            // The UES comes from the options, not the source, so
+            // there's no line to blame.
+            errors::at_line(0, blueprint.set_ues(ues))?;
+        }
+        if let Some(sig) = &config.default_sig {
+            blueprint.default_sig(sig);
+        }
+        if let Some(pad) = config.pad_sig {
+            blueprint.pad_sig(pad);
+        }
+        let mut warnings = content.warnings.clone();
+        warnings.extend(lint::check(&config.lint, &content, &blueprint));
+        Ok((blueprint, warnings))
+    })?;
+    let gal = timed(&mut profile.build, || {
+        build_gal(&content, &blueprint, config)
+    })?;
+
+    Ok(Compiled {
+        content,
+        blueprint,
+        gal,
+        warnings,
+    })
+}
+
+fn build_gal(
+    content: &parser::Content,
+    blueprint: &blueprint::Blueprint,
+    config: &Config,
+) -> Result<gal::GAL, errors::Error> {
+    let gal = gal_builder::build(blueprint, &config.builder)?;
+    sim::check_asserts(content, blueprint, &gal)?;
+    Ok(gal)
+}
+
+/// Build a fresh GAL from an earlier compilation's blueprint, without
+/// parsing the source again. The config should be the one it was
+/// compiled with.
+///
+/// ```
+/// let source = "GAL16V8\nSig\n\
+///               I0 I1 NC NC NC NC NC NC NC GND\n\
+///               NC O0 NC NC NC NC NC NC NC VCC\n\
+///               O0 = I0 * I1\n";
+/// let compiled = galette::compile(source).unwrap();
+/// let gal = galette::rebuild(&compiled, &Default::default()).unwrap();
+///
+/// assert_eq!(gal.fuses, compiled.gal.fuses);
+/// assert_eq!(gal.xor, compiled.gal.xor);
+/// assert_eq!(gal.fuse_hex(), compiled.gal.fuse_hex());
+/// ```
+pub fn rebuild(compiled: &Compiled, config: &Config) -> Result<gal::GAL, errors::Error> {
+    build_gal(&compiled.content, &compiled.blueprint, config)
+}
+
+// The last file assembled by 'assemble_cached', so that if its
+// source is unchanged it needn't be parsed again.
+#[derive(Default)]
+pub struct Cache {
+    file_name: String,
+    source: String,
+    compiled: Option<Compiled>,
+}
+
+// Like 'assemble_to_sink', but reusing the parsed source and
+// blueprint from the cache if the file hasn't changed since the
+// last call. The config must be the same each time.
+pub fn assemble_cached(
+    file_name: &str,
+    config: &Config,
+    sink: &mut dyn writer::Sink,
+    cache: &mut Cache,
+) -> Result<Vec<errors::Warning>, errors::FileError> {
+    (|| {
+        let source = std::fs::read_to_string(file_name).map_err(errors::read_failed)?;
+        let unchanged = cache.file_name == file_name && cache.source == source;
+        let compiled = match cache.compiled.take() {
+            Some(mut compiled) if unchanged => {
+                compiled.gal = rebuild(&compiled, config)?;
+                compiled
+            }
+            _ => build_stages(
+                parser::parse_named(file_name, &source, &config.parser)?,
+                config,
+            )?,
+        };
+        let blueprint = &compiled.blueprint;
+        writer::write_files(
+            sink,
+            file_name,
+            &writer_config(config, &compiled.content),
+            blueprint,
+            &compiled.gal,
+        )
+        .unwrap();
+
+        let warnings = compiled.warnings.clone();
+        *cache = Cache {
+            file_name: file_name.to_string(),
+            source,
+            compiled: Some(compiled),
+        };
+        Ok(warnings)
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
+
+// Assemble the given file, returning any warnings.
+pub fn assemble(
+    file_name: &str,
+    config: &Config,
+) -> Result<Vec<errors::Warning>, errors::FileError> {
+    assemble_to_sink(file_name, config, &mut writer::FileSink)
+}
+
+// Like 'assemble', but sending the outputs to the given sink, e.g. a
+// 'writer::MemorySink' to see what would be written.
+pub fn assemble_to_sink(
+    file_name: &str,
+    config: &Config,
+    sink: &mut dyn writer::Sink,
+) -> Result<Vec<errors::Warning>, errors::FileError> {
+    assemble_profiled(file_name, config, sink, &mut Profile::default())
+}
+
+// Like 'assemble_to_sink', but adding the time spent in each stage
+// to the profile.
+pub fn assemble_profiled(
+    file_name: &str,
+    config: &Config,
+    sink: &mut dyn writer::Sink,
+    profile: &mut Profile,
+) -> Result<Vec<errors::Warning>, errors::FileError> {
+    profile.files += 1;
+    (|| {
+        let content = timed(&mut profile.parse, || {
+            parser::parse(file_name, &config.parser)
+        })?;
+        let compiled = build_stages_timed(content, config, profile)?;
+        let blueprint = &compiled.blueprint;
+        timed(&mut profile.write, || {
+            writer::write_files(
+                sink,
+                file_name,
+                &writer_config(config, &compiled.content),
+                blueprint,
+                &compiled.gal,
+            )
+        })
+        .unwrap();
+
+        Ok(compiled.warnings)
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
+
+// Summarise what the given file assembles to, as 'writer::make_stats'.
+pub fn stats(file_name: &str, config: &Config) -> Result<String, errors::FileError> {
+    (|| {
+        let compiled = build_stages(parser::parse(file_name, &config.parser)?, config)?;
+        Ok(writer::make_stats(&compiled.gal, &compiled.blueprint.olmcs))
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
+
+// Run every lint over the given file without writing any output,
+// and report the warnings as 'lint::make_report'.
+pub fn lint(file_name: &str, config: &Config) -> Result<String, errors::FileError> {
+    let mut config = config.clone();
+    config.lint = lint::Config::all();
+    config.parser.check_pin_numbers = true;
+    (|| {
+        let compiled = build_stages(parser::parse(file_name, &config.parser)?, &config)?;
+        Ok(lint::make_report(&compiled.warnings))
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
+
+// Build an in-memory source with every lint, returning editor
+// diagnostics as 'writer::make_diagnostics'.
+pub fn diagnostics(source: &str, config: &Config) -> String {
+    let mut config = config.clone();
+    config.lint = lint::Config::all();
+    config.parser.check_pin_numbers = true;
+    let result = parser::parse_str(source, &config.parser)
+        .and_then(|content| build_stages(content, &config))
+        .map(|compiled| compiled.warnings);
+    writer::make_diagnostics(source, &result)
+}
+
+// Cross-reference the given file's equations, as 'writer::make_xref'.
+pub fn xref(file_name: &str, config: &Config) -> Result<String, errors::FileError> {
+    parser::parse(file_name, &config.parser)
+        .map(|content| writer::make_xref(&content))
+        .map_err(|err| errors::FileError {
+            file: file_name.into(),
+            err,
+        })
+}
+
+// The one-line fuse digest of the given file, as 'GAL::fuse_hex'.
+pub fn fuse_hex(file_name: &str, config: &Config) -> Result<String, errors::FileError> {
+    (|| {
+        let compiled = build_stages(parser::parse(file_name, &config.parser)?, config)?;
+        Ok(compiled.gal.fuse_hex())
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
+
+// Report what the given file needs from its chip, and the smallest
+// chip that provides it, as 'recommend::make_report'. The design only
+// needs to parse, not to fit its current chip.
+pub fn recommend_chip(file_name: &str, config: &Config) -> Result<String, errors::FileError> {
     (|| {
-        let content = parser::parse(file_name)?;
+        let content = parser::parse(file_name, &config.parser)?;
         let blueprint = blueprint::Blueprint::from(&content)?;
-        let gal = gal_builder::build(&blueprint)?;
-        writer::write_files(file_name, config, &blueprint.pins, &blueprint.olmcs, &gal).unwrap();
+        Ok(recommend::make_report(&blueprint))
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
 
+// Regenerate the .pin and .chp files from an assembled JEDEC file,
+// sending them to the sink. Without pin names, the pins are numbered.
+pub fn describe(
+    file_name: &str,
+    pin_names: Option<Vec<String>>,
+    sink: &mut dyn writer::Sink,
+) -> Result<(), errors::FileError> {
+    (|| {
+        let data = std::fs::read_to_string(file_name).map_err(errors::read_failed)?;
+        let gal = reader::read_jedec(&data)?;
+        let chip = gal.chip;
+        let pin_names = match pin_names {
+            Some(names) if names.len() != chip.num_pins() => {
+                return Err(errors::Error {
+                    code: errors::ErrorCode::BadPinNameCount {
+                        chip: chip.name().to_string(),
+                        expected: chip.num_pins(),
+                        found: names.len(),
+                    },
+                    line: 0,
+                })
+            }
+            Some(names) => names,
+            None => reader::numbered_pin_names(chip),
+        };
+        let olmcs = reader::decode_olmcs(&gal);
+        let pin = writer::make_pin(&gal, &pin_names, &olmcs);
+        sink.write(file_name, "pin", pin.as_bytes())
+            .map_err(errors::write_failed)?;
+        let chp = writer::make_chip(chip, &pin_names);
+        sink.write(file_name, "chp", chp.as_bytes())
+            .map_err(errors::write_failed)?;
         Ok(())
     })()
     .map_err(|err| errors::FileError {
@@ -35,3 +474,17 @@ pub fn assemble(file_name: &str, config: &writer::Config) -> Result<(), errors::
         err,
     })
 }
+
+// Rewrite a source file in the canonical layout.
+pub fn format(file_name: &str) -> Result<(), errors::FileError> {
+    (|| {
+        let data = std::fs::read_to_string(file_name).map_err(errors::read_failed)?;
+        let formatted = formatter::format_str(&data)?;
+        writer::write_atomically(file_name, |file| file.write_all(formatted.as_bytes()))
+            .map_err(errors::write_failed)
+    })()
+    .map_err(|err| errors::FileError {
+        file: file_name.into(),
+        err,
+    })
+}
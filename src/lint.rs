@@ -0,0 +1,317 @@
+//
+// lint.rs: Design warnings
+//
+// These analyses don't stop a design from being assembled, but flag
+// things that are legal yet likely to surprise the designer. They're
+// opt-in, and controlled by 'Config'.
+//
+
+use std::fmt::Write;
+
+use crate::{
+    blueprint::{Active, Blueprint, PinMode, OLMC},
+    errors::{LineNum, Warning, WarningCode},
+    gal::{Pin, Term},
+    parser::Content,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Explain when an output is used as an input.
+    pub feedback: bool,
+    // Flag outputs with logically identical equations.
+    pub duplicates: bool,
+    // Flag products made redundant by a more general product in the
+    // same sum.
+    pub redundant: bool,
+    // Flag a blank signature, which is usually a forgotten placeholder.
+    pub signature: bool,
+    // Flag named output pins that are neither driven nor read.
+    pub unused_olmcs: bool,
+}
+
+impl Config {
+    // Every analysis, as used by 'galette lint'.
+    pub fn all() -> Self {
+        Config {
+            feedback: true,
+            duplicates: true,
+            redundant: true,
+            signature: true,
+            unused_olmcs: true,
+        }
+    }
+}
+
+pub fn check(config: &Config, content: &Content, blueprint: &Blueprint) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    if config.feedback {
+        warnings.extend(check_feedback(content, blueprint));
+    }
+
+    if config.duplicates {
+        warnings.extend(check_duplicates(blueprint));
+    }
+
+    if config.redundant {
+        warnings.extend(check_redundant(blueprint));
+    }
+
+    if config.signature {
+        warnings.extend(check_signature(blueprint));
+    }
+
+    if config.unused_olmcs {
+        warnings.extend(check_unused_olmcs(content, blueprint));
+    }
+
+    warnings
+}
+
+// The headings 'make_report' groups warnings under, in order.
+const CATEGORIES: [&str; 6] = [
+    "Source",
+    "Unused pins",
+    "Feedback",
+    "Duplicate outputs",
+    "Redundant terms",
+    "Signature",
+];
+
+fn category(code: &WarningCode) -> &'static str {
+    match code {
+        WarningCode::AssumedChip { .. }
+        | WarningCode::PinNumberMismatch { .. }
+        | WarningCode::LongLine { .. } => "Source",
+        WarningCode::UnusedOLMC { .. } => "Unused pins",
+        WarningCode::OutputFeedback { .. } => "Feedback",
+        WarningCode::DuplicateEquation { .. } => "Duplicate outputs",
+        WarningCode::RedundantProduct { .. } => "Redundant terms",
+        WarningCode::EmptySignature => "Signature",
+    }
+}
+
+// List the warnings by category, skipping empty categories, and then
+// give a count.
+pub fn make_report(warnings: &[Warning]) -> String {
+    let mut buf = String::new();
+    for heading in CATEGORIES.iter() {
+        let mut found = warnings
+            .iter()
+            .filter(|warning| category(&warning.code) == *heading)
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            continue;
+        }
+        found.sort_by_key(|warning| warning.line);
+        let _ = writeln!(buf, "{}:", heading);
+        for warning in found {
+            let _ = writeln!(buf, "  line {}: {}", warning.line, warning.code);
+        }
+    }
+    let _ = writeln!(buf, "{} warning(s)", warnings.len());
+    buf
+}
+
+// Using an output pin on the RHS doesn't read an external signal, but
+// the output itself fed back into the array.
+fn check_feedback(content: &Content, blueprint: &Blueprint) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    for eqn in content.eqns.iter() {
+        let mut seen = Vec::new();
+        for input in eqn.rhs.iter() {
+            if seen.contains(&input.pin) {
+                continue;
+            }
+            seen.push(input.pin);
+
+            let olmc = match blueprint.chip.pin_to_olmc(input.pin) {
+                Some(i) => &blueprint.olmcs[i],
+                None => continue,
+            };
+            let reads = match olmc.output {
+                Some((PinMode::Registered, _)) => {
+                    "the register's state via feedback, not the external pin"
+                }
+                Some((PinMode::Combinatorial, _)) => {
+                    "its own value via feedback, not an external signal"
+                }
+                Some((PinMode::Tristate, _)) => {
+                    "its own value via feedback while enabled, and an external signal only while disabled"
+                }
+                None => continue,
+            };
+            warnings.push(Warning {
+                code: WarningCode::OutputFeedback {
+                    name: blueprint.pins[input.pin - 1].clone(),
+                    reads,
+                },
+                line: eqn.line_num,
+            });
+        }
+    }
+
+    warnings
+}
+
+// Two outputs computing the same thing may be a copy-paste error, or
+// could be driven from a single OLMC.
+fn check_duplicates(blueprint: &Blueprint) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut seen: Vec<(usize, LineNum, Normalised)> = Vec::new();
+
+    for pin in 1..=blueprint.chip.num_pins() {
+        let olmc = match blueprint.chip.pin_to_olmc(pin) {
+            Some(i) => &blueprint.olmcs[i],
+            None => continue,
+        };
+        let (line, normalised) = match normalise_olmc(olmc) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if let Some((other, other_line, _)) = seen.iter().find(|(_, _, n)| *n == normalised) {
+            warnings.push(Warning {
+                code: WarningCode::DuplicateEquation {
+                    name: blueprint.pins[pin - 1].clone(),
+                    other: blueprint.pins[other - 1].clone(),
+                    other_line: *other_line,
+                },
+                line,
+            });
+        } else {
+            seen.push((pin, line, normalised));
+        }
+    }
+
+    warnings
+}
+
+// The signature line is the second line of the source. This checks
+// the blueprint rather than the parsed content, so that a blank line
+// that's intentionally overridden by a UES isn't reported.
+fn check_signature(blueprint: &Blueprint) -> Option<Warning> {
+    if blueprint.sig.iter().all(u8::is_ascii_whitespace) {
+        Some(Warning {
+            code: WarningCode::EmptySignature,
+            line: 2,
+        })
+    } else {
+        None
+    }
+}
+
+// An output pin given a name but no equation, and not read by any
+// equation, may be a forgotten connection. Pins named NC are
+// deliberately unused.
+fn check_unused_olmcs(content: &Content, blueprint: &Blueprint) -> Vec<Warning> {
+    let chip = blueprint.chip;
+    let mut warnings = Vec::new();
+
+    for pin in 1..=chip.num_pins() {
+        let olmc = match chip.pin_to_olmc(pin) {
+            Some(i) => &blueprint.olmcs[i],
+            None => continue,
+        };
+        let name = &blueprint.pins[pin - 1];
+        if olmc.output.is_some() || olmc.feedback || name == "NC" {
+            continue;
+        }
+        warnings.push(Warning {
+            code: WarningCode::UnusedOLMC {
+                name: name.clone(),
+                pin,
+            },
+            line: content.pin_lines[(pin > chip.num_pins() / 2) as usize],
+        });
+    }
+
+    warnings
+}
+
+// If a sum contains both A*B and A*B*C, the latter is absorbed by the
+// former, and just wastes a row.
+fn check_redundant(blueprint: &Blueprint) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    for pin in 1..=blueprint.chip.num_pins() {
+        let olmc = match blueprint.chip.pin_to_olmc(pin) {
+            Some(i) => &blueprint.olmcs[i],
+            None => continue,
+        };
+        let term = match &olmc.output {
+            Some((_, term)) => term,
+            None => continue,
+        };
+
+        // Normalising drops the absorbed products, and each of them
+        // is covered by one of the products that are left.
+        let kept = term.normalise().pins;
+        let mut reported = Vec::new();
+        for product in term.pins.iter() {
+            let covered = Term {
+                line_num: term.line_num,
+                pins: vec![product.clone()],
+            }
+            .normalise()
+            .pins
+            .remove(0);
+            if kept.contains(&covered) || reported.contains(&covered) {
+                continue;
+            }
+            let covering = kept
+                .iter()
+                .find(|covering| covering.iter().all(|x| covered.contains(x)))
+                .unwrap();
+            warnings.push(Warning {
+                code: WarningCode::RedundantProduct {
+                    name: blueprint.pins[pin - 1].clone(),
+                    product: product_to_string(blueprint, &covered),
+                    covering: product_to_string(blueprint, covering),
+                },
+                line: term.line_num,
+            });
+            reported.push(covered);
+        }
+    }
+
+    warnings
+}
+
+fn product_to_string(blueprint: &Blueprint, product: &[Pin]) -> String {
+    product
+        .iter()
+        .map(|pin| {
+            format!(
+                "{}{}",
+                if pin.neg { "/" } else { "" },
+                blueprint.pins[pin.pin - 1]
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" * ")
+}
+
+// A term's products after 'Term::normalise', so that reordering
+// doesn't hide a match.
+type NormalisedTerm = Vec<Vec<Pin>>;
+
+// Everything that determines an output's behaviour.
+type Normalised = (PinMode, Active, NormalisedTerm, [Option<NormalisedTerm>; 4]);
+
+fn normalise_olmc(olmc: &OLMC) -> Option<(LineNum, Normalised)> {
+    let (mode, term) = olmc.output.as_ref()?;
+    let controls = [&olmc.tri_con, &olmc.clock, &olmc.arst, &olmc.aprst]
+        .map(|control| control.as_ref().map(|term| term.normalise().pins));
+    Some((
+        term.line_num,
+        (
+            mode.clone(),
+            olmc.get_active(),
+            term.normalise().pins,
+            controls,
+        ),
+    ))
+}
@@ -9,23 +9,111 @@
 extern crate clap;
 extern crate galette;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
-use std::process;
+use std::{
+    fmt,
+    io::{IsTerminal, Read},
+    process, thread,
+    time::Duration,
+};
 
-use galette::writer;
+use galette::{
+    batch, blueprint, chips::Chip, compare, errors::ErrorCode, gal_builder, lint, parser, repl,
+    writer,
+};
 
 fn main() {
     let matches = App::new("Galette")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Simon Frankau <sgf@arbitrary.name>")
         .about("GALasm-compatible GAL assembler")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .global(true)
+                .help("Colour errors and warnings; 'auto' colours a terminal unless NO_COLOR is set"),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Interactively try out equations")
+                .arg(
+                    Arg::with_name("chip")
+                        .long("chip")
+                        .takes_value(true)
+                        .default_value("GAL16V8")
+                        .validator(|s| Chip::from_name(&s).map(|_| ()).map_err(|e| e.to_string()))
+                        .help("The chip to assemble for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Run every analysis over a file, writing a report rather than output files")
+                .arg(
+                    Arg::with_name("INPUT.pld")
+                        .help("Input file")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lsp")
+                .about("Read a source on stdin, and write its errors and warnings as LSP diagnostics in JSON"),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Assemble a file with both galette and GALasm, and report where the outputs differ")
+                .arg(
+                    Arg::with_name("galasm")
+                        .long("galasm")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true)
+                        .help("The GALasm executable to compare against"),
+                )
+                .arg(
+                    Arg::with_name("INPUT.pld")
+                        .help("Input file")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("describe")
+                .about("Regenerate the .pin and .chp files from an assembled .jed file")
+                .arg(
+                    Arg::with_name("pins")
+                        .long("pins")
+                        .takes_value(true)
+                        .value_name("NAMES")
+                        .help("Comma-separated pin names, in pin order; the pins are numbered otherwise"),
+                )
+                .arg(
+                    Arg::with_name("INPUT.jed")
+                        .help("Input file")
+                        .required(true)
+                        .index(1),
+                ),
+        )
         .arg(
             Arg::with_name("INPUT.pld")
                 .help("Input file")
-                .required(true)
+                .required_unless_one(&["list-chips", "batch"])
                 .index(1),
         )
+        .arg(
+            Arg::with_name("batch")
+                .long("batch")
+                .takes_value(true)
+                .value_name("MANIFEST")
+                .conflicts_with_all(&["INPUT.pld", "auto-signature", "format", "dry-run"])
+                .help("Assemble each file listed in a build manifest"),
+        )
         .arg(
             Arg::with_name("secure")
                 .short("s")
@@ -47,6 +135,12 @@ fn main() {
                 .takes_value(false)
                 .help("Disable .fus file output"),
         )
+        .arg(
+            Arg::with_name("ignore-options")
+                .long("ignore-options")
+                .takes_value(false)
+                .help("Ignore the source's OPTIONS lines, so only the command line sets the outputs"),
+        )
         .arg(
             Arg::with_name("nopin")
                 .short("p")
@@ -54,19 +148,609 @@ fn main() {
                 .takes_value(false)
                 .help("Disable .pin file output"),
         )
+        .arg(
+            Arg::with_name("fuse-glyphs")
+                .long("fuse-glyphs")
+                .takes_value(true)
+                .value_name("ON_OFF")
+                .validator(|s| parse_glyphs(&s).map(|_| ()))
+                .help("Characters for set and clear fuses in the .fus file [default: -x]"),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .takes_value(true)
+                .value_name("STYLE")
+                .possible_values(&["galette", "galasm", "custom"])
+                .default_value("galette")
+                .help("Header of the .jed file; 'galasm' matches GALasm 2.1's output"),
+        )
+        .arg(
+            Arg::with_name("header-text")
+                .long("header-text")
+                .takes_value(true)
+                .value_name("NAME")
+                .required_if("header", "custom")
+                .help("Assembler named by '--header custom'"),
+        )
+        .arg(
+            Arg::with_name("fuse-offsets")
+                .long("fuse-offsets")
+                .takes_value(false)
+                .help("Also number each OLMC's rows from zero in the .fus file"),
+        )
+        .arg(
+            Arg::with_name("full-rows")
+                .long("full-rows")
+                .takes_value(false)
+                .help("List every fuse row in the .jed file, for easier diffing"),
+        )
+        .arg(
+            Arg::with_name("blif")
+                .long("blif")
+                .takes_value(false)
+                .help("Write a .blif netlist of the equations, for formal tools"),
+        )
+        .arg(
+            Arg::with_name("blif-line-comments")
+                .long("blif-line-comments")
+                .takes_value(false)
+                .requires("blif")
+                .help("Comment each .blif table with its equation's source line"),
+        )
+        .arg(
+            Arg::with_name("logisim")
+                .long("logisim")
+                .takes_value(false)
+                .help("Write a Logisim .circ circuit of the equations"),
+        )
+        .arg(
+            Arg::with_name("cupl")
+                .long("cupl")
+                .takes_value(false)
+                .help("Write a .cupl export of the equations in CUPL syntax"),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .takes_value(false)
+                .help("Write a .manifest.json recording the chip, outputs and checksums"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("List the files that would be written, without writing them"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .takes_value(false)
+                .help("Print a summary of the assembled design"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .takes_value(false)
+                // Options that report on a single build, or read the
+                // source up front, don't make sense while watching.
+                .conflicts_with_all(&[
+                    "batch",
+                    "format",
+                    "dry-run",
+                    "recommend-chip",
+                    "stats",
+                    "xref",
+                    "fuse-hex",
+                    "profile",
+                    "auto-signature",
+                ])
+                .help("Reassemble the input whenever it changes, until interrupted"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(false)
+                .help("Print the time spent in each stage of assembly"),
+        )
+        .arg(
+            Arg::with_name("fuse-hex")
+                .long("fuse-hex")
+                .takes_value(false)
+                .help("Print every fuse as one line of hex, for comparing builds"),
+        )
+        .arg(
+            Arg::with_name("dump-tokens")
+                .long("dump-tokens")
+                .takes_value(false)
+                .hidden(true)
+                .help("Print the equations' tokens, for debugging the parser"),
+        )
+        .arg(
+            Arg::with_name("list-chips")
+                .long("list-chips")
+                .takes_value(false)
+                .help("List the supported chips and their parameters"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(false)
+                .help("Rewrite the input in canonical layout, instead of assembling it"),
+        )
+        .arg(
+            Arg::with_name("xref")
+                .long("xref")
+                .takes_value(false)
+                .help("Print which equations read each pin, and which pins each equation reads"),
+        )
+        .arg(
+            Arg::with_name("recommend-chip")
+                .long("recommend-chip")
+                .takes_value(false)
+                .help("Report the features the design needs and the smallest chip that has them"),
+        )
+        .arg(
+            Arg::with_name("ues")
+                .long("ues")
+                .takes_value(true)
+                .value_name("HEX")
+                .validator(|s| parse_hex(&s).map(|_| ()))
+                .help(
+                    "Set the user electronic signature from hex, replacing the source's signature",
+                ),
+        )
+        .arg(
+            Arg::with_name("auto-signature")
+                .long("auto-signature")
+                .takes_value(false)
+                .conflicts_with("ues")
+                .help("If the signature is blank, use a hash of the source file (32-bit FNV-1a, in hex)"),
+        )
+        .arg(
+            Arg::with_name("pad-signature")
+                .long("pad-signature")
+                .takes_value(true)
+                .value_name("CHAR")
+                .validator(|s| parse_pad(&s).map(|_| ()))
+                .help("Right-pad a short signature to its full width with the given character"),
+        )
+        .arg(
+            Arg::with_name("require-description")
+                .long("require-description")
+                .takes_value(false)
+                .help("Require a DESCRIPTION section, as galasm does"),
+        )
+        .arg(
+            Arg::with_name("require-signature")
+                .long("require-signature")
+                .takes_value(false)
+                .help("Fail if the signature line is blank, rather than warning with --warn-signature"),
+        )
+        .arg(
+            Arg::with_name("parse-past-description")
+                .long("parse-past-description")
+                .takes_value(false)
+                .help("Skip the DESCRIPTION line and parse the rest of the file too"),
+        )
+        .arg(
+            Arg::with_name("define")
+                .short("D")
+                .long("define")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME")
+                .help("Define a name for '#ifdef', selecting a build variant"),
+        )
+        .arg(
+            Arg::with_name("max-line")
+                .long("max-line")
+                .takes_value(true)
+                .value_name("N")
+                .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Warn about lines longer than N characters, ignoring comments"),
+        )
+        .arg(
+            Arg::with_name("assume-chip-from-filename")
+                .long("assume-chip-from-filename")
+                .takes_value(false)
+                .help("If the chip type is missing, take it from a file name like 'name.16v8.pld'"),
+        )
+        .arg(
+            Arg::with_name("no-auto-mode")
+                .long("no-auto-mode")
+                .takes_value(false)
+                .help("On GALxxV8s, fail rather than move to complex mode because of feedback"),
+        )
+        .arg(
+            Arg::with_name("keep-unused-rows")
+                .long("keep-unused-rows")
+                .takes_value(false)
+                .help("On GALxxV8s, leave unused rows unprogrammed and disable them, rather than clearing them"),
+        )
+        .arg(
+            Arg::with_name("warn-pin-numbers")
+                .long("warn-pin-numbers")
+                .takes_value(false)
+                .help("Warn when a comment of column numbers doesn't match the pin row below it"),
+        )
+        .arg(
+            Arg::with_name("warn-feedback")
+                .long("warn-feedback")
+                .takes_value(false)
+                .help("Warn when an output is also used as an input"),
+        )
+        .arg(
+            Arg::with_name("warn-duplicates")
+                .long("warn-duplicates")
+                .takes_value(false)
+                .help("Warn when two outputs have identical equations"),
+        )
+        .arg(
+            Arg::with_name("warn-redundant")
+                .long("warn-redundant")
+                .takes_value(false)
+                .help("Warn about product terms absorbed by a more general term"),
+        )
+        .arg(
+            Arg::with_name("warn-signature")
+                .long("warn-signature")
+                .takes_value(false)
+                .help("Warn when the signature is blank"),
+        )
+        .arg(
+            Arg::with_name("warn-unused-olmcs")
+                .long("warn-unused-olmcs")
+                .takes_value(false)
+                .help("Warn when a named output pin has no equation and isn't used as an input"),
+        )
         .get_matches();
 
-    let file_name = matches.value_of("INPUT.pld").unwrap();
+    let colour = use_colour(matches.value_of("color").unwrap());
+
+    if let Some(matches) = matches.subcommand_matches("repl") {
+        let chip = Chip::from_name(matches.value_of("chip").unwrap()).unwrap();
+        let stdin = std::io::stdin();
+        repl::run(chip, stdin.lock(), std::io::stdout()).unwrap();
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        let file_name = matches.value_of("INPUT.pld").unwrap();
+        match galette::lint(file_name, &galette::Config::default()) {
+            Ok(report) => print!("{}", report),
+            Err(e) => {
+                report(colour, e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("lsp").is_some() {
+        let mut source = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source)
+            .expect("Unable to read stdin");
+        print!(
+            "{}",
+            galette::diagnostics(&source, &galette::Config::default())
+        );
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        let file_name = matches.value_of("INPUT.pld").unwrap();
+        let galasm = std::path::Path::new(matches.value_of("galasm").unwrap());
+        match compare::compare_with_galasm(file_name, galasm, &galette::Config::default()) {
+            Ok(differences) if differences.is_empty() => {
+                println!("{}: outputs match GALasm", file_name);
+            }
+            Ok(differences) => {
+                for difference in differences.iter() {
+                    println!("{}", difference);
+                }
+                process::exit(1);
+            }
+            Err(e) => {
+                report(colour, e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("describe") {
+        let file_name = matches.value_of("INPUT.jed").unwrap();
+        let pin_names = matches
+            .value_of("pins")
+            .map(|s| s.split(',').map(|name| name.trim().to_string()).collect());
+        if let Err(e) = galette::describe(file_name, pin_names, &mut writer::FileSink) {
+            report(colour, e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if matches.is_present("list-chips") {
+        print!("{}", writer::make_chip_list());
+        return;
+    }
+
+    let file_name = matches.value_of("INPUT.pld");
 
-    let config = writer::Config {
-        gen_fuse: !matches.is_present("nofuse"),
-        gen_chip: !matches.is_present("nochip"),
-        gen_pin: !matches.is_present("nopin"),
-        jedec_sec_bit: matches.is_present("secure"),
+    let config = galette::Config {
+        ues: matches.value_of("ues").map(|s| parse_hex(s).unwrap()),
+        default_sig: if matches.is_present("auto-signature") {
+            let data = std::fs::read(file_name.unwrap()).expect("Unable to read file");
+            Some(blueprint::auto_signature(&data))
+        } else {
+            None
+        },
+        pad_sig: matches
+            .value_of("pad-signature")
+            .map(|s| parse_pad(s).unwrap()),
+        parser: parser::Config {
+            require_description: matches.is_present("require-description"),
+            require_signature: matches.is_present("require-signature"),
+            parse_past_description: matches.is_present("parse-past-description"),
+            assume_chip_from_filename: matches.is_present("assume-chip-from-filename"),
+            assume_chip: None,
+            defines: matches
+                .values_of("define")
+                .map_or(Vec::new(), |names| names.map(String::from).collect()),
+            max_line: matches.value_of("max-line").map(|s| s.parse().unwrap()),
+            check_pin_numbers: matches.is_present("warn-pin-numbers"),
+            ignore_options: matches.is_present("ignore-options"),
+            limits: parser::Limits::default(),
+        },
+        lint: lint::Config {
+            feedback: matches.is_present("warn-feedback"),
+            duplicates: matches.is_present("warn-duplicates"),
+            redundant: matches.is_present("warn-redundant"),
+            signature: matches.is_present("warn-signature"),
+            unused_olmcs: matches.is_present("warn-unused-olmcs"),
+        },
+        builder: gal_builder::Config {
+            fixed_mode: matches.is_present("no-auto-mode"),
+            keep_unused_rows: matches.is_present("keep-unused-rows"),
+        },
+        writer: writer::Config {
+            gen_fuse: !matches.is_present("nofuse"),
+            gen_chip: !matches.is_present("nochip"),
+            gen_pin: !matches.is_present("nopin"),
+            jedec_sec_bit: matches.is_present("secure"),
+            gen_manifest: matches.is_present("manifest"),
+            gen_blif: matches.is_present("blif"),
+            blif_line_comments: matches.is_present("blif-line-comments"),
+            gen_logisim: matches.is_present("logisim"),
+            gen_cupl: matches.is_present("cupl"),
+            jedec_full_rows: matches.is_present("full-rows"),
+            fuse_glyphs: matches
+                .value_of("fuse-glyphs")
+                .map_or(writer::Config::default().fuse_glyphs, |s| {
+                    parse_glyphs(s).unwrap()
+                }),
+            fuse_offsets: matches.is_present("fuse-offsets"),
+            jedec_header: match matches.value_of("header").unwrap() {
+                "galasm" => writer::JedecHeader::GALasm,
+                "custom" => writer::JedecHeader::Custom(
+                    matches.value_of("header-text").unwrap().to_string(),
+                ),
+                _ => writer::JedecHeader::Galette,
+            },
+        },
     };
 
-    if let Err(e) = galette::assemble(file_name, &config) {
-        eprintln!("{}", e);
-        process::exit(1);
+    let mut profile = galette::Profile::default();
+
+    if let Some(manifest) = matches.value_of("batch") {
+        let outcomes = batch::run(manifest, &config, &mut profile).unwrap_or_else(|e| {
+            report(colour, e);
+            process::exit(1);
+        });
+        let mut failures = 0;
+        for (input, outcome) in outcomes.iter() {
+            match outcome {
+                Ok(warnings) => {
+                    for warning in warnings.iter() {
+                        report(colour, format!("{}: {}", input, warning));
+                    }
+                }
+                Err(e) => {
+                    report(colour, e);
+                    failures += 1;
+                }
+            }
+        }
+        println!(
+            "{} of {} files assembled",
+            outcomes.len() - failures,
+            outcomes.len()
+        );
+        if matches.is_present("profile") {
+            print!("{}", profile);
+        }
+        if failures > 0 {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let file_name = file_name.unwrap();
+
+    if matches.is_present("dump-tokens") {
+        let data = std::fs::read_to_string(file_name).expect("Unable to read file");
+        match parser::dump_tokens(file_name, &data, &config.parser) {
+            Ok(dump) => print!("{}", dump),
+            Err(e) => {
+                report(colour, format!("{}: {}", file_name, e));
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("watch") {
+        watch(file_name, &config, colour);
+    }
+
+    let result = if matches.is_present("format") {
+        galette::format(file_name).map(|_| Vec::new())
+    } else if matches.is_present("recommend-chip") {
+        galette::recommend_chip(file_name, &config).map(|report| {
+            print!("{}", report);
+            Vec::new()
+        })
+    } else if matches.is_present("dry-run") {
+        let mut sink = writer::MemorySink::default();
+        let result = galette::assemble_to_sink(file_name, &config, &mut sink);
+        if result.is_ok() {
+            for (name, bytes) in sink.files.iter() {
+                println!("{} ({} bytes)", name, bytes.len());
+            }
+        }
+        result
+    } else {
+        galette::assemble_profiled(file_name, &config, &mut writer::FileSink, &mut profile)
+    };
+
+    match result {
+        Ok(warnings) => {
+            for warning in warnings.iter() {
+                report(colour, format!("{}: {}", file_name, warning));
+            }
+            if matches.is_present("stats") {
+                print!("{}", galette::stats(file_name, &config).unwrap());
+            }
+            if matches.is_present("xref") {
+                print!("{}", galette::xref(file_name, &config).unwrap());
+            }
+            if matches.is_present("fuse-hex") {
+                println!("{}", galette::fuse_hex(file_name, &config).unwrap());
+            }
+            if matches.is_present("profile") {
+                print!("{}", profile);
+            }
+        }
+        Err(e) => {
+            report(colour, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Poll the input's modification time, reassembling it whenever it
+// changes. An unchanged source is rebuilt from the cached blueprint.
+// If the file can't be read, it's retried on the next poll, but the
+// error is only reported once.
+fn watch(file_name: &str, config: &galette::Config, colour: bool) -> ! {
+    let mut cache = galette::Cache::default();
+    let mut last_modified = None;
+    let mut read_failed = false;
+    loop {
+        // The file may briefly vanish while an editor saves it.
+        let modified = std::fs::metadata(file_name).and_then(|m| m.modified());
+        if let Ok(modified) = modified {
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                match galette::assemble_cached(file_name, config, &mut writer::FileSink, &mut cache)
+                {
+                    Ok(warnings) => {
+                        for warning in warnings.iter() {
+                            report(colour, format!("{}: {}", file_name, warning));
+                        }
+                        eprintln!("{}: assembled", file_name);
+                        read_failed = false;
+                    }
+                    Err(e) if matches!(e.err.code, ErrorCode::ReadFailed { .. }) => {
+                        last_modified = None;
+                        if !read_failed {
+                            report(colour, e);
+                        }
+                        read_failed = true;
+                    }
+                    Err(e) => {
+                        report(colour, e);
+                        read_failed = false;
+                    }
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+// Whether to colour messages: 'auto' colours them if stderr is a
+// terminal and NO_COLOR isn't set.
+fn use_colour(when: &str) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        _ => {
+            std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+                && std::io::stderr().is_terminal()
+        }
+    }
+}
+
+// Print an error or warning to stderr, optionally highlighting its
+// "Error in line N:" or "Warning in line N:" part. The library's
+// messages stay plain.
+fn report(colour: bool, msg: impl fmt::Display) {
+    let msg = msg.to_string();
+    let highlight = [("Error in line ", "1;31"), ("Warning in line ", "1;33")]
+        .iter()
+        .find_map(|(label, sgr)| msg.find(label).map(|start| (start, sgr)));
+    match highlight {
+        Some((start, sgr)) if colour => {
+            let end = msg[start..].find(':').map_or(msg.len(), |i| start + i + 1);
+            eprintln!(
+                "{}\x1b[{}m{}\x1b[0m{}",
+                &msg[..start],
+                sgr,
+                &msg[start..end],
+                &msg[end..]
+            );
+        }
+        _ => eprintln!("{}", msg),
+    }
+}
+
+// Convert a string of hex digit pairs into bytes.
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex data: '{}'", s))
+        })
+        .collect()
+}
+
+// The signature is stored as bytes, so only a single ASCII character
+// can be used as padding.
+fn parse_pad(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [c] if c.is_ascii() => Ok(*c),
+        _ => Err(format!("expected a single ASCII character, found '{}'", s)),
+    }
+}
+
+// Split a two-character string into the set and clear fuse glyphs.
+fn parse_glyphs(s: &str) -> Result<(char, char), String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(on), Some(off), None) if on != off && !on.is_whitespace() && !off.is_whitespace() => {
+            Ok((on, off))
+        }
+        _ => Err(format!(
+            "expected two different non-space characters, found '{}'",
+            s
+        )),
     }
 }
@@ -7,14 +7,76 @@
 // present, but try to leave other checks for later in the pipeline.
 //
 
-use std::{collections::HashMap, fs, iter::Peekable};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt, fs,
+    iter::Peekable,
+    path::Path,
+};
 
 use crate::{
     chips::Chip,
-    errors::{at_line, Error, ErrorCode, LineNum},
+    errors::{at_line, Error, ErrorCode, LineNum, Warning, WarningCode},
     gal::Pin,
+    writer,
 };
 
+////////////////////////////////////////////////////////////////////////
+// Parsing options
+//
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Error out if there's no DESCRIPTION section, as galasm does.
+    pub require_description: bool,
+    // Error out if the signature line is blank. A short signature is
+    // fine; this only catches one that's missing.
+    pub require_signature: bool,
+    // Skip a DESCRIPTION line rather than stopping there, so that
+    // the rest of the file is parsed too.
+    pub parse_past_description: bool,
+    // If the first line isn't a chip type, guess the chip from a file
+    // name like "decoder.16v8.pld" instead of failing.
+    pub assume_chip_from_filename: bool,
+    // Like 'assume_chip_from_filename', but with the chip given
+    // directly, which takes precedence over the file name. A chip
+    // line naming a different chip is an error.
+    pub assume_chip: Option<Chip>,
+    // Names defined for '#ifdef'.
+    pub defines: Vec<String>,
+    // Warn about lines longer than this, ignoring comments.
+    pub max_line: Option<usize>,
+    // Warn if a comment of column numbers above a pin row doesn't
+    // match the number of pins.
+    pub check_pin_numbers: bool,
+    // Check but don't apply 'OPTIONS' lines, so that only the
+    // command line sets the outputs.
+    pub ignore_options: bool,
+    pub limits: Limits,
+}
+
+// Bounds on the input, so that a library user fed untrusted source
+// gets an error rather than unbounded memory or CPU use. The defaults
+// are far beyond anything that fits in a real GAL.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_name_len: usize,
+    pub max_terms: usize,
+    pub max_continuation_lines: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_name_len: 256,
+            max_terms: 4096,
+            max_continuation_lines: 1024,
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 // Parsing output
 //
@@ -23,7 +85,50 @@ pub struct Content {
     pub chip: Chip,
     pub sig: Vec<u8>,
     pub pins: Vec<String>,
+    // The lines the two rows of pin names are on.
+    pub pin_lines: [LineNum; 2],
     pub eqns: Vec<Equation>,
+    // 'ASSERT' lines, which state an output's expected logic, checked
+    // against the assembled fuses rather than programmed.
+    pub asserts: Vec<Equation>,
+    // Warnings found while parsing, reported along with the lints.
+    pub warnings: Vec<Warning>,
+    pub options: Options,
+    // Power-on levels for registered outputs, for the simulator only.
+    pub inits: Vec<Init>,
+}
+
+// 'INIT=0' or 'INIT=1' in the comment on a registered equation's
+// first line, as in 'Q.R = D ; INIT=1', gives the level of the
+// output, as written on the left-hand side, at power-on. Being in a
+// comment, it's invisible to GALasm, and it doesn't affect the fuses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Init {
+    pub line_num: LineNum,
+    pub pin: Pin,
+    pub level: bool,
+}
+
+// Output options set by 'OPTIONS' lines between the signature and
+// the pins, as in 'OPTIONS secure nochip'. Each is the equivalent of
+// the command-line flag of the same name. The command-line flags can
+// only switch these behaviours on, so the two combine, and
+// '--ignore-options' leaves the command line in sole charge.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    pub secure: bool,
+    pub nochip: bool,
+    pub nofuse: bool,
+    pub nopin: bool,
+}
+
+impl Options {
+    pub fn apply(&self, config: &mut writer::Config) {
+        config.jedec_sec_bit |= self.secure;
+        config.gen_chip &= !self.nochip;
+        config.gen_fuse &= !self.nofuse;
+        config.gen_pin &= !self.nopin;
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -50,6 +155,22 @@ pub enum Suffix {
     CLK,
     APRST,
     ARST,
+    POL,
+}
+
+impl fmt::Display for Suffix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "",
+            Self::T => "T",
+            Self::R => "R",
+            Self::E => "E",
+            Self::CLK => "CLK",
+            Self::APRST => "APRST",
+            Self::ARST => "ARST",
+            Self::POL => "POL",
+        })
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////
@@ -62,34 +183,87 @@ pub enum Suffix {
 const EOF_LINE: LineNum = 0;
 
 #[derive(Debug, Eq, PartialEq)]
-enum Token {
+pub(crate) enum Token {
     Item((NamedPin, Suffix)),
     Equals,
+    // ':=', for registered assignment.
+    RegEquals,
     And,
     Or,
+    // Separates the outputs in 'O1, O2 = ...'.
+    Comma,
 }
 
 #[derive(Debug, Eq, PartialEq)]
-struct NamedPin {
-    name: String,
-    neg: bool,
+pub(crate) struct NamedPin {
+    pub(crate) name: String,
+    pub(crate) neg: bool,
+    // Negated with a trailing '/', as in 'O/', which is only allowed
+    // on the left-hand side of an equation.
+    pub(crate) postfix: bool,
+}
+
+// Tokens are displayed in source syntax, always negating with a
+// leading '/'.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Item((NamedPin { name, neg, .. }, suffix)) => {
+                if *neg {
+                    f.write_str("/")?;
+                }
+                f.write_str(name)?;
+                if *suffix != Suffix::None {
+                    write!(f, ".{}", suffix)?;
+                }
+                Ok(())
+            }
+            Token::Equals => f.write_str("="),
+            Token::RegEquals => f.write_str(":="),
+            Token::And => f.write_str("*"),
+            Token::Or => f.write_str("+"),
+            Token::Comma => f.write_str(","),
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////
 // Input tokenisation
 //
 
-// Tokenise a full line.
-fn tokenise((line_num, s): (LineNum, &str)) -> Result<Vec<(LineNum, Token)>, Error> {
+// Tokenise a full line. The chip is only needed to explain bad
+// suffixes.
+pub(crate) fn tokenise(
+    limits: &Limits,
+    chip: Chip,
+    (line_num, s): (LineNum, &str),
+) -> Result<Vec<(LineNum, Token)>, Error> {
     let mut res = Vec::new();
+    let mut num_terms = 0;
     let mut chars = s.chars().peekable();
+    // Tabs are always separators, so we track them to explain a name
+    // that's been split in two by one.
+    let mut after_tab = false;
     loop {
-        match chars.peek().cloned() {
+        let c = chars.peek().cloned();
+        if after_tab && matches!(c, Some(c) if c.is_ascii_digit()) {
+            return err(line_num, ErrorCode::TabInName);
+        }
+        after_tab = c == Some('\t');
+        match c {
             Some(c) => match c {
                 '=' => {
                     chars.next();
                     res.push((line_num, Token::Equals));
                 }
+                ':' => {
+                    chars.next();
+                    if chars.peek() != Some(&'=') {
+                        return err(line_num, ErrorCode::BadChar { c });
+                    }
+                    chars.next();
+                    res.push((line_num, Token::RegEquals));
+                }
                 '+' | '#' => {
                     chars.next();
                     res.push((line_num, Token::Or));
@@ -98,8 +272,22 @@ fn tokenise((line_num, s): (LineNum, &str)) -> Result<Vec<(LineNum, Token)>, Err
                     chars.next();
                     res.push((line_num, Token::And));
                 }
-                '/' => res.push(tokenise_pin(line_num, &mut chars)?),
-                c if c.is_ascii_alphabetic() => res.push(tokenise_pin(line_num, &mut chars)?),
+                ',' => {
+                    chars.next();
+                    res.push((line_num, Token::Comma));
+                }
+                c if c == '/' || c == '!' || c.is_ascii_alphabetic() => {
+                    num_terms += 1;
+                    if num_terms > limits.max_terms {
+                        return err(
+                            line_num,
+                            ErrorCode::TooManyTerms {
+                                max: limits.max_terms,
+                            },
+                        );
+                    }
+                    res.push(tokenise_pin(limits, chip, line_num, &mut chars)?);
+                }
                 c if c.is_whitespace() => {
                     chars.next();
                 }
@@ -111,15 +299,20 @@ fn tokenise((line_num, s): (LineNum, &str)) -> Result<Vec<(LineNum, Token)>, Err
 }
 
 // Tokenise a single pin name.
-fn tokenise_pin<I>(line_num: LineNum, chars: &mut Peekable<I>) -> Result<(LineNum, Token), Error>
+fn tokenise_pin<I>(
+    limits: &Limits,
+    chip: Chip,
+    line_num: LineNum,
+    chars: &mut Peekable<I>,
+) -> Result<(LineNum, Token), Error>
 where
     I: Iterator<Item = char>,
 {
     let mut name = String::new();
     let mut neg = false;
 
-    // Look for a negation prefix.
-    if chars.peek() == Some(&'/') {
+    // Look for a negation prefix, '/' or ABEL's '!'.
+    if matches!(chars.peek(), Some('/') | Some('!')) {
         chars.next();
         neg = true;
     }
@@ -138,6 +331,14 @@ where
     loop {
         match chars.peek().cloned() {
             Some(c) if c.is_ascii_alphanumeric() => {
+                if name.len() == limits.max_name_len {
+                    return err(
+                        line_num,
+                        ErrorCode::NameTooLong {
+                            max: limits.max_name_len,
+                        },
+                    );
+                }
                 chars.next();
                 name.push(c);
             }
@@ -145,7 +346,17 @@ where
         }
     }
 
-    let named_pin = NamedPin { name, neg };
+    // Other tools put the negation after the name.
+    let postfix = chars.peek() == Some(&'/');
+    if postfix {
+        chars.next();
+        if neg {
+            return err(line_num, ErrorCode::InvertedTwice { name });
+        }
+        neg = true;
+    }
+
+    let named_pin = NamedPin { name, neg, postfix };
 
     // Look for extension
     let mut suffix = Suffix::None;
@@ -155,37 +366,61 @@ where
         loop {
             match chars.peek().cloned() {
                 Some(c) if c.is_ascii_alphanumeric() => {
+                    if ext.len() == limits.max_name_len {
+                        return err(
+                            line_num,
+                            ErrorCode::NameTooLong {
+                                max: limits.max_name_len,
+                            },
+                        );
+                    }
                     chars.next();
                     ext.push(c);
                 }
                 _ => break,
             }
         }
-        suffix = at_line(line_num, ext_to_suffix(&ext))?;
+        suffix = at_line(line_num, ext_to_suffix(chip, &ext))?;
     }
 
     Ok((line_num, Token::Item((named_pin, suffix))))
 }
 
-fn ext_to_suffix(s: &str) -> Result<Suffix, ErrorCode> {
+fn ext_to_suffix(chip: Chip, s: &str) -> Result<Suffix, ErrorCode> {
     Ok(match s {
         "T" => Suffix::T,
-        "R" => Suffix::R,
         "E" => Suffix::E,
         "CLK" => Suffix::CLK,
-        "APRST" => Suffix::APRST,
-        "ARST" => Suffix::ARST,
+        // Synonyms used by other tools.
+        "R" | "D" => Suffix::R,
+        "APRST" | "SET" => Suffix::APRST,
+        "ARST" | "RESET" => Suffix::ARST,
+        "POL" => Suffix::POL,
         _ => {
             return Err(ErrorCode::BadSuffix {
                 suffix: s.to_string(),
+                valid: valid_suffixes(chip),
             })
         }
     })
 }
 
+// The suffixes 'ext_to_suffix' accepts that make sense on the chip,
+// leaving out the synonyms.
+fn valid_suffixes(chip: Chip) -> &'static str {
+    match chip {
+        Chip::GAL20RA10 => ".T, .R, .E, .CLK, .ARST, .APRST and .POL",
+        // '.CLK' is only accepted to document the fixed pin 1 clock.
+        Chip::GAL22V10 => ".T, .R, .E, .CLK and .POL",
+        _ => ".T, .R, .E and .POL",
+    }
+}
+
 // Take an iterator that returns lines, convert it to an iterator that
 // converts lines and concatenates continuation lines.
 fn tokenised_lines<'a, I>(
+    limits: Limits,
+    chip: Chip,
     lines: I,
 ) -> impl Iterator<Item = Result<Vec<(LineNum, Token)>, Error>> + 'a
 where
@@ -201,18 +436,31 @@ where
     where
         I: Iterator<Item = TokItem>,
     {
+        // A leading '=' continues an equation whose LHS is on the
+        // previous line. If that line already had an '=', the
+        // equation parser will reject the second one.
         if let Some(Ok(line)) = iter.peek() {
-            matches!(line.first(), Some((_, Token::And)) | Some((_, Token::Or)))
+            matches!(
+                line.first(),
+                Some((_, Token::And | Token::Or | Token::Equals | Token::RegEquals))
+            )
         } else {
             false
         }
     }
 
+    fn num_terms(v: &[(LineNum, Token)]) -> usize {
+        v.iter()
+            .filter(|(_, token)| matches!(token, Token::Item(_)))
+            .count()
+    }
+
     struct ConcatIterator<T>
     where
         T: Iterator<Item = TokItem>,
     {
         iter: Peekable<T>,
+        limits: Limits,
     }
 
     impl<T> Iterator for ConcatIterator<T>
@@ -224,11 +472,41 @@ where
         fn next(&mut self) -> Option<Self::Item> {
             match self.iter.next() {
                 Some(Ok(mut line)) => {
+                    let mut terms = num_terms(&line);
+                    let mut continuations = 0;
                     while has_continuation(&line) || is_continuation(&mut self.iter) {
                         match self.iter.next() {
-                            Some(Ok(mut next)) => line.append(&mut next),
+                            Some(Ok(mut next)) => {
+                                let (line_num, _) = next.first().unwrap();
+                                let line_num = *line_num;
+                                continuations += 1;
+                                if continuations > self.limits.max_continuation_lines {
+                                    return Some(err(
+                                        line_num,
+                                        ErrorCode::TooManyContinuationLines {
+                                            max: self.limits.max_continuation_lines,
+                                        },
+                                    ));
+                                }
+                                terms += num_terms(&next);
+                                if terms > self.limits.max_terms {
+                                    return Some(err(
+                                        line_num,
+                                        ErrorCode::TooManyTerms {
+                                            max: self.limits.max_terms,
+                                        },
+                                    ));
+                                }
+                                line.append(&mut next);
+                            }
                             e @ Some(Err(_)) => return e,
-                            // EOF? Let the error get handled later.
+                            // EOF after a trailing operator is an error,
+                            // reported on the line with the operator.
+                            None if has_continuation(&line) => {
+                                let (line_num, _) = line.last().unwrap();
+                                return Some(err(*line_num, ErrorCode::ContinuationEOF));
+                            }
+                            // Otherwise, let any error get handled later.
                             None => return Some(Ok(line)),
                         }
                     }
@@ -241,14 +519,17 @@ where
     }
 
     ConcatIterator {
-        iter: lines.map(tokenise).peekable(),
+        iter: lines
+            .map(move |line| tokenise(&limits, chip, line))
+            .peekable(),
+        limits,
     }
 }
 
 ////////////////////////////////////////////////////////////////////////
 // Functions to extract specific elements.
 
-fn remove_comment(s: &str) -> &str {
+pub(crate) fn remove_comment(s: &str) -> &str {
     match s.find(';') {
         Some(i) => &s[..i],
         None => s,
@@ -265,19 +546,71 @@ where
     }
 }
 
-fn parse_chip<'a, I>(line_iter: &mut I) -> Result<Chip, Error>
+// Find a chip name among the dot-separated parts of a file name,
+// with or without the "GAL" prefix.
+fn chip_from_file_name(file_name: &str) -> Option<Chip> {
+    let name = Path::new(file_name).file_name()?.to_str()?;
+    name.split('.').find_map(|part| {
+        let part = part.to_uppercase();
+        if part.starts_with("GAL") {
+            Chip::from_name(&part).ok()
+        } else {
+            Chip::from_name(&format!("GAL{}", part)).ok()
+        }
+    })
+}
+
+// If the first line isn't a chip type and there's a fallback chip,
+// the line is left for the signature, as the chip line is missing.
+// A chip given in the config is used silently, but one guessed from
+// the file name gets a warning.
+fn parse_chip<'a, I>(
+    line_iter: &mut Peekable<I>,
+    config: &Config,
+    fallback: Option<Chip>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Chip, Error>
 where
     I: Iterator<Item = (LineNum, &'a str)>,
 {
+    if let Some((line_num, name)) = line_iter.peek() {
+        if Chip::from_name(name).is_err() {
+            if let Some(chip) = config.assume_chip {
+                return Ok(chip);
+            }
+            if let Some(chip) = fallback {
+                warnings.push(Warning {
+                    code: WarningCode::AssumedChip {
+                        chip: chip.name().to_string(),
+                    },
+                    line: *line_num,
+                });
+                return Ok(chip);
+            }
+        }
+    }
+
     let (line_num, name) = next_or_fail(
         line_iter,
         ErrorCode::BadGALType {
             gal: "<eof>".to_string(),
         },
     )?;
-    at_line(line_num, Chip::from_name(name.trim()))
+    let chip = at_line(line_num, Chip::from_name(name.trim()))?;
+    match config.assume_chip {
+        Some(expected) if expected != chip => err(
+            line_num,
+            ErrorCode::ChipMismatch {
+                found: chip.name().to_string(),
+                expected: expected.name().to_string(),
+            },
+        ),
+        _ => Ok(chip),
+    }
 }
 
+// Comments have been stripped by now, so a line holding only a
+// comment is an empty signature, not a missing one.
 fn parse_signature<'a, I>(line_iter: &mut I) -> Result<Vec<u8>, Error>
 where
     I: Iterator<Item = (LineNum, &'a str)>,
@@ -286,56 +619,135 @@ where
     Ok(sig.bytes().take(8).collect::<Vec<u8>>())
 }
 
-// Parse one line of pins
+// What a pin name refers to: one pin, or the pins of a group of
+// paralleled inputs, declared by writing '@NAME' on each of them.
+#[derive(Clone, Debug)]
+struct PinRef {
+    group: bool,
+    pins: Vec<Pin>,
+}
+
+// Strip the '@' from group members in a pin row, so that it can be
+// tokenised, returning which pins were marked. Every word must be a
+// single name, or a pin's two names joined by a comma, so the markers
+// line up with the pins.
+pub(crate) fn strip_group_markers(line: &str) -> (Vec<bool>, String) {
+    let mut words: Vec<String> = Vec::new();
+    for word in line.split_whitespace() {
+        match words.last_mut() {
+            Some(last) if last.ends_with(',') || word.starts_with(',') => last.push_str(word),
+            _ => words.push(word.to_string()),
+        }
+    }
+    let grouped = words
+        .iter()
+        .map(|word| word.starts_with('@'))
+        .collect::<Vec<_>>();
+    let stripped = words
+        .iter()
+        .map(|word| word.strip_prefix('@').unwrap_or(word))
+        .collect::<Vec<_>>()
+        .join(" ");
+    (grouped, stripped)
+}
+
+// Parse one line of pins, returning them with the line number.
 fn parse_pins<'a, I>(
-    pin_map: &mut HashMap<String, Pin>,
+    limits: &Limits,
+    pin_map: &mut HashMap<String, PinRef>,
     chip: Chip,
     row_num: usize,
     line_iter: &mut I,
-) -> Result<Vec<(String, bool)>, Error>
+) -> Result<(LineNum, Vec<(String, bool)>), Error>
 where
     I: Iterator<Item = (LineNum, &'a str)>,
 {
     let mut pins = Vec::new();
     let line @ (line_num, _) = next_or_fail(line_iter, ErrorCode::BadPinEOF)?;
-    let tokens = tokenise(line)?;
-    let len = tokens.len();
-    for token in tokens.into_iter() {
-        match token {
-            (_, Token::Item((name, suffix))) if suffix == Suffix::None => {
-                pins.push((name.name, name.neg))
+    // An equation here most likely means the pins were forgotten or
+    // put further down, which is clearer to say than a bad pin count.
+    if line.1.contains('=') {
+        return err(line_num, ErrorCode::EquationBeforePins);
+    }
+    let (grouped, stripped) = strip_group_markers(line.1);
+    // Each pin's second name, as in 'DQOUT,DQIN'.
+    let mut second_names = Vec::new();
+    let mut after_comma = false;
+    for token in tokenise(limits, chip, (line_num, &stripped))?.into_iter() {
+        let name = match token {
+            (line_num, Token::Item((name, _))) if name.postfix => {
+                return err(line_num, ErrorCode::PostfixNegation)
             }
+            (_, Token::Item((name, Suffix::None))) => (name.name, name.neg),
             (line_num, Token::Item(_)) => return err(line_num, ErrorCode::BadPinSuffix),
+            (_, Token::Comma) if !after_comma && second_names.last() == Some(&None) => {
+                after_comma = true;
+                continue;
+            }
             (line_num, _) => return err(line_num, ErrorCode::BadToken { expected: "pin" }),
+        };
+        if after_comma {
+            *second_names.last_mut().unwrap() = Some(name);
+            after_comma = false;
+        } else {
+            pins.push(name);
+            second_names.push(None);
         }
     }
+    if after_comma {
+        return err(line_num, ErrorCode::BadToken { expected: "pin" });
+    }
+    let len = pins.len();
 
     // We test this afterwards in case there was a bad token
     // causing us to miscount. In that case, the earlier error
     // message willl be more useful.
     if len != chip.num_pins() / 2 {
+        let (found, expected) = (len, chip.num_pins() / 2);
+        // Tabs are separators, but might have been meant as part of a name.
         return err(
             line_num,
-            ErrorCode::BadPinCount {
-                found: len,
-                expected: chip.num_pins() / 2,
+            if line.1.contains('\t') {
+                ErrorCode::BadPinCountTab { found, expected }
+            } else {
+                ErrorCode::BadPinCount { found, expected }
             },
         );
     }
 
     // Extend the pin map with the pins we've just defined.
-    at_line(line_num, extend_pin_map(pin_map, chip, row_num, &pins))?;
+    at_line(
+        line_num,
+        extend_pin_map(pin_map, chip, row_num, &pins, &second_names, &grouped),
+    )?;
 
-    Ok(pins)
+    Ok((line_num, pins))
 }
 
+// Look up a name, returning several pins for a group of paralleled
+// inputs.
 fn lookup_pin(
     chip: Chip,
-    pin_map: &HashMap<String, Pin>,
+    pin_map: &HashMap<String, PinRef>,
     pin_name: &NamedPin,
-) -> Result<Pin, ErrorCode> {
-    let pin = pin_map
-        .get(pin_name.name.as_str())
+) -> Result<Vec<Pin>, ErrorCode> {
+    // TRUE and FALSE are spellings of VCC and GND, so they get the
+    // same treatment, being allowed only as a whole right-hand side.
+    let constant = match pin_name.name.as_str() {
+        "TRUE" => Some(Pin {
+            pin: chip.num_pins(),
+            neg: false,
+        }),
+        "FALSE" => Some(Pin {
+            pin: chip.num_pins() / 2,
+            neg: false,
+        }),
+        _ => None,
+    };
+
+    let pins = constant
+        .map(|pin| vec![pin])
+        .or_else(|| pin_map.get(pin_name.name.as_str()).map(|r| r.pins.clone()))
         .ok_or_else(|| match pin_name.name.as_str() {
             "NC" => ErrorCode::BadNC,
             "AR" if chip == Chip::GAL22V10 => ErrorCode::BadSpecial {
@@ -349,20 +761,30 @@ fn lookup_pin(
             },
         })?;
 
-    Ok(Pin {
-        pin: pin.pin,
-        neg: pin.neg != pin_name.neg,
-    })
+    Ok(pins
+        .iter()
+        .map(|pin| Pin {
+            pin: pin.pin,
+            neg: pin.neg != pin_name.neg,
+        })
+        .collect())
 }
 
-// Read a pin on the RHS (where suffices are not allowed), and convert to pin number.
-fn parse_pin<I>(chip: Chip, pin_map: &HashMap<String, Pin>, iter: &mut I) -> Result<Pin, Error>
+// Read a pin on the RHS (where suffices are not allowed), and convert
+// to pin numbers, of which a group has several.
+fn parse_pin<I>(
+    chip: Chip,
+    pin_map: &HashMap<String, PinRef>,
+    iter: &mut I,
+) -> Result<Vec<Pin>, Error>
 where
     I: Iterator<Item = (LineNum, Token)>,
 {
     let (line_num, token) = next_or_fail(iter, ErrorCode::BadEOL)?;
     if let Token::Item((named_pin, suffix)) = token {
-        if suffix != Suffix::None {
+        if named_pin.postfix {
+            err(line_num, ErrorCode::PostfixNegation)
+        } else if suffix != Suffix::None {
             err(line_num, ErrorCode::BadPinSuffix)
         } else {
             at_line(line_num, lookup_pin(chip, pin_map, &named_pin))
@@ -373,7 +795,7 @@ where
 }
 
 // Parse and check the LHS (where suffices are allowed, but there are other constraints)
-fn parse_lhs<I>(chip: Chip, pin_map: &HashMap<String, Pin>, iter: &mut I) -> Result<LHS, Error>
+fn parse_lhs<I>(chip: Chip, pin_map: &HashMap<String, PinRef>, iter: &mut I) -> Result<LHS, Error>
 where
     I: Iterator<Item = (LineNum, Token)>,
 {
@@ -403,7 +825,24 @@ where
                     LHS::Sp
                 }
             } else {
-                let pin = at_line(line_num, lookup_pin(chip, pin_map, &named_pin))?;
+                let mut pins = at_line(line_num, lookup_pin(chip, pin_map, &named_pin))?;
+                // Paralleled pins can only be inputs, as outputs
+                // can't be merged on-chip.
+                if pins.len() > 1 {
+                    return err(
+                        line_num,
+                        ErrorCode::GroupAsOutput {
+                            name: named_pin.name,
+                        },
+                    );
+                }
+                let mut pin = pins.remove(0);
+                // A pin declared active-low sets the sense of its output,
+                // but control terms have no sense to flip, so only an
+                // explicit '/' on the equation counts as inverting them.
+                if !matches!(suffix, Suffix::None | Suffix::T | Suffix::R) {
+                    pin.neg = named_pin.neg;
+                }
                 LHS::Pin((pin, suffix))
             }
         }
@@ -411,34 +850,88 @@ where
     })
 }
 
+// 'ASSERT O = ...' is followed by a pin, where an equation would have
+// an operator, so it can't be confused with a pin named ASSERT.
+fn is_assert(tokens: &[(LineNum, Token)]) -> bool {
+    match tokens {
+        [(
+            _,
+            Token::Item((
+                NamedPin {
+                    name, neg: false, ..
+                },
+                Suffix::None,
+            )),
+        ), (_, Token::Item(_)), ..] => name == "ASSERT",
+        _ => false,
+    }
+}
+
+// Parse an equation. 'O1, O2 = ...' assigns the same right-hand side
+// to several outputs, giving an equation for each.
 fn parse_equation<I>(
     chip: Chip,
-    pin_map: &HashMap<String, Pin>,
+    pin_map: &HashMap<String, PinRef>,
     tokens: &mut I,
-) -> Result<Equation, Error>
+) -> Result<Vec<Equation>, Error>
 where
     I: Iterator<Item = (LineNum, Token)>,
 {
-    let lhs = parse_lhs(chip, pin_map, tokens)?;
+    let mut lhses = vec![parse_lhs(chip, pin_map, tokens)?];
 
-    let (line_num, eq_token) = next_or_fail(tokens, ErrorCode::BadEquationEOF)?;
-    if eq_token != Token::Equals {
-        return err(line_num, ErrorCode::NoEquals);
+    let (mut line_num, mut eq_token) = next_or_fail(tokens, ErrorCode::BadEquationEOF)?;
+    while eq_token == Token::Comma {
+        let lhs = parse_lhs(chip, pin_map, tokens)?;
+        // The outputs must all be the same kind, so that the list
+        // reads as one assignment.
+        match (&lhses[0], &lhs) {
+            (LHS::Pin((_, first)), LHS::Pin((_, suffix))) if first == suffix => (),
+            _ => return err(line_num, ErrorCode::MixedOutputList),
+        }
+        lhses.push(lhs);
+        let next = next_or_fail(tokens, ErrorCode::BadEquationEOF)?;
+        line_num = next.0;
+        eq_token = next.1;
+    }
+    match eq_token {
+        Token::Equals => (),
+        // 'Q := D' is another way of writing 'Q.R = D'.
+        Token::RegEquals => {
+            for lhs in lhses.iter_mut() {
+                match lhs {
+                    LHS::Pin((pin, Suffix::None)) => *lhs = LHS::Pin((*pin, Suffix::R)),
+                    LHS::Pin(_) => return err(line_num, ErrorCode::RegEqualsSuffix),
+                    LHS::Ar | LHS::Sp => return err(line_num, ErrorCode::RegEqualsSpecial),
+                }
+            }
+        }
+        _ => return err(line_num, ErrorCode::NoEquals),
     }
 
-    let mut rhs = vec![parse_pin(chip, pin_map, tokens)?];
-    let mut is_or = vec![false];
+    let mut rhs = Vec::new();
+    let mut is_or = Vec::new();
+    // A group's pins are ANDed together, being the same signal.
+    let mut push = |pins: Vec<Pin>, or: bool| {
+        for (pin, i) in pins.into_iter().zip(0..) {
+            rhs.push(pin);
+            is_or.push(or && i == 0);
+        }
+    };
+    push(parse_pin(chip, pin_map, tokens)?, false);
 
     loop {
         match tokens.next() {
-            Some((_, Token::And)) => {
-                is_or.push(false);
-                rhs.push(parse_pin(chip, pin_map, tokens)?);
-            }
-            Some((_, Token::Or)) => {
-                is_or.push(true);
-                rhs.push(parse_pin(chip, pin_map, tokens)?);
+            // Two names in a row, probably split by whitespace.
+            Some((token_line_num, Token::Item((named_pin, _)))) => {
+                return err(
+                    token_line_num,
+                    ErrorCode::MissingOperator {
+                        name: named_pin.name,
+                    },
+                )
             }
+            Some((_, Token::And)) => push(parse_pin(chip, pin_map, tokens)?, false),
+            Some((_, Token::Or)) => push(parse_pin(chip, pin_map, tokens)?, true),
             Some((token_line_num, _)) => {
                 return err(
                     token_line_num,
@@ -451,98 +944,288 @@ where
         }
     }
 
-    Ok(Equation {
-        line_num,
-        lhs,
-        rhs,
-        is_or,
-    })
+    Ok(lhses
+        .into_iter()
+        .map(|lhs| Equation {
+            line_num,
+            lhs,
+            rhs: rhs.clone(),
+            is_or: is_or.clone(),
+        })
+        .collect())
 }
 
 // Add a row's worth of pins to the pin map.
 fn extend_pin_map(
-    pin_map: &mut HashMap<String, Pin>,
+    pin_map: &mut HashMap<String, PinRef>,
     chip: Chip,
     row_num: usize,
     pins: &[(String, bool)],
+    second_names: &[Option<(String, bool)>],
+    grouped: &[bool],
 ) -> Result<(), ErrorCode> {
     let num_pins = chip.num_pins();
     let first_pin = 1 + row_num * num_pins / 2;
-    for ((name, neg), pin_num) in pins.iter().cloned().zip(first_pin..) {
-        if pin_num == num_pins && (name.as_str(), neg) != ("VCC", false) {
-            return Err(ErrorCode::InvalidPowerPinName {
+    let names = pins.iter().cloned().zip(second_names).zip(grouped);
+    for ((((name, neg), second_name), group), pin_num) in names.zip(first_pin..) {
+        // A second name gives an output's input sense its own name,
+        // so only makes sense on a pin with an OLMC.
+        if let Some((second_name, _)) = second_name {
+            if chip.pin_to_olmc(pin_num).is_none() {
+                return Err(ErrorCode::SecondNameNotOutput { pin: pin_num });
+            }
+            if name == "NC" || second_name == "NC" {
+                return Err(ErrorCode::SecondNameNC { pin: pin_num });
+            }
+        }
+
+        // Each power pin named as the other is most likely a swap,
+        // rather than a power name used as a signal.
+        if pin_num == num_pins && name == "GND" {
+            return Err(ErrorCode::SwappedPowerPins {
                 pin: pin_num,
-                name: "VCC",
+                name: "GND",
+                expected: "VCC",
             });
         }
-        if pin_num == num_pins / 2 && (name.as_str(), neg) != ("GND", false) {
-            return Err(ErrorCode::InvalidPowerPinName {
+        if pin_num == num_pins / 2 && name == "VCC" {
+            return Err(ErrorCode::SwappedPowerPins {
                 pin: pin_num,
-                name: "GND",
+                name: "VCC",
+                expected: "GND",
             });
         }
-        if name == "VCC" && pin_num != num_pins {
-            return Err(ErrorCode::InvalidPowerPinLocation {
+        if pin_num == num_pins && (name.as_str(), neg) != ("VCC", false) {
+            return Err(ErrorCode::InvalidPowerPinName {
                 pin: pin_num,
                 name: "VCC",
-                expected_pin: num_pins,
             });
         }
-        if name == "GND" && pin_num != num_pins / 2 {
-            return Err(ErrorCode::InvalidPowerPinLocation {
+        if pin_num == num_pins / 2 && (name.as_str(), neg) != ("GND", false) {
+            return Err(ErrorCode::InvalidPowerPinName {
                 pin: pin_num,
                 name: "GND",
-                expected_pin: num_pins / 2,
             });
         }
-        if name != "NC" {
-            if pin_map.contains_key(&name) {
-                return Err(ErrorCode::RepeatedPinName { name });
-            }
 
-            if chip == Chip::GAL22V10 {
-                // parse returns Ok if name is "AR" or "SP"
-                if let Ok(term) = name.parse() {
-                    return Err(ErrorCode::ReservedPinName { term });
-                }
+        let second_name = second_name.clone().map(|(name, neg)| (name, neg, false));
+        for (name, neg, group) in std::iter::once((name, neg, *group)).chain(second_name) {
+            if name == "VCC" && pin_num != num_pins {
+                return Err(ErrorCode::InvalidPowerPinLocation {
+                    pin: pin_num,
+                    name: "VCC",
+                    expected_pin: num_pins,
+                });
+            }
+            if name == "GND" && pin_num != num_pins / 2 {
+                return Err(ErrorCode::InvalidPowerPinLocation {
+                    pin: pin_num,
+                    name: "GND",
+                    expected_pin: num_pins / 2,
+                });
             }
+            if name != "NC" {
+                add_pin_name(pin_map, chip, name, Pin { pin: pin_num, neg }, group)?;
+            }
+        }
+    }
+
+    Ok(())
+}
 
-            pin_map.insert(name, Pin { pin: pin_num, neg });
+fn add_pin_name(
+    pin_map: &mut HashMap<String, PinRef>,
+    chip: Chip,
+    name: String,
+    pin: Pin,
+    group: bool,
+) -> Result<(), ErrorCode> {
+    if let Some(pin_ref) = pin_map.get_mut(&name) {
+        if !(pin_ref.group && group) {
+            return Err(ErrorCode::RepeatedPinName { name });
+        }
+        // Every pin of a group must be the same signal.
+        if pin_ref.pins[0].neg != pin.neg {
+            return Err(ErrorCode::InconsistentGroup { name });
+        }
+        pin_ref.pins.push(pin);
+        return Ok(());
+    }
+
+    if name == "TRUE" || name == "FALSE" {
+        return Err(ErrorCode::ReservedConstantName { name });
+    }
+
+    if chip == Chip::GAL22V10 {
+        // parse returns Ok if name is "AR" or "SP"
+        if let Ok(term) = name.parse() {
+            return Err(ErrorCode::ReservedPinName { term });
         }
     }
 
+    pin_map.insert(
+        name,
+        PinRef {
+            group,
+            pins: vec![pin],
+        },
+    );
     Ok(())
 }
 
-fn parse_core<'a, I>(line_iter: I) -> Result<Content, Error>
+// Chip type and signature must be on first two lines. Returns the
+// chip, signature and the signature's line number.
+fn parse_header<'a, I>(
+    config: &Config,
+    fallback: Option<Chip>,
+    line_iter: &mut Peekable<I>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(Chip, Vec<u8>, LineNum), Error>
 where
     I: Iterator<Item = (LineNum, &'a str)>,
 {
-    // Ignore comments (and start/end-of-line whitespace) on all lines.
-    let mut line_iter = line_iter.map(|(i, x)| (i, str::trim(remove_comment(x))));
-
-    // Chip type and signature must be on first two lines.
-    let chip = parse_chip(&mut line_iter)?;
-    let signature = parse_signature(&mut line_iter)?;
+    let chip = parse_chip(line_iter, config, fallback, warnings)?;
+    let sig_line = line_iter.peek().map_or(EOF_LINE, |(line_num, _)| *line_num);
+    // The whole line is checked, not just the part that's kept.
+    let blank_sig = line_iter
+        .peek()
+        .is_some_and(|(_, sig)| sig.trim().is_empty());
+    let signature = parse_signature(line_iter)?;
+    if config.require_signature && blank_sig {
+        return err(sig_line, ErrorCode::BlankSignature);
+    }
+    Ok((chip, signature, sig_line))
+}
 
-    // We now ignore blank lines. Unlike galasm, we don't *require* a
-    // DESCRIPTION line, but if we encounter one we stop there.
-    let mut line_iter = line_iter
+// After the header, blank lines are ignored. Unlike galasm, we don't
+// *require* a DESCRIPTION line (unless configured to), but if we
+// encounter one we stop there (unless configured to skip it and
+// carry on). 'found_description' records whether there was one.
+fn body_lines<'a, I>(
+    config: &'a Config,
+    found_description: &'a Cell<bool>,
+    line_iter: I,
+) -> Peekable<impl Iterator<Item = (LineNum, &'a str)> + 'a>
+where
+    I: Iterator<Item = (LineNum, &'a str)> + 'a,
+{
+    line_iter
         .filter(|(_, x)| !x.is_empty())
-        .take_while(|(_, x)| *x != "DESCRIPTION");
+        .filter(move |(_, x)| {
+            let is_description = *x == "DESCRIPTION";
+            found_description.set(found_description.get() || is_description);
+            !(is_description && config.parse_past_description)
+        })
+        .take_while(|(_, x)| *x != "DESCRIPTION")
+        .peekable()
+}
+
+// The 'OPTIONS' lines and pin definitions before the equations.
+struct Preamble {
+    options: Options,
+    pin_map: HashMap<String, PinRef>,
+    pins: Vec<(String, bool)>,
+    pin_lines: [LineNum; 2],
+}
+
+fn parse_preamble<'a, I>(
+    config: &Config,
+    chip: Chip,
+    line_iter: &mut Peekable<I>,
+) -> Result<Preamble, Error>
+where
+    I: Iterator<Item = (LineNum, &'a str)>,
+{
+    let mut options = Options::default();
+    while let Some((line_num, line)) = line_iter.next_if(|(_, x)| is_options(x)) {
+        at_line(line_num, parse_options(&mut options, line))?;
+    }
+    if config.ignore_options {
+        options = Options::default();
+    }
 
     let mut pin_map = HashMap::new();
-    let mut pins = parse_pins(&mut pin_map, chip, 0, &mut line_iter)?;
-    let mut pins2 = parse_pins(&mut pin_map, chip, 1, &mut line_iter)?;
+    let (line1, mut pins) = parse_pins(&config.limits, &mut pin_map, chip, 0, line_iter)?;
+    let (line2, mut pins2) = parse_pins(&config.limits, &mut pin_map, chip, 1, line_iter)?;
     pins.append(&mut pins2);
 
+    Ok(Preamble {
+        options,
+        pin_map,
+        pins,
+        pin_lines: [line1, line2],
+    })
+}
+
+fn parse_core<'a, I>(
+    config: &Config,
+    fallback: Option<Chip>,
+    line_iter: I,
+) -> Result<Content, Error>
+where
+    I: Iterator<Item = (LineNum, &'a str)>,
+{
+    // The pin number check needs the comments.
+    let raw_lines = line_iter.collect::<Vec<_>>();
+
+    // Ignore comments (and start/end-of-line whitespace) on all lines.
+    // The DESCRIPTION is free text, so long lines are only checked
+    // as they're parsed.
+    let long_lines = RefCell::new(Vec::new());
+    let mut line_iter = raw_lines
+        .iter()
+        .cloned()
+        .map(|(i, x)| (i, str::trim(remove_comment(x))))
+        .inspect(|(i, x)| match config.max_line {
+            Some(max) if x.chars().count() > max => long_lines.borrow_mut().push(Warning {
+                code: WarningCode::LongLine {
+                    len: x.chars().count(),
+                    max,
+                },
+                line: *i,
+            }),
+            _ => (),
+        })
+        .peekable();
+
+    let mut warnings = Vec::new();
+    let (chip, signature, sig_line) =
+        parse_header(config, fallback, &mut line_iter, &mut warnings)?;
+    let found_description = Cell::new(false);
+    let mut line_iter = body_lines(config, &found_description, line_iter);
+    let Preamble {
+        options,
+        pin_map,
+        pins,
+        pin_lines,
+    } = parse_preamble(config, chip, &mut line_iter)?;
+
     // We tokenise the lines first, as the equation parser will want
     // to look ahead onto the token starting the next line (not yet
     // implemented).
     let mut equations = Vec::new();
-    for tokens_or_err in tokenised_lines(line_iter) {
+    let mut asserts = Vec::new();
+    for tokens_or_err in tokenised_lines(config.limits, chip, line_iter) {
         let tokens = tokens_or_err?;
-        equations.push(parse_equation(chip, &pin_map, &mut tokens.into_iter())?);
+        if is_assert(&tokens) {
+            asserts.extend(parse_equation(
+                chip,
+                &pin_map,
+                &mut tokens.into_iter().skip(1),
+            )?);
+        } else {
+            equations.extend(parse_equation(chip, &pin_map, &mut tokens.into_iter())?);
+        }
+    }
+
+    let inits = parse_inits(&raw_lines, &equations)?;
+
+    if config.require_description && !found_description.get() {
+        return err(EOF_LINE, ErrorCode::NoDescription);
+    }
+    warnings.extend(long_lines.into_inner());
+    if config.check_pin_numbers {
+        warnings.extend(check_pin_numbers(&raw_lines, sig_line, chip.num_pins() / 2));
     }
 
     // The rest of the pipeline just wants string names.
@@ -563,10 +1246,115 @@ where
         chip,
         sig: signature,
         pins: pin_names,
+        pin_lines,
         eqns: equations,
+        asserts,
+        warnings,
+        options,
+        inits,
     })
 }
 
+// Find the 'INIT=' annotations in the comments on the equations'
+// first lines.
+fn parse_inits(lines: &[(LineNum, &str)], eqns: &[Equation]) -> Result<Vec<Init>, Error> {
+    let mut inits = Vec::new();
+    for eqn in eqns.iter() {
+        let comment = lines
+            .iter()
+            .find(|(line_num, _)| *line_num == eqn.line_num)
+            .and_then(|(_, line)| line.find(';').map(|i| &line[i + 1..]))
+            .unwrap_or("");
+        let value = match comment
+            .split_whitespace()
+            .find_map(|word| word.strip_prefix("INIT="))
+        {
+            Some(value) => value,
+            None => continue,
+        };
+        let level = match value {
+            "0" => false,
+            "1" => true,
+            _ => {
+                let value = value.to_string();
+                return err(eqn.line_num, ErrorCode::BadInit { value });
+            }
+        };
+        match &eqn.lhs {
+            LHS::Pin((pin, Suffix::R)) => inits.push(Init {
+                line_num: eqn.line_num,
+                pin: *pin,
+                level,
+            }),
+            _ => return err(eqn.line_num, ErrorCode::InitNotRegistered),
+        }
+    }
+    Ok(inits)
+}
+
+// Whether a line is an 'OPTIONS' line.
+pub(crate) fn is_options(line: &str) -> bool {
+    remove_comment(line).split_whitespace().next() == Some("OPTIONS")
+}
+
+fn parse_options(options: &mut Options, line: &str) -> Result<(), ErrorCode> {
+    for word in line.split_whitespace().skip(1) {
+        let option = match word {
+            "secure" => &mut options.secure,
+            "nochip" => &mut options.nochip,
+            "nofuse" => &mut options.nofuse,
+            "nopin" => &mut options.nopin,
+            _ => {
+                return Err(ErrorCode::UnknownOption {
+                    name: word.to_string(),
+                })
+            }
+        };
+        *option = true;
+    }
+    Ok(())
+}
+
+// Sources often have a comment of column numbers above each pin row,
+// e.g. "; 1 2 3 4 5 6 7 8 9 10". If the count doesn't match the row,
+// the numbers are probably misleading about which pin is which. The
+// pin rows are the first two lines with code after the signature.
+fn check_pin_numbers(lines: &[(LineNum, &str)], sig_line: LineNum, row_len: usize) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut numbers = None;
+    let mut rows = 0;
+    for (line_num, line) in lines.iter().filter(|(line_num, _)| *line_num > sig_line) {
+        if is_options(line) {
+            continue;
+        }
+        if !remove_comment(line).trim().is_empty() {
+            match numbers {
+                Some(numbers) if numbers != row_len => warnings.push(Warning {
+                    code: WarningCode::PinNumberMismatch {
+                        numbers,
+                        pins: row_len,
+                    },
+                    line: *line_num,
+                }),
+                _ => (),
+            }
+            numbers = None;
+            rows += 1;
+            if rows == 2 {
+                break;
+            }
+        } else if let Some((_, comment)) = line.split_once(';') {
+            let words = comment.split_whitespace().collect::<Vec<_>>();
+            numbers = if !words.is_empty() && words.iter().all(|w| w.parse::<usize>().is_ok()) {
+                Some(words.len())
+            } else {
+                None
+            };
+        }
+    }
+    warnings
+}
+
 fn err<T>(line_num: LineNum, error_code: ErrorCode) -> Result<T, Error> {
     Err(Error {
         code: error_code,
@@ -574,16 +1362,861 @@ fn err<T>(line_num: LineNum, error_code: ErrorCode) -> Result<T, Error> {
     })
 }
 
-pub fn parse(file_name: &str) -> Result<Content, Error> {
+pub fn parse(file_name: &str, config: &Config) -> Result<Content, Error> {
     let data = fs::read_to_string(file_name).expect("Unable to read file");
-    parse_core((1..).zip(data.lines())).map_err(|e| {
-        if e.line == EOF_LINE {
-            Error {
-                line: data.lines().count(),
-                ..e
+    parse_named(file_name, &data, config)
+}
+
+// Parse a file that's already been read, given its name, which is
+// needed to guess the chip.
+pub fn parse_named(file_name: &str, data: &str, config: &Config) -> Result<Content, Error> {
+    parse_data(data, fallback_chip(file_name, config), config)
+}
+
+fn fallback_chip(file_name: &str, config: &Config) -> Option<Chip> {
+    if config.assume_chip_from_filename {
+        chip_from_file_name(file_name)
+    } else {
+        None
+    }
+}
+
+// 'lines' copes with "\r\n", but a file with classic Mac "\r"
+// line endings would otherwise be read as a single line.
+pub(crate) fn normalise_line_endings(data: &str) -> Cow<'_, str> {
+    if data.contains('\r') {
+        Cow::Owned(data.replace("\r\n", "\n").replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+// Parse a source held in memory, rather than read from a file. With
+// no file name, there's no chip to assume.
+pub fn parse_str(data: &str, config: &Config) -> Result<Content, Error> {
+    parse_data(data, None, config)
+}
+
+// Conditional compilation directives, as the first word on a line.
+const DIRECTIVES: [&str; 3] = ["#ifdef", "#else", "#endif"];
+
+pub(crate) fn is_directive(line: &str) -> bool {
+    let first = remove_comment(line).split_whitespace().next();
+    matches!(first, Some(word) if DIRECTIVES.contains(&word))
+}
+
+// Handle "#ifdef NAME", "#else" and "#endif", which may be nested.
+// Directives and excluded lines are replaced with blank lines, so
+// that line numbers are unchanged. The DESCRIPTION section is free
+// text, so it's passed through untouched, unless it's to be parsed.
+fn preprocess(data: &str, defines: &[String], stop_at_description: bool) -> Result<String, Error> {
+    struct Block {
+        line_num: LineNum,
+        taken: bool,
+        seen_else: bool,
+    }
+
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut buf = String::new();
+    let mut lines = (1..).zip(data.lines());
+    for (line_num, line) in lines.by_ref() {
+        let mut words = remove_comment(line).split_whitespace();
+        let first = words.next();
+        if first == Some("DESCRIPTION") && stop_at_description {
+            buf.push_str(line);
+            buf.push('\n');
+            break;
+        }
+
+        let bad_directive = |directive, expected| {
+            err(
+                line_num,
+                ErrorCode::BadDirective {
+                    directive,
+                    expected,
+                },
+            )
+        };
+        match first {
+            Some("#ifdef") => {
+                let name = match (words.next(), words.next()) {
+                    (Some(name), None) => name,
+                    _ => return bad_directive("#ifdef", "a single name"),
+                };
+                blocks.push(Block {
+                    line_num,
+                    taken: defines.iter().any(|define| define == name),
+                    seen_else: false,
+                });
+            }
+            Some("#else") => {
+                if words.next().is_some() {
+                    return bad_directive("#else", "nothing after it");
+                }
+                match blocks.last_mut() {
+                    Some(block) if !block.seen_else => {
+                        block.taken = !block.taken;
+                        block.seen_else = true;
+                    }
+                    Some(_) => return err(line_num, ErrorCode::RepeatedElse),
+                    None => {
+                        return err(
+                            line_num,
+                            ErrorCode::UnmatchedDirective { directive: "#else" },
+                        )
+                    }
+                }
+            }
+            Some("#endif") => {
+                if words.next().is_some() {
+                    return bad_directive("#endif", "nothing after it");
+                }
+                if blocks.pop().is_none() {
+                    return err(
+                        line_num,
+                        ErrorCode::UnmatchedDirective {
+                            directive: "#endif",
+                        },
+                    );
+                }
+            }
+            _ => {
+                if blocks.iter().all(|block| block.taken) {
+                    buf.push_str(line);
+                }
             }
-        } else {
-            e
         }
-    })
+        buf.push('\n');
+    }
+
+    if let Some(block) = blocks.last() {
+        return err(block.line_num, ErrorCode::UnterminatedIfdef);
+    }
+
+    for (_, line) in lines {
+        buf.push_str(line);
+        buf.push('\n');
+    }
+
+    Ok(buf)
+}
+
+// Get the raw source ready for line-by-line parsing. Some Windows
+// editors start UTF-8 files with a byte order mark, which would
+// otherwise stop the chip name being recognised.
+fn prepare(data: &str, config: &Config) -> Result<String, Error> {
+    let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+    preprocess(
+        &normalise_line_endings(data),
+        &config.defines,
+        !config.parse_past_description,
+    )
+}
+
+fn parse_data(data: &str, fallback: Option<Chip>, config: &Config) -> Result<Content, Error> {
+    let data = prepare(data, config)?;
+    parse_core(config, fallback, (1..).zip(data.lines())).map_err(|e| eof_at_last_line(&data, e))
+}
+
+// Errors at the end of the file are reported against its last line.
+fn eof_at_last_line(data: &str, e: Error) -> Error {
+    if e.line == EOF_LINE {
+        Error {
+            line: data.lines().count(),
+            ..e
+        }
+    } else {
+        e
+    }
+}
+
+// For debugging the parser, show the equations' tokens, one line per
+// equation after continuation lines have been joined. Each token is
+// prefixed with the line it came from.
+pub fn dump_tokens(file_name: &str, data: &str, config: &Config) -> Result<String, Error> {
+    let data = prepare(data, config)?;
+    (|| {
+        let mut line_iter = (1..)
+            .zip(data.lines())
+            .map(|(i, x)| (i, str::trim(remove_comment(x))))
+            .peekable();
+        let fallback = fallback_chip(file_name, config);
+        let (chip, _, _) = parse_header(config, fallback, &mut line_iter, &mut Vec::new())?;
+        let found_description = Cell::new(false);
+        let mut line_iter = body_lines(config, &found_description, line_iter);
+        parse_preamble(config, chip, &mut line_iter)?;
+
+        let mut buf = String::new();
+        for tokens in tokenised_lines(config.limits, chip, line_iter) {
+            let tokens = tokens?
+                .iter()
+                .map(|(line_num, token)| format!("{}:{}", line_num, token))
+                .collect::<Vec<_>>();
+            buf.push_str(&tokens.join(" "));
+            buf.push('\n');
+        }
+        Ok(buf)
+    })()
+    .map_err(|e| eof_at_last_line(&data, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "GAL16V8\nLimits\n\
+                          I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                          I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n";
+
+    fn parse_err(source: &str) -> Error {
+        match parse_str(source, &Config::default()) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn chip_from_file_names() {
+        assert_eq!(chip_from_file_name("decoder.16v8.pld"), Some(Chip::GAL16V8));
+        assert_eq!(
+            chip_from_file_name("dir.20v8/x.GAL22V10.pld"),
+            Some(Chip::GAL22V10)
+        );
+        assert_eq!(chip_from_file_name("dir.20v8/decoder.pld"), None);
+        assert_eq!(chip_from_file_name("decoder16v8.pld"), None);
+    }
+
+    #[test]
+    fn assume_chip() {
+        let source = "Assumed\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n";
+        let config = Config::default();
+        let content = parse_data(source, Some(Chip::GAL16V8), &config).unwrap();
+        assert_eq!(content.chip, Chip::GAL16V8);
+        assert_eq!(content.sig, b"Assumed");
+        assert_eq!(content.warnings.len(), 1);
+        assert!(matches!(
+            &content.warnings[0].code,
+            WarningCode::AssumedChip { chip } if chip == "GAL16V8"
+        ));
+
+        // A valid chip line always wins, silently.
+        let with_chip = format!("GAL20V8\n{}", source);
+        let content = parse_data(&with_chip, Some(Chip::GAL16V8), &config);
+        assert!(matches!(
+            content,
+            Err(Error {
+                code: ErrorCode::BadPinCount { .. },
+                ..
+            })
+        ));
+
+        // With nothing recognisable in the file name, the chip line
+        // is required as usual.
+        let e = parse_err(source);
+        assert!(matches!(e.code, ErrorCode::BadGALType { .. }));
+        assert_eq!(e.line, 1);
+
+        // A chip given in the config beats the file name, and isn't
+        // worth a warning.
+        let config = Config {
+            assume_chip: Some(Chip::GAL16V8),
+            ..Config::default()
+        };
+        let content = parse_data(source, Some(Chip::GAL20V8), &config).unwrap();
+        assert_eq!(content.chip, Chip::GAL16V8);
+        assert!(content.warnings.is_empty());
+
+        // A chip line must agree with it.
+        let content = parse_data(&format!("GAL16V8\n{}", source), None, &config).unwrap();
+        assert_eq!(content.chip, Chip::GAL16V8);
+        let e = parse_data(&with_chip, None, &config).err().unwrap();
+        assert!(matches!(
+            e.code,
+            ErrorCode::ChipMismatch { found, expected } if found == "GAL20V8" && expected == "GAL16V8"
+        ));
+        assert_eq!(e.line, 1);
+    }
+
+    #[test]
+    fn comment_signature() {
+        let pins = "I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                    I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                    O0 = I0\n";
+        let source = format!("GAL16V8\n; No signature\n{}", pins);
+        let content = parse_str(&source, &Config::default()).unwrap();
+        assert!(content.sig.is_empty());
+        assert_eq!(content.pins[0], "I0");
+
+        let config = Config {
+            require_signature: true,
+            ..Config::default()
+        };
+        let e = parse_str(&source, &config).err().unwrap();
+        assert!(matches!(e.code, ErrorCode::BlankSignature));
+        assert_eq!(e.line, 2);
+
+        // Only the end of the file is a missing signature.
+        let e = parse_err("GAL16V8\n");
+        assert!(matches!(e.code, ErrorCode::BadSigEOF));
+        assert_eq!(e.code.to_string(), "expected signature, found end of file");
+        let e = parse_err("GAL16V8\n; No signature\n");
+        assert!(matches!(e.code, ErrorCode::BadPinEOF));
+    }
+
+    #[test]
+    fn init_annotations() {
+        let source = format!(
+            "{}O0.R = I0 ; INIT=1\n\
+             /O1.R = I1 ; Starts high. INIT=0\n\
+             O2.R = I2 ; Initially unknown\n",
+            HEADER
+        );
+        let content = parse_str(&source, &Config::default()).unwrap();
+        let inits = content
+            .inits
+            .iter()
+            .map(|init| (init.line_num, init.pin.neg, init.level))
+            .collect::<Vec<_>>();
+        assert_eq!(inits, [(5, false, true), (6, true, false)]);
+
+        let e = parse_err(&format!("{}O0 = I0 ; INIT=1\n", HEADER));
+        assert!(matches!(e.code, ErrorCode::InitNotRegistered));
+        let e = parse_err(&format!("{}O0.R = I0 ; INIT=X\n", HEADER));
+        assert!(matches!(e.code, ErrorCode::BadInit { .. }));
+        assert_eq!(e.line, 5);
+    }
+
+    #[test]
+    fn pin_numbers() {
+        let config = Config {
+            check_pin_numbers: true,
+            ..Config::default()
+        };
+        let source = "GAL16V8\nNumbered\n\
+                      ; 1  2  3  4  5  6  7  8  9  10\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      ; 11 12 13 14 15 16 17 18 19\n\
+                      \n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      ; 1 2 3\n\
+                      O0 = I0\n";
+        let content = parse_str(source, &config).unwrap();
+        assert_eq!(content.warnings.len(), 1);
+        assert_eq!(content.warnings[0].line, 7);
+        assert!(matches!(
+            content.warnings[0].code,
+            WarningCode::PinNumberMismatch {
+                numbers: 9,
+                pins: 10
+            }
+        ));
+
+        // Comments that aren't just numbers are left alone.
+        let source = source.replace("; 11 12 13", "; pins 11 12 13");
+        assert!(parse_str(&source, &config).unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn max_line() {
+        let config = Config {
+            max_line: Some(30),
+            ..Config::default()
+        };
+        let long = format!("{}O0 = I0 * I1 + I2 * I3 + I4 * I5 ; comment\n", HEADER);
+        let content = parse_str(&long, &config).unwrap();
+        assert_eq!(content.warnings.len(), 1);
+        assert_eq!(content.warnings[0].line, 5);
+        assert!(matches!(
+            content.warnings[0].code,
+            WarningCode::LongLine { len: 32, max: 30 }
+        ));
+
+        let continued = format!("{}O0 = I0 * I1 + I2 * I3 +\n     I4 * I5\n", HEADER);
+        let content = parse_str(&continued, &config).unwrap();
+        assert!(content.warnings.is_empty());
+
+        // Off by default.
+        let content = parse_str(&long, &Config::default()).unwrap();
+        assert!(content.warnings.is_empty());
+    }
+
+    fn parse_with(source: &str, defines: &[&str]) -> Result<Content, Error> {
+        let config = Config {
+            defines: defines.iter().map(|s| s.to_string()).collect(),
+            ..Config::default()
+        };
+        parse_str(source, &config)
+    }
+
+    #[test]
+    fn ifdef() {
+        let source = format!(
+            "{}#ifdef DEBUG\n\
+             O0 = I0\n\
+             #ifdef FAST ; Nested\n\
+             O1 = I1\n\
+             #else\n\
+             O1 = I1 * I2\n\
+             #endif\n\
+             #else\n\
+             O0 = /I0\n\
+             #endif\n\
+             O2 = I3\n",
+            HEADER
+        );
+
+        // Line numbers are unaffected by the removed lines.
+        let content = parse_with(&source, &["DEBUG"]).unwrap();
+        let lines = content.eqns.iter().map(|e| e.line_num).collect::<Vec<_>>();
+        assert_eq!(lines, [6, 10, 15]);
+
+        let content = parse_with(&source, &["DEBUG", "FAST"]).unwrap();
+        let lines = content.eqns.iter().map(|e| e.line_num).collect::<Vec<_>>();
+        assert_eq!(lines, [6, 8, 15]);
+
+        let content = parse_with(&source, &[]).unwrap();
+        let lines = content.eqns.iter().map(|e| e.line_num).collect::<Vec<_>>();
+        assert_eq!(lines, [13, 15]);
+        assert!(content.eqns[0].rhs[0].neg);
+    }
+
+    #[test]
+    fn ifdef_errors() {
+        let check = |body: &str, line: LineNum| {
+            let e = match parse_with(&format!("{}{}", HEADER, body), &[]) {
+                Ok(_) => panic!("expected an error for {:?}", body),
+                Err(e) => e,
+            };
+            assert_eq!(e.line, line, "{:?}", body);
+            e.code
+        };
+
+        let code = check("O0 = I0\n#endif\n", 6);
+        assert!(matches!(
+            code,
+            ErrorCode::UnmatchedDirective {
+                directive: "#endif"
+            }
+        ));
+        let code = check("#else\n", 5);
+        assert!(matches!(
+            code,
+            ErrorCode::UnmatchedDirective { directive: "#else" }
+        ));
+        let code = check("#ifdef A\n#ifdef B\n#endif\nO0 = I0\n", 5);
+        assert!(matches!(code, ErrorCode::UnterminatedIfdef));
+        let code = check("#ifdef A\n#else\n#else\n#endif\n", 7);
+        assert!(matches!(code, ErrorCode::RepeatedElse));
+        let code = check("#ifdef\n#endif\n", 5);
+        assert!(matches!(code, ErrorCode::BadDirective { .. }));
+
+        // Directives in the description are just text.
+        parse_with(&format!("{}DESCRIPTION\n#endif\n", HEADER), &[]).unwrap();
+    }
+
+    #[test]
+    fn parse_past_description() {
+        let source = format!(
+            "{}O0 = I0\n\
+             DESCRIPTION\n\
+             #ifdef LATE\n\
+             O1 = I1\n\
+             #endif\n",
+            HEADER
+        );
+        let content = parse_with(&source, &["LATE"]).unwrap();
+        assert_eq!(content.eqns.len(), 1);
+
+        let config = Config {
+            parse_past_description: true,
+            require_description: true,
+            defines: vec![String::from("LATE")],
+            ..Config::default()
+        };
+        let content = parse_str(&source, &config).unwrap();
+        assert_eq!(content.eqns.len(), 2);
+        assert_eq!(content.eqns[1].line_num, 8);
+        assert_eq!(
+            dump_tokens("test.pld", &source, &config).unwrap(),
+            "5:O0 5:= 5:I0\n\
+             8:O1 8:= 8:I1\n"
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let source = format!(
+            "{}O0 = I0 * /I1 + ; Comment\n\
+             \n\
+             I2\n\
+             O1.T := I3\n\
+             DESCRIPTION\n\
+             Ignored\n",
+            HEADER
+        );
+        assert_eq!(
+            dump_tokens("test.pld", &source, &Config::default()).unwrap(),
+            "5:O0 5:= 5:I0 5:* 5:/I1 5:+ 7:I2\n\
+             8:O1.T 8::= 8:I3\n"
+        );
+
+        let e = dump_tokens(
+            "test.pld",
+            &format!("{}O0 = I0 +\n", HEADER),
+            &Config::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(e.code, ErrorCode::ContinuationEOF));
+        assert_eq!(e.line, 5);
+
+        // The chip is found the same way as for assembly.
+        let chipless = format!("{}O0 = I0\n", HEADER.strip_prefix("GAL16V8\n").unwrap());
+        let config = Config {
+            assume_chip_from_filename: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            dump_tokens("test.16v8.pld", &chipless, &config).unwrap(),
+            "4:O0 4:= 4:I0\n"
+        );
+        let config = Config {
+            assume_chip: Some(Chip::GAL16V8),
+            ..Config::default()
+        };
+        assert_eq!(
+            dump_tokens("test.pld", &chipless, &config).unwrap(),
+            "4:O0 4:= 4:I0\n"
+        );
+        let config = Config {
+            assume_chip: Some(Chip::GAL20V8),
+            ..Config::default()
+        };
+        let e = dump_tokens("test.pld", &source, &config).unwrap_err();
+        assert!(matches!(e.code, ErrorCode::ChipMismatch { .. }));
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let source = format!("\u{feff}{}O0 = I0\n", HEADER);
+        let content = parse_str(&source, &Config::default()).unwrap();
+        assert_eq!(content.chip, Chip::GAL16V8);
+        assert_eq!(content.eqns.len(), 1);
+        assert!(crate::compile(&source).is_ok());
+
+        // Only at the very start.
+        let e = parse_err(&HEADER.replace("I0 I1", "\u{feff}I0 I1"));
+        assert!(matches!(e.code, ErrorCode::BadChar { .. }));
+    }
+
+    #[test]
+    fn line_endings() {
+        let source = "GAL16V8\nEndings\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n\
+                      O1 = Q\n";
+        for ending in ["\r", "\r\n"].iter() {
+            let e = parse_err(&source.replace('\n', ending));
+            assert!(matches!(e.code, ErrorCode::UnknownPin { .. }));
+            assert_eq!(e.line, 6);
+        }
+
+        // Errors found at the end of the file report the last line.
+        let e = parse_err("GAL16V8\rTruncated\r");
+        assert!(matches!(e.code, ErrorCode::BadPinEOF));
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn long_name_limit() {
+        let source = format!("{}O0 = {}\n", HEADER, "I".repeat(1_000_000));
+        let e = parse_err(&source);
+        assert_eq!(e.line, 5);
+        assert!(matches!(e.code, ErrorCode::NameTooLong { max: 256 }));
+    }
+
+    #[test]
+    fn long_equation_limit() {
+        let source = format!("{}O0 = I0{}\n", HEADER, " +\nI1".repeat(10_000));
+        let e = parse_err(&source);
+        assert_eq!(e.line, 5 + 1024 + 1);
+        assert!(matches!(
+            e.code,
+            ErrorCode::TooManyContinuationLines { max: 1024 }
+        ));
+
+        let source = format!("{}O0 = I0{}\n", HEADER, " + I1".repeat(10_000));
+        let e = parse_err(&source);
+        assert_eq!(e.line, 5);
+        assert!(matches!(e.code, ErrorCode::TooManyTerms { max: 4096 }));
+    }
+
+    #[test]
+    fn bad_suffix_lists_valid() {
+        let e = parse_err(&format!("{}O0.CLK = I0\nO1.UNK = I1\n", HEADER));
+        assert_eq!(
+            e.code.to_string(),
+            "unknown suffix found: 'UNK'; the valid suffixes are .T, .R, .E and .POL"
+        );
+
+        let source = "GAL20RA10\nSuffixes\n\
+                      /PL Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      /OE O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.UNK = I0\n";
+        assert_eq!(
+            parse_err(source).code.to_string(),
+            "unknown suffix found: 'UNK'; the valid suffixes are .T, .R, .E, .CLK, .ARST, .APRST and .POL"
+        );
+
+        let source = "GAL22V10\nSuffixes\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.UNK = I0\n";
+        assert_eq!(
+            parse_err(source).code.to_string(),
+            "unknown suffix found: 'UNK'; the valid suffixes are .T, .R, .E, .CLK and .POL"
+        );
+    }
+
+    #[test]
+    fn set_reset_synonyms() {
+        let header = "GAL20RA10\nSetReset\n\
+                      /PL Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      /OE O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0\nO0.CLK = Clock\nO1.R = I1\nO1.CLK = Clock\n";
+        let standard = format!("{}O0.APRST = I2\nO1.ARST = I3 * I4\n", header);
+        let synonyms = format!("{}O0.SET = I2\nO1.RESET = I3 * I4\n", header);
+        let standard = crate::compile(&standard).unwrap().gal;
+        let synonyms = crate::compile(&synonyms).unwrap().gal;
+        assert_eq!(standard.fuses, synonyms.fuses);
+
+        // The same checks apply.
+        let e = crate::compile(&format!("{}/O0.SET = I2\n", header))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e.code,
+            ErrorCode::InvertedControl {
+                suffix: crate::errors::OutputSuffix::APRST
+            }
+        ));
+    }
+
+    #[test]
+    fn output_lists() {
+        let separate = format!(
+            "{}O0 = I0 * I1\nO1 = I0 * I1\n/O2 = I0 * I1\nO3.T = I2\nO3.E = I3\nO4.T = I2\nO4.E = I3\n",
+            HEADER
+        );
+        let listed = format!(
+            "{}O0, O1,/O2 = I0 * I1\nO3.T, O4.T = I2\nO3.E , O4.E = I3\n",
+            HEADER
+        );
+        let separate = crate::compile(&separate).unwrap().gal;
+        let listed = crate::compile(&listed).unwrap().gal;
+        assert_eq!(separate.fuses, listed.fuses);
+        assert_eq!(separate.xor, listed.xor);
+        assert_eq!(separate.ac1, listed.ac1);
+
+        // Every output in the list is checked.
+        let e = crate::compile(&format!("{}O0, I1 = I2\n", HEADER))
+            .err()
+            .unwrap();
+        assert!(matches!(e.code, ErrorCode::NotAnOutput));
+        let e = crate::compile(&format!("{}O0, O0 = I2\n", HEADER))
+            .err()
+            .unwrap();
+        assert!(matches!(e.code, ErrorCode::RepeatedOutput { .. }));
+        assert!(matches!(
+            parse_err(&format!("{}O0.T, O1 = I2\n", HEADER)).code,
+            ErrorCode::MixedOutputList
+        ));
+        assert!(matches!(
+            parse_err(&format!("{}O0, = I2\n", HEADER)).code,
+            ErrorCode::BadToken { .. }
+        ));
+    }
+
+    #[test]
+    fn d_synonym() {
+        let header = "GAL16V8\nRegister\n\
+                      Clock I0 I1 NC NC NC NC NC NC GND\n\
+                      /OE NC O0 O1 NC NC NC NC NC VCC\n";
+        let standard = format!("{}O0.R = I0 * I1\n/O1.R = O0\n", header);
+        let synonym = format!("{}O0.D = I0 * I1\n/O1.D = O0\n", header);
+        let standard = crate::compile(&standard).unwrap().gal;
+        let synonym = crate::compile(&synonym).unwrap().gal;
+        assert_eq!(standard.fuses, synonym.fuses);
+        assert_eq!(standard.xor, synonym.xor);
+        assert_eq!(standard.ac1, synonym.ac1);
+
+        // The same checks apply, e.g. pin 1 is needed as the clock.
+        let standard = crate::compile(&format!("{}O0.R = I0\n", HEADER))
+            .err()
+            .unwrap();
+        let synonym = crate::compile(&format!("{}O0.D = I0\n", HEADER))
+            .err()
+            .unwrap();
+        assert_eq!(standard.code.to_string(), synonym.code.to_string());
+        assert_eq!(standard.line, synonym.line);
+    }
+
+    #[test]
+    fn postfix_negation() {
+        let prefix = format!("{}/O0 = I0 * I1\n/O1.T = I2\n", HEADER);
+        let postfix = format!("{}O0/ = I0 * I1\nO1/.T = I2\n", HEADER);
+        let prefix = crate::compile(&prefix).unwrap();
+        let postfix = crate::compile(&postfix).unwrap();
+        assert_eq!(prefix.gal.xor, postfix.gal.xor);
+        assert_eq!(prefix.gal.fuses, postfix.gal.fuses);
+        assert_eq!(
+            prefix.blueprint.olmcs[6].active,
+            postfix.blueprint.olmcs[6].active
+        );
+
+        let e = parse_err(&format!("{}/O0/ = I0\n", HEADER));
+        assert!(matches!(e.code, ErrorCode::InvertedTwice { name } if name == "O0"));
+        let e = parse_err(&format!("{}O0 = I0 * I1/\n", HEADER));
+        assert!(matches!(e.code, ErrorCode::PostfixNegation));
+        assert_eq!(e.line, 5);
+        let e = parse_err(&HEADER.replace("I9 O0", "I9/ O0"));
+        assert!(matches!(e.code, ErrorCode::PostfixNegation));
+    }
+
+    #[test]
+    fn paralleled_inputs() {
+        let source = "GAL16V8\nGroups\n\
+                      @DATA @DATA I0 NC NC NC NC NC NC GND\n\
+                      NC NC O0 O1 NC NC NC NC NC VCC\n\
+                      O0 = DATA * I0\n\
+                      O1 = I0 + /DATA\n";
+        let content = parse_str(source, &Config::default()).unwrap();
+        let pins = |eqn: &Equation| eqn.rhs.iter().map(|p| (p.pin, p.neg)).collect::<Vec<_>>();
+        assert_eq!(pins(&content.eqns[0]), [(1, false), (2, false), (3, false)]);
+        assert_eq!(content.eqns[0].is_or, [false, false, false]);
+        assert_eq!(pins(&content.eqns[1]), [(3, false), (1, true), (2, true)]);
+        assert_eq!(content.eqns[1].is_or, [false, true, false]);
+        assert_eq!(content.pins[..2], ["DATA", "DATA"]);
+        crate::compile(source).unwrap();
+
+        let e = parse_err(&source.replace("O0 = DATA * I0", "DATA = I0"));
+        assert!(matches!(e.code, ErrorCode::GroupAsOutput { name } if name == "DATA"));
+        assert_eq!(e.line, 5);
+        let e = parse_err(&source.replace("@DATA @DATA", "@DATA @/DATA"));
+        assert!(matches!(e.code, ErrorCode::InconsistentGroup { name } if name == "DATA"));
+        let e = parse_err(&source.replace("@DATA @DATA", "DATA @DATA"));
+        assert!(matches!(e.code, ErrorCode::RepeatedPinName { name } if name == "DATA"));
+    }
+
+    #[test]
+    fn options_lines() {
+        let source = HEADER.replace(
+            "Limits\n",
+            "Limits\nOPTIONS secure ; comment\n\nOPTIONS nochip nopin\n",
+        );
+        let content = parse_str(&source, &Config::default()).unwrap();
+        assert_eq!(
+            content.options,
+            Options {
+                secure: true,
+                nochip: true,
+                nofuse: false,
+                nopin: true,
+            }
+        );
+        assert_eq!(content.pin_lines, [6, 7]);
+
+        let mut config = writer::Config::default();
+        content.options.apply(&mut config);
+        assert!(config.jedec_sec_bit && config.gen_fuse);
+        assert!(!config.gen_chip && !config.gen_pin);
+
+        let config = Config {
+            ignore_options: true,
+            ..Config::default()
+        };
+        let content = parse_str(&source, &config).unwrap();
+        assert_eq!(content.options, Options::default());
+
+        let e = parse_err(&HEADER.replace("Limits\n", "Limits\nOPTIONS secure bogus\n"));
+        assert!(matches!(e.code, ErrorCode::UnknownOption { name } if name == "bogus"));
+        assert_eq!(e.line, 3);
+    }
+
+    #[test]
+    fn second_pin_names() {
+        let source = "GAL16V8\nBidir\n\
+                      I0 I1 I2 NC NC NC NC NC NC GND\n\
+                      NC NC NC DQOUT,DQIN O0 NC NC NC NC VCC\n\
+                      DQOUT.T = I0\n\
+                      DQOUT.E = I1\n\
+                      O0 = DQIN * I2\n";
+        let content = parse_str(source, &Config::default()).unwrap();
+        assert_eq!(content.pins[13..15], ["DQOUT", "O0"]);
+        assert_eq!(content.eqns[2].rhs[0].pin, 14);
+
+        // Either name can be used on either side.
+        let one_name = source
+            .replace("DQOUT,DQIN", "DQOUT")
+            .replace("DQIN", "DQOUT");
+        let swapped = source
+            .replace("DQOUT,DQIN", "DQOUT , DQIN")
+            .replace("O0 = DQIN", "O0 = DQOUT")
+            .replace("DQOUT.T", "DQIN.T");
+        let gal = crate::compile(source).unwrap().gal;
+        for source in [one_name, swapped] {
+            assert_eq!(crate::compile(&source).unwrap().gal.fuses, gal.fuses);
+        }
+
+        let e = parse_err(&source.replace("I0 I1", "I0,IN0 I1"));
+        assert!(matches!(e.code, ErrorCode::SecondNameNotOutput { pin: 1 }));
+        assert_eq!(e.line, 3);
+        let e = parse_err(&source.replace("DQOUT,DQIN O0", "DQOUT,DQIN NC,O0"));
+        assert!(matches!(e.code, ErrorCode::SecondNameNC { pin: 15 }));
+        let e = parse_err(&source.replace("DQOUT,DQIN", "DQOUT,I0"));
+        assert!(matches!(e.code, ErrorCode::RepeatedPinName { name } if name == "I0"));
+        let e = parse_err(&source.replace("DQOUT,DQIN", "DQOUT,DQIN,DQ"));
+        assert!(matches!(e.code, ErrorCode::BadToken { .. }));
+        let e = parse_err(&source.replace("NC VCC", "NC VCC,"));
+        assert!(matches!(e.code, ErrorCode::BadToken { .. }));
+    }
+
+    #[test]
+    fn registered_assignment() {
+        let header = "GAL22V10\nRegEq\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC NC NC VCC\n";
+        let suffixed = format!("{}O0.R = I0 * I1\n/O1.R = I2\n", header);
+        let assigned = format!("{}O0 := I0 * I1\n/O1\n:= I2\n", header);
+        let suffixed = crate::compile(&suffixed).unwrap().gal;
+        let assigned = crate::compile(&assigned).unwrap().gal;
+        assert_eq!(suffixed.fuses, assigned.fuses);
+        assert_eq!(suffixed.ac1, assigned.ac1);
+        assert_eq!(suffixed.xor, assigned.xor);
+
+        let e = parse_err(&format!("{}O0 : I0\n", header));
+        assert!(matches!(e.code, ErrorCode::BadChar { c: ':' }));
+    }
+
+    #[test]
+    fn configured_limits() {
+        let config = Config {
+            limits: Limits {
+                max_name_len: 3,
+                max_terms: 10,
+                max_continuation_lines: 1,
+            },
+            ..Config::default()
+        };
+        let source = format!(
+            "{}O0 = I0 + I1 + I2 + I3 +\nI4 * I5 * I6 * I7 * I8 * I9\n",
+            HEADER
+        );
+        let e = parse_str(&source, &config).err().unwrap();
+        assert_eq!(e.line, 6);
+        assert!(matches!(e.code, ErrorCode::TooManyTerms { max: 10 }));
+    }
 }
@@ -0,0 +1,277 @@
+//
+// reader.rs: JEDEC reading
+//
+// Reads an assembled JEDEC file back into a GAL, so that a chip we
+// only have the fuses for can be described. The chip is identified
+// from the fuse count, and the fuses are unpacked in the order that
+// 'writer::make_jedec' packs them.
+//
+
+use crate::{
+    blueprint::{Active, Blueprint, PinMode, OLMC},
+    chips::{Chip, CHIPS},
+    errors::{Error, ErrorCode, LineNum},
+    gal::{Mode, Term, GAL},
+};
+
+fn err<T>(line: LineNum, code: ErrorCode) -> Result<T, Error> {
+    Err(Error { code, line })
+}
+
+pub fn read_jedec(data: &str) -> Result<GAL, Error> {
+    // The fuse data lies between STX and ETX, if they're present.
+    let start = data.find('\x02').map_or(0, |i| i + 1);
+    let end = data[start..].find('\x03').map_or(data.len(), |i| start + i);
+
+    // Fields start with '*', after a free-form header. We keep the
+    // line each starts on, for errors.
+    let mut line_num = 1 + data[..start].matches('\n').count();
+    let mut fields = Vec::new();
+    for (i, field) in data[start..end].split('*').enumerate() {
+        if i > 0 {
+            fields.push((line_num, field.trim()));
+        }
+        line_num += field.matches('\n').count();
+    }
+
+    let mut size = None;
+    let mut default = false;
+    let mut lists = Vec::new();
+    for (line, field) in fields.into_iter() {
+        if let Some(count) = field.strip_prefix("QF") {
+            match count.parse::<usize>() {
+                Ok(count) => size = Some((line, count)),
+                Err(_) => return err(line, ErrorCode::BadJedecField { field: "QF" }),
+            }
+        } else if let Some(value) = field.strip_prefix('F') {
+            default = match value {
+                "0" => false,
+                "1" => true,
+                _ => return err(line, ErrorCode::BadJedecField { field: "F" }),
+            };
+        } else if let Some(list) = field.strip_prefix('L') {
+            lists.push((line, list));
+        }
+    }
+
+    let (size_line, size) = match size {
+        Some(size) => size,
+        None => return err(line_num, ErrorCode::NoJedecFuseCount),
+    };
+    let chip = match CHIPS.iter().copied().find(|chip| chip.total_size() == size) {
+        Some(chip) => chip,
+        None => return err(size_line, ErrorCode::UnknownJedecSize { size }),
+    };
+
+    let mut bits = vec![default; size];
+    for (line, list) in lists.into_iter() {
+        let mut words = list.split_whitespace();
+        let address = match words.next().map(str::parse::<usize>) {
+            Some(Ok(address)) => address,
+            _ => return err(line, ErrorCode::BadJedecField { field: "L" }),
+        };
+        for (c, i) in words.flat_map(str::chars).zip(address..) {
+            if i >= size {
+                return err(line, ErrorCode::JedecFusesOutOfRange { address, size });
+            }
+            bits[i] = match c {
+                '0' => false,
+                '1' => true,
+                _ => return err(line, ErrorCode::BadJedecField { field: "L" }),
+            };
+        }
+    }
+
+    let mut gal = GAL::new(chip);
+    let mut bits = bits.into_iter();
+    let mut fill = |dest: &mut [bool]| {
+        for bit in dest.iter_mut() {
+            *bit = bits.next().unwrap();
+        }
+    };
+    fill(&mut gal.fuses);
+    if chip == Chip::GAL22V10 {
+        // XOR bits are interleaved with S1 bits.
+        for i in 0..chip.num_olmcs() {
+            fill(&mut gal.xor[i..=i]);
+            fill(&mut gal.ac1[i..=i]);
+        }
+    } else {
+        fill(&mut gal.xor);
+    }
+    fill(&mut gal.sig);
+    if chip == Chip::GAL16V8 || chip == Chip::GAL20V8 {
+        fill(&mut gal.ac1);
+        fill(&mut gal.pt);
+        fill(std::slice::from_mut(&mut gal.syn));
+        fill(std::slice::from_mut(&mut gal.ac0));
+        // The fourth combination isn't a mode, and the fuses that set
+        // it aren't tied to any one field.
+        if !gal.syn && !gal.ac0 {
+            return err(
+                0,
+                ErrorCode::BadJedecMode {
+                    chip: chip.name().to_string(),
+                },
+            );
+        }
+    }
+
+    Ok(gal)
+}
+
+// Rebuild the OLMCs from the fuses, as far as the pin types need:
+// which outputs are driven, in what mode, and which pins are read.
+// A driven output's term holds its products, and control terms
+// aren't decoded.
+pub fn decode_olmcs(gal: &GAL) -> Vec<OLMC> {
+    let chip = gal.chip;
+    let num_olmcs = chip.num_olmcs();
+    let matrix = gal.decoded_matrix();
+    let is_read = |pin: usize| {
+        matrix
+            .iter()
+            .flat_map(|row| row.inputs.iter().flatten())
+            .any(|input| input.pin == pin)
+    };
+    let all_set = |row: usize| {
+        let num_cols = chip.num_cols();
+        gal.fuses[row * num_cols..(row + 1) * num_cols]
+            .iter()
+            .all(|fuse| *fuse)
+    };
+
+    let mut olmcs = Blueprint::new(chip).olmcs;
+    for pin in 1..=chip.num_pins() {
+        let i = match chip.pin_to_olmc(pin) {
+            Some(i) => i,
+            None => continue,
+        };
+        let idx = num_olmcs - 1 - i;
+        // An OLMC that doesn't drive its pin has its rows and XOR bit
+        // left clear. (An active-low output that's always high, with
+        // no products, looks the same.) Otherwise, the first row is
+        // the output enable, except for registered outputs and simple
+        // mode on the GALxxV8s.
+        let bounds = chip.get_bounds(i);
+        let enable_row = bounds.start_row;
+        let unused = !gal.xor[idx]
+            && (enable_row..enable_row + bounds.max_row).all(|row| gal.is_default_row(row));
+        let enabled = || {
+            if gal.is_default_row(enable_row) {
+                None
+            } else if all_set(enable_row) {
+                Some(PinMode::Combinatorial)
+            } else {
+                Some(PinMode::Tristate)
+            }
+        };
+        let mode = match chip {
+            _ if unused => None,
+            Chip::GAL16V8 | Chip::GAL20V8 => match gal.get_mode() {
+                Mode::Simple if gal.ac1[idx] => None,
+                Mode::Simple => Some(PinMode::Combinatorial),
+                Mode::Registered if !gal.ac1[idx] => Some(PinMode::Registered),
+                _ => enabled(),
+            },
+            Chip::GAL22V10 if !gal.ac1[idx] => enabled().map(|_| PinMode::Registered),
+            // Only registered outputs have a clock term.
+            Chip::GAL20RA10 if !gal.is_default_row(enable_row + 1) => {
+                enabled().map(|_| PinMode::Registered)
+            }
+            Chip::GAL22V10 | Chip::GAL20RA10 => enabled(),
+        };
+
+        let olmc = &mut olmcs[i];
+        olmc.output = mode.map(|mode| {
+            let term = Term {
+                line_num: 0,
+                pins: gal.product_terms(pin),
+            };
+            (mode, term)
+        });
+        olmc.active = if gal.xor[idx] {
+            Active::High
+        } else {
+            Active::Low
+        };
+        olmc.feedback = is_read(pin);
+    }
+    olmcs
+}
+
+// Names for the pins when the originals aren't known: the power pins
+// get their usual names, and the rest are numbered.
+pub fn numbered_pin_names(chip: Chip) -> Vec<String> {
+    let num_pins = chip.num_pins();
+    (1..=num_pins)
+        .map(|pin| match pin {
+            _ if pin == num_pins => String::from("VCC"),
+            _ if pin == num_pins / 2 => String::from("GND"),
+            _ => format!("P{}", pin),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer;
+
+    #[test]
+    fn jedec_round_trip() {
+        let sources = [
+            "GAL16V8\nReader\n\
+             Clock I0 I1 NC NC NC NC NC NC GND\n\
+             /OE NC NC NC NC NC NC Q0 O0 VCC\n\
+             O0 = I0 * /I1\nQ0.R = I1\n",
+            "GAL22V10\nReader\n\
+             Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+             NC NC NC NC NC NC NC NC NC Q0 O0 VCC\n\
+             O0 = I0 * /I1\nQ0.R = I1\n",
+        ];
+        for source in sources.iter() {
+            let gal = crate::compile(source).unwrap().gal;
+            let jedec = writer::make_jedec(&writer::Config::default(), &gal);
+            let read = read_jedec(&jedec).unwrap();
+            assert_eq!(read.chip, gal.chip);
+            assert_eq!(read.fuses, gal.fuses);
+            assert_eq!(read.xor, gal.xor);
+            assert_eq!(read.sig, gal.sig);
+            assert_eq!(read.ac1, gal.ac1);
+            assert_eq!(read.pt, gal.pt);
+            assert_eq!((read.syn, read.ac0), (gal.syn, gal.ac0));
+        }
+    }
+
+    #[test]
+    fn bad_jedec() {
+        let err = read_jedec("\x02\n*QF1234\n*\x03").err().unwrap();
+        assert!(matches!(
+            err.code,
+            ErrorCode::UnknownJedecSize { size: 1234 }
+        ));
+        assert_eq!(err.line, 2);
+        let err = read_jedec("\x02\n*F0\n*\x03").err().unwrap();
+        assert!(matches!(err.code, ErrorCode::NoJedecFuseCount));
+        let err = read_jedec("\x02\n*QF2194\n*L2190 011111\n*\x03")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.code,
+            ErrorCode::JedecFusesOutOfRange { address: 2190, .. }
+        ));
+        assert_eq!(err.line, 3);
+
+        // An ETX before the STX doesn't end the fuse data.
+        let err = read_jedec("x\x03y\x02*QF1234*").err().unwrap();
+        assert!(matches!(
+            err.code,
+            ErrorCode::UnknownJedecSize { size: 1234 }
+        ));
+        assert_eq!(err.line, 1);
+        // With every fuse clear, SYN and AC0 select no mode.
+        let err = read_jedec("\x02\n*QF2194*F0\n*\x03").err().unwrap();
+        assert!(matches!(err.code, ErrorCode::BadJedecMode { chip } if chip == "GAL16V8"));
+    }
+}
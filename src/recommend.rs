@@ -0,0 +1,202 @@
+//
+// recommend.rs: Chip selection
+//
+// Works out what a design needs from its chip - registers, tristate
+// outputs, asynchronous reset, pins and product terms - and picks the
+// smallest supported chip that provides it. Outputs are assumed to be
+// free to move between OLMCs, so the biggest terms can go in the
+// biggest OLMCs.
+//
+
+use std::fmt::Write;
+
+use crate::{
+    blueprint::{Blueprint, PinMode},
+    chips::{Chip, CHIPS},
+    gal::Pin,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Requirements {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub registered: bool,
+    pub tristate: bool,
+    // The GAL22V10's AR and SP terms.
+    pub async_reset: bool,
+    // The GAL20RA10's .CLK, .ARST and .APRST.
+    pub per_output_controls: bool,
+    // Products in each output's main term, largest first, and
+    // whether that output is registered.
+    pub products: Vec<(usize, bool)>,
+}
+
+impl Requirements {
+    pub fn from(blueprint: &Blueprint) -> Self {
+        let chip = blueprint.chip;
+        let mut req = Requirements {
+            async_reset: blueprint.ar.is_some() || blueprint.sp.is_some(),
+            ..Requirements::default()
+        };
+
+        for olmc in blueprint.olmcs.iter() {
+            let (mode, term) = match &olmc.output {
+                Some(output) => output,
+                None => continue,
+            };
+            req.outputs += 1;
+            let registered = *mode == PinMode::Registered;
+            req.registered |= registered;
+            req.tristate |= *mode == PinMode::Tristate;
+            req.products.push((term.pins.len(), registered));
+
+            // A registered output may name pin 1 as its clock, which
+            // every chip provides.
+            let pin1 = [vec![Pin { pin: 1, neg: false }]];
+            let own_clock = matches!(&olmc.clock, Some(term) if !(registered && term.pins == pin1));
+            req.per_output_controls |= own_clock || olmc.arst.is_some() || olmc.aprst.is_some();
+        }
+        req.products.sort_unstable_by(|a, b| b.cmp(a));
+
+        // Every other named pin is an input.
+        let output_pins = (1..=chip.num_pins())
+            .filter(|pin| matches!(chip.pin_to_olmc(*pin), Some(i) if blueprint.olmcs[i].output.is_some()))
+            .count();
+        req.inputs = blueprint
+            .pins
+            .iter()
+            .map(|name| name.trim_start_matches('/'))
+            .filter(|name| !matches!(*name, "NC" | "GND" | "VCC"))
+            .count()
+            - output_pins;
+
+        req
+    }
+
+    // Whether the chip can hold the design, in outline.
+    pub fn fits(&self, chip: Chip) -> bool {
+        let is_v8 = chip == Chip::GAL16V8 || chip == Chip::GAL20V8;
+
+        if self.async_reset && !matches!(chip, Chip::GAL22V10 | Chip::GAL20RA10) {
+            return false;
+        }
+        if self.per_output_controls && chip != Chip::GAL20RA10 {
+            return false;
+        }
+
+        // The GALxxV8s' registered mode takes a pin for the output
+        // enable.
+        let oe_pin = (is_v8 && self.registered) as usize;
+        if self.outputs > chip.num_olmcs()
+            || self.inputs + self.outputs + oe_pin > chip.num_pins() - 2
+        {
+            return false;
+        }
+
+        // Rows left for each OLMC's main term, after the rows for
+        // control terms.
+        let reserved = match chip {
+            Chip::GAL16V8 | Chip::GAL20V8 => 0,
+            Chip::GAL22V10 => 1,
+            Chip::GAL20RA10 => 4,
+        };
+        let mut budgets = (0..chip.num_olmcs())
+            .map(|i| chip.num_rows_for_olmc(i) - reserved)
+            .collect::<Vec<_>>();
+        budgets.sort_unstable_by(|a, b| b.cmp(a));
+
+        // Outside simple mode, the GALxxV8s' non-registered outputs
+        // lose a row to the tristate enable.
+        let simple = !self.registered && !self.tristate;
+        self.products
+            .iter()
+            .zip(budgets)
+            .all(|((products, registered), budget)| {
+                let enable = (is_v8 && !simple && !registered) as usize;
+                products + enable <= budget
+            })
+    }
+
+    // The smallest supported chip the design fits.
+    pub fn recommend(&self) -> Option<Chip> {
+        CHIPS.iter().copied().find(|chip| self.fits(*chip))
+    }
+}
+
+// Describe the design's requirements and the recommended chip.
+pub fn make_report(blueprint: &Blueprint) -> String {
+    let req = Requirements::from(blueprint);
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+    let mut buf = String::new();
+    let _ = writeln!(buf, "Inputs: {}", req.inputs);
+    let _ = writeln!(buf, "Outputs: {}", req.outputs);
+    let _ = writeln!(buf, "Registers: {}", yes_no(req.registered));
+    let _ = writeln!(buf, "Tristate: {}", yes_no(req.tristate));
+    let _ = writeln!(buf, "Async reset/preset: {}", yes_no(req.async_reset));
+    let _ = writeln!(
+        buf,
+        "Per-output controls: {}",
+        yes_no(req.per_output_controls)
+    );
+    let most = req.products.first().map_or(0, |(products, _)| *products);
+    let _ = writeln!(buf, "Most products per output: {}", most);
+    let _ = writeln!(
+        buf,
+        "Smallest suitable chip: {}",
+        req.recommend()
+            .map_or(String::from("none"), |chip| chip.name().to_string())
+    );
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirements(source: &str) -> Requirements {
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        Requirements::from(&Blueprint::from(&content).unwrap())
+    }
+
+    #[test]
+    fn small_design_fits_16v8() {
+        let req = requirements(
+            "GAL22V10\nSmall\n\
+             Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+             NC O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+             O0.R = I0 * I1\n\
+             O1 = I0 + I1\n",
+        );
+        assert_eq!(req.inputs, 3);
+        assert_eq!(req.outputs, 2);
+        assert!(req.registered);
+        assert_eq!(req.recommend(), Some(Chip::GAL16V8));
+    }
+
+    #[test]
+    fn many_registered_terms_need_22v10() {
+        let req = requirements(
+            "GAL22V10\nBig\n\
+             Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+             NC O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+             O0.R = I0 + I1 + I2 + I3 + I4 + I5 + I6 + I7 + I8 + I9\n\
+             O1.R = I0 * I1\n",
+        );
+        assert_eq!(req.products, [(10, true), (1, true)]);
+        assert!(!req.fits(Chip::GAL16V8));
+        assert!(!req.fits(Chip::GAL20V8));
+        assert_eq!(req.recommend(), Some(Chip::GAL22V10));
+
+        // AR needs a GAL22V10 even for a small design.
+        let req = requirements(
+            "GAL22V10\nReset\n\
+             Clock I0 NC NC NC NC NC NC NC NC NC GND\n\
+             NC O0 NC NC NC NC NC NC NC NC NC VCC\n\
+             O0.R = I0\n\
+             AR = I0\n",
+        );
+        assert!(req.async_reset);
+        assert_eq!(req.recommend(), Some(Chip::GAL22V10));
+    }
+}
@@ -0,0 +1,241 @@
+//
+// repl.rs: Interactive equation explorer
+//
+// Lets the user type equations one at a time against a chip, and
+// shows where each lands. Rather than keeping state of its own, the
+// REPL rebuilds a complete source file from the accepted lines and
+// runs it through the normal pipeline after every addition, so it
+// behaves exactly like the assembler.
+//
+
+use std::io::{self, BufRead, Write};
+
+use crate::{chips::Chip, writer};
+
+const HELP: &str = "\
+Enter equations such as 'P12 = P2 * /P3', or one of:
+  :pins NAME...  rename all the pins, in pin order
+  :fuses         show the current fuse map
+  :help          show this message
+  :quit          exit
+";
+
+struct Repl {
+    chip: Chip,
+    pins: Vec<String>,
+    eqns: Vec<String>,
+}
+
+impl Repl {
+    fn new(chip: Chip) -> Self {
+        let num_pins = chip.num_pins();
+        let pins = (1..=num_pins)
+            .map(|pin| match pin {
+                _ if pin == num_pins / 2 => String::from("GND"),
+                _ if pin == num_pins => String::from("VCC"),
+                _ => format!("P{}", pin),
+            })
+            .collect();
+        Repl {
+            chip,
+            pins,
+            eqns: Vec::new(),
+        }
+    }
+
+    // Build a source file from the current state.
+    fn source(&self, pins: &[String], eqns: &[String]) -> String {
+        let (row1, row2) = pins.split_at(pins.len() / 2);
+        let mut source = format!(
+            "{}\nREPL\n{}\n{}\n",
+            self.chip.name(),
+            row1.join(" "),
+            row2.join(" ")
+        );
+        for eqn in eqns.iter() {
+            source.push_str(eqn);
+            source.push('\n');
+        }
+        source
+    }
+
+    fn compile(&self, pins: &[String], eqns: &[String]) -> Result<crate::Compiled, String> {
+        // The line numbers refer to the generated source, so only
+        // report the message.
+        crate::compile(&self.source(pins, eqns)).map_err(|e| e.code.to_string())
+    }
+
+    // Describe the output driven by the last equation, which must
+    // come from the last line of the source. Anything else, such as
+    // a comment or a DESCRIPTION line, added no equation.
+    fn describe(&self, compiled: &crate::Compiled, line_num: usize) -> Result<String, String> {
+        let eqn = match compiled.content.eqns.last() {
+            Some(eqn) if eqn.line_num == line_num => eqn,
+            _ => return Err(String::from("no equation")),
+        };
+        let pin = match &eqn.lhs {
+            crate::parser::LHS::Pin((pin, _)) => pin.pin,
+            crate::parser::LHS::Ar => return Ok(String::from("AR: 1 row")),
+            crate::parser::LHS::Sp => return Ok(String::from("SP: 1 row")),
+        };
+        let olmc_num = self.chip.pin_to_olmc(pin).unwrap();
+        let olmc = &compiled.blueprint.olmcs[olmc_num];
+        let terms = olmc.output.as_ref().map_or(0, |(_, term)| term.pins.len());
+        Ok(format!(
+            "{} -> pin {}, OLMC {}: {} of {} product terms",
+            self.pins[pin - 1],
+            pin,
+            olmc_num,
+            terms,
+            self.chip.num_rows_for_olmc(olmc_num)
+        ))
+    }
+
+    // Process a line of input, returning false to quit.
+    fn handle<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => (),
+            Some(":quit") => return Ok(false),
+            Some(":help") => write!(out, "{}", HELP)?,
+            Some(":pins") => {
+                let pins = words.map(String::from).collect::<Vec<_>>();
+                if pins.len() != self.chip.num_pins() {
+                    writeln!(
+                        out,
+                        "error: expected {} pin names, found {}",
+                        self.chip.num_pins(),
+                        pins.len()
+                    )?;
+                } else {
+                    match self.compile(&pins, &self.eqns) {
+                        Ok(_) => self.pins = pins,
+                        Err(e) => writeln!(out, "error: {}", e)?,
+                    }
+                }
+            }
+            Some(":fuses") => match self.compile(&self.pins, &self.eqns) {
+                Ok(compiled) => {
+                    let glyphs = writer::Config::default().fuse_glyphs;
+                    write!(
+                        out,
+                        "{}",
+                        writer::make_fuse(glyphs, false, &self.pins, &compiled.gal).trim_start()
+                    )?;
+                }
+                Err(e) => writeln!(out, "error: {}", e)?,
+            },
+            Some(cmd) if cmd.starts_with(':') => {
+                writeln!(out, "error: unknown command '{}', try :help", cmd)?
+            }
+            Some(_) => {
+                let mut eqns = self.eqns.clone();
+                eqns.push(line.to_string());
+                // The chip, signature and pin rows come first.
+                let line_num = 4 + eqns.len();
+                let described = self
+                    .compile(&self.pins, &eqns)
+                    .and_then(|compiled| self.describe(&compiled, line_num));
+                match described {
+                    Ok(description) => {
+                        writeln!(out, "{}", description)?;
+                        self.eqns = eqns;
+                    }
+                    Err(e) => writeln!(out, "error: {}", e)?,
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn run<R: BufRead, W: Write>(chip: Chip, input: R, mut out: W) -> io::Result<()> {
+    let mut repl = Repl::new(chip);
+    writeln!(
+        out,
+        "{} REPL, pins are named {}. Type :help for help.",
+        chip.name(),
+        repl.pins.join(" ")
+    )?;
+
+    let mut lines = input.lines();
+    loop {
+        write!(out, "> ")?;
+        out.flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if !repl.handle(&line, &mut out)? {
+            break;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(chip: Chip, script: &str) -> String {
+        let mut out = Vec::new();
+        run(chip, script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn scripted_session() {
+        let out = run_script(
+            Chip::GAL16V8,
+            ":pins C A B NC NC NC NC NC NC GND NC Y Z NC NC NC NC NC NC VCC\n\
+             Y = A * B\n\
+             Z = A + B + /C\n\
+             Y = A\n\
+             Q = A\n\
+             :pins C X B NC NC NC NC NC NC GND NC Y Z NC NC NC NC NC NC VCC\n\
+             :quit\n\
+             Z = A\n",
+        );
+        let lines = out.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "> > Y -> pin 12, OLMC 0: 1 of 8 product terms",
+                "> Z -> pin 13, OLMC 1: 3 of 8 product terms",
+                "> error: output Y is defined multiple times",
+                "> error: unknown pinname 'Q'",
+                "> error: unknown pinname 'A'",
+                "> ",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_equations() {
+        let out = run_script(
+            Chip::GAL16V8,
+            "; just a comment\n\
+             DESCRIPTION\n\
+             P12 = P2\n",
+        );
+        let lines = out.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "> error: no equation",
+                "> error: no equation",
+                "> P12 -> pin 12, OLMC 0: 1 of 8 product terms",
+                "> ",
+            ]
+        );
+    }
+
+    #[test]
+    fn fuses() {
+        let out = run_script(Chip::GAL22V10, "P14 = P2\n:fuses\n");
+        assert!(out.contains("Pin 14 = P14"));
+        assert!(out.contains("\n123  ---- x---"));
+    }
+}
@@ -0,0 +1,456 @@
+//
+// sim.rs: Logic simulation
+//
+// Evaluates the logic programmed into a GAL, reading the products
+// back out of the fuses with 'GAL::product_terms', so that it checks
+// what was actually built rather than the equations it was built
+// from. ASSERT only models combinatorial logic, with registered
+// outputs treated as free inputs.
+//
+// 'Simulator' steps the registers through clock edges as well,
+// starting each from its declared 'INIT=' level (or low), with the
+// asynchronous reset and preset terms overriding the stored levels.
+// The GAL22V10's SP is synchronous, so only acts on a clock edge.
+//
+// 'verify_equivalence' compares two GALs on pseudo-random inputs, to
+// check that a transform of the equations, such as minimisation,
+// hasn't changed what they compute.
+//
+
+use std::collections::BTreeMap;
+
+use crate::{
+    blueprint::{self, Blueprint, PinMode},
+    chips::Chip,
+    errors::{at_line, Error, ErrorCode},
+    gal::{Pin, GAL},
+    parser::{Content, Suffix, LHS},
+};
+
+// ASSERT checks every combination of inputs, so bound the work.
+const MAX_ASSERT_INPUTS: usize = 16;
+
+struct Logic {
+    // Each combinatorial output pin's products, and whether it's
+    // active high.
+    outputs: BTreeMap<usize, (Vec<Vec<Pin>>, bool)>,
+}
+
+impl Logic {
+    fn new(gal: &GAL, blueprint: &Blueprint) -> Self {
+        let chip = gal.chip;
+        let num_olmcs = chip.num_olmcs();
+        let mut outputs = BTreeMap::new();
+        for pin in 1..=chip.num_pins() {
+            if let Some(i) = chip.pin_to_olmc(pin) {
+                match &blueprint.olmcs[i].output {
+                    Some((PinMode::Registered, _)) | None => (),
+                    Some(_) => {
+                        let active_high = gal.xor[num_olmcs - 1 - i];
+                        outputs.insert(pin, (gal.product_terms(pin), active_high));
+                    }
+                }
+            }
+        }
+        Logic { outputs }
+    }
+
+    // The level of a pin, given the levels of the free pins. A
+    // combinatorial loop has no defined level, so is cut off as low.
+    fn level(&self, pin: usize, free: &dyn Fn(usize) -> bool, depth: usize) -> bool {
+        let (products, active_high) = match self.outputs.get(&pin) {
+            Some(output) => output,
+            None => return free(pin),
+        };
+        if depth > self.outputs.len() {
+            return false;
+        }
+        let sum = products.iter().any(|ands| {
+            ands.iter()
+                .all(|input| self.level(input.pin, free, depth + 1) != input.neg)
+        });
+        sum == *active_high
+    }
+
+    // The free pins that the given pins depend on, in order.
+    fn free_pins(&self, pins: &[usize]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut todo = pins.to_vec();
+        while let Some(pin) = todo.pop() {
+            if seen.contains(&pin) {
+                continue;
+            }
+            seen.push(pin);
+            if let Some((products, _)) = self.outputs.get(&pin) {
+                todo.extend(products.iter().flatten().map(|input| input.pin));
+            }
+        }
+        let mut free = seen
+            .into_iter()
+            .filter(|pin| !self.outputs.contains_key(pin))
+            .collect::<Vec<_>>();
+        free.sort_unstable();
+        free
+    }
+}
+
+// Check each 'ASSERT' against the assembled GAL, over every
+// combination of the inputs involved, reporting the first failure.
+pub fn check_asserts(content: &Content, blueprint: &Blueprint, gal: &GAL) -> Result<(), Error> {
+    let logic = Logic::new(gal, blueprint);
+    let name = |pin: usize| content.pins[pin - 1].trim_start_matches('/').to_string();
+
+    for assert in content.asserts.iter() {
+        let line = assert.line_num;
+        let pin = match &assert.lhs {
+            LHS::Pin((pin, Suffix::None)) if logic.outputs.contains_key(&pin.pin) => pin,
+            LHS::Pin((pin, _)) => {
+                let name = name(pin.pin);
+                return at_line(line, Err(ErrorCode::AssertNotCombinatorial { name }));
+            }
+            LHS::Ar | LHS::Sp => {
+                let name = format!("{:?}", assert.lhs).to_uppercase();
+                return at_line(line, Err(ErrorCode::AssertNotCombinatorial { name }));
+            }
+        };
+        let expected = at_line(line, blueprint::eqn_to_term(gal.chip, assert))?;
+
+        let mut pins = vec![pin.pin];
+        pins.extend(expected.pins.iter().flatten().map(|input| input.pin));
+        let free = logic.free_pins(&pins);
+        if free.len() > MAX_ASSERT_INPUTS {
+            return at_line(
+                line,
+                Err(ErrorCode::AssertTooManyInputs {
+                    max: MAX_ASSERT_INPUTS,
+                }),
+            );
+        }
+
+        for bits in 0..1u32 << free.len() {
+            let levels = |p: usize| {
+                let i = free.iter().position(|f| *f == p).unwrap();
+                bits & (1 << i) != 0
+            };
+            let actual = logic.level(pin.pin, &levels, 0);
+            let wanted = expected.pins.iter().any(|ands| {
+                ands.iter()
+                    .all(|input| logic.level(input.pin, &levels, 0) != input.neg)
+            }) != pin.neg;
+            if actual != wanted {
+                let inputs = free
+                    .iter()
+                    .map(|p| format!("{}={}", name(*p), levels(*p) as u8))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                return at_line(
+                    line,
+                    Err(ErrorCode::AssertionFailed {
+                        name: name(pin.pin),
+                        inputs,
+                        level: actual as u8,
+                    }),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A registered output's state, with its reset and preset rows. A
+// missing row is never true.
+struct Register {
+    products: Vec<Vec<Pin>>,
+    active_high: bool,
+    reset: Option<Vec<Pin>>,
+    preset: Option<Vec<Pin>>,
+    sync_preset: Option<Vec<Pin>>,
+    level: bool,
+}
+
+fn asserted(row: &Option<Vec<Pin>>, level: &dyn Fn(usize) -> bool) -> bool {
+    row.as_ref()
+        .is_some_and(|ands| ands.iter().all(|input| level(input.pin) != input.neg))
+}
+
+impl Register {
+    // Reset clears the register, and preset sets it, so the pin's
+    // level depends on the polarity. Reset wins if both are asserted.
+    fn forced(&self, level: &dyn Fn(usize) -> bool) -> Option<bool> {
+        if asserted(&self.reset, level) {
+            Some(!self.active_high)
+        } else if asserted(&self.preset, level) {
+            Some(self.active_high)
+        } else {
+            None
+        }
+    }
+
+    // The level loaded on a clock edge.
+    fn next(&self, level: &dyn Fn(usize) -> bool) -> bool {
+        if asserted(&self.sync_preset, level) {
+            return self.active_high;
+        }
+        let sum = self
+            .products
+            .iter()
+            .any(|ands| ands.iter().all(|input| level(input.pin) != input.neg));
+        sum == self.active_high
+    }
+}
+
+// Simulates a GAL's combinatorial and registered logic. Each 'step'
+// is one rising edge of the clock. On the GAL20RA10, where each
+// register has its own clock term, every register is clocked.
+pub struct Simulator {
+    logic: Logic,
+    registers: BTreeMap<usize, Register>,
+}
+
+impl Simulator {
+    pub fn new(gal: &GAL, blueprint: &Blueprint) -> Self {
+        let chip = gal.chip;
+        let num_olmcs = chip.num_olmcs();
+        let matrix = gal.decoded_matrix();
+        let row = |row: usize| matrix[row].inputs.clone();
+
+        let mut registers = BTreeMap::new();
+        for pin in 1..=chip.num_pins() {
+            let i = match chip.pin_to_olmc(pin) {
+                Some(i) => i,
+                None => continue,
+            };
+            let olmc = &blueprint.olmcs[i];
+            if let Some((PinMode::Registered, _)) = olmc.output {
+                let start_row = chip.get_bounds(i).start_row;
+                let (reset, preset, sync_preset) = match chip {
+                    Chip::GAL22V10 => (row(0), None, row(chip.num_rows() - 1)),
+                    Chip::GAL20RA10 => (row(start_row + 2), row(start_row + 3), None),
+                    Chip::GAL16V8 | Chip::GAL20V8 => (None, None, None),
+                };
+                let register = Register {
+                    products: gal.product_terms(pin),
+                    active_high: gal.xor[num_olmcs - 1 - i],
+                    reset,
+                    preset,
+                    sync_preset,
+                    level: olmc.init.unwrap_or(false),
+                };
+                registers.insert(pin, register);
+            }
+        }
+
+        Simulator {
+            logic: Logic::new(gal, blueprint),
+            registers,
+        }
+    }
+
+    // The level of a pin with the registers as stored, so that the
+    // reset and preset terms can be evaluated without recursion.
+    fn stored_level(&self, pin: usize, inputs: &dyn Fn(usize) -> bool) -> bool {
+        let free = |p: usize| match self.registers.get(&p) {
+            Some(register) => register.level,
+            None => inputs(p),
+        };
+        self.logic.level(pin, &free, 0)
+    }
+
+    // The level of a pin, given the levels of the input pins.
+    pub fn evaluate(&self, pin: usize, inputs: &dyn Fn(usize) -> bool) -> bool {
+        let stored = |p: usize| self.stored_level(p, inputs);
+        let free = |p: usize| match self.registers.get(&p) {
+            Some(register) => register.forced(&stored).unwrap_or(register.level),
+            None => inputs(p),
+        };
+        self.logic.level(pin, &free, 0)
+    }
+
+    // Clock every register, loading them all at once from the levels
+    // before the edge.
+    pub fn step(&mut self, inputs: &dyn Fn(usize) -> bool) {
+        let level = |p: usize| self.evaluate(p, inputs);
+        let next = self
+            .registers
+            .iter()
+            .map(|(pin, register)| (*pin, register.next(&level)))
+            .collect::<Vec<_>>();
+        for (pin, level) in next.into_iter() {
+            self.registers.get_mut(&pin).unwrap().level = level;
+        }
+
+        // Anything held in reset or preset stays there.
+        let forced = self
+            .registers
+            .iter()
+            .map(|(pin, register)| {
+                let stored = |p: usize| self.stored_level(p, inputs);
+                (*pin, register.forced(&stored))
+            })
+            .collect::<Vec<_>>();
+        for (pin, level) in forced.into_iter() {
+            if let Some(level) = level {
+                self.registers.get_mut(&pin).unwrap().level = level;
+            }
+        }
+    }
+}
+
+// Whether two GALs compute the same thing, as far as 'n' input
+// vectors from 'seed' can tell. The chips and their mode bits must
+// match, and then each OLMC's main term is compared with every pin,
+// including feedback, treated as a free input. The control terms
+// aren't compared.
+pub fn verify_equivalence(a: &GAL, b: &GAL, seed: u64, n: usize) -> bool {
+    if a.chip != b.chip || a.syn != b.syn || a.ac0 != b.ac0 || a.ac1 != b.ac1 {
+        return false;
+    }
+
+    let chip = a.chip;
+    let num_olmcs = chip.num_olmcs();
+    let outputs = |gal: &GAL| {
+        (1..=chip.num_pins())
+            .filter_map(|pin| chip.pin_to_olmc(pin).map(|i| (pin, i)))
+            .map(|(pin, i)| (gal.product_terms(pin), gal.xor[num_olmcs - 1 - i]))
+            .collect::<Vec<_>>()
+    };
+    let (a, b) = (outputs(a), outputs(b));
+
+    // xorshift64, which mustn't start from zero.
+    let mut state = seed.max(1);
+    for _ in 0..n {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let level = |pin: &Pin| (state >> (pin.pin - 1)) & 1 != 0;
+        let eval = |(products, active_high): &(Vec<Vec<Pin>>, bool)| {
+            let sum = products
+                .iter()
+                .any(|ands| ands.iter().all(|input| level(input) != input.neg));
+            sum == *active_high
+        };
+        if a.iter().zip(b.iter()).any(|(a, b)| eval(a) != eval(b)) {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "GAL16V8\nAsserts\n\
+                          I0 I1 I2 NC NC NC NC NC NC GND\n\
+                          NC NC O0 O1 NC NC NC NC NC VCC\n\
+                          O0 = I0 * I1\n\
+                          /O1 = O0 + I2\n";
+
+    fn check(asserts: &str) -> Result<(), Error> {
+        crate::compile(&format!("{}{}", SOURCE, asserts)).map(|_| ())
+    }
+
+    #[test]
+    fn passing_asserts() {
+        check("ASSERT O0 = I1 * I0\n").unwrap();
+        // Feedback is followed through to the inputs.
+        check("ASSERT /O1 = I0 * I1 + I2\n").unwrap();
+        check("ASSERT O1 = /I0 * /I2 + /I1 * /I2\n").unwrap();
+    }
+
+    #[test]
+    fn failing_asserts() {
+        match check("ASSERT O0 = I0 * I1\nASSERT O1 = I0 + I2\n") {
+            Err(Error {
+                code:
+                    ErrorCode::AssertionFailed {
+                        name,
+                        inputs,
+                        level: 1,
+                    },
+                line: 8,
+            }) => {
+                assert_eq!(name, "O1");
+                assert_eq!(inputs, "I0=0 I1=0 I2=0");
+            }
+            e => panic!("unexpected result {:?}", e.err()),
+        }
+
+        let registered = "GAL16V8\nAsserts\n\
+                          Clock I0 NC NC NC NC NC NC NC GND\n\
+                          /OE NC NC O0 NC NC NC NC NC VCC\n\
+                          O0.R = I0\n\
+                          ASSERT O0 = I0\n";
+        let e = crate::compile(registered).err().unwrap();
+        assert!(matches!(e.code, ErrorCode::AssertNotCombinatorial { name } if name == "O0"));
+    }
+
+    #[test]
+    fn equivalence() {
+        let header = "GAL16V8\nEquiv\n\
+                      I0 I1 I2 NC NC NC NC NC NC GND\n\
+                      NC NC O0 O1 NC NC NC NC NC VCC\n";
+        let gal = |eqns: &str| crate::compile(&format!("{}{}", header, eqns)).unwrap().gal;
+        let original = gal("O0 = I0 * I1 + I0 * I1 * I2\nO1 = O0 + I2\n");
+
+        // Minimisation drops the absorbed product, and reordering
+        // makes no difference.
+        let minimised = gal("O0 = I1 * I0\nO1 = O0 + I2\n");
+        assert!(verify_equivalence(&original, &minimised, 1, 256));
+        assert!(verify_equivalence(&original, &original, 0, 256));
+
+        // Dropping an input that matters is caught.
+        let broken = gal("O0 = I0\nO1 = O0 + I2\n");
+        assert!(!verify_equivalence(&original, &broken, 1, 256));
+        let inverted = gal("/O0 = I0 * I1\nO1 = O0 + I2\n");
+        assert!(!verify_equivalence(&original, &inverted, 1, 256));
+    }
+
+    #[test]
+    fn registers_start_from_init() {
+        let source = "GAL16V8\nCounter\n\
+                      Clock I0 NC NC NC NC NC NC NC GND\n\
+                      /OE NC NC NC NC NC NC Q1 Q0 VCC\n\
+                      Q0.R = /Q0 ; INIT=1\n\
+                      /Q1.R = I0 ; INIT=1\n";
+        let compiled = crate::compile(source).unwrap();
+        assert_eq!(compiled.content.inits.len(), 2);
+        let mut sim = Simulator::new(&compiled.gal, &compiled.blueprint);
+        let low = |_: usize| false;
+
+        // Q0 toggles from its initial high, and '/Q1' starts high, so
+        // Q1 starts low.
+        assert!(sim.evaluate(19, &low));
+        assert!(!sim.evaluate(18, &low));
+        sim.step(&low);
+        assert!(!sim.evaluate(19, &low));
+        assert!(sim.evaluate(18, &low));
+        sim.step(&low);
+        assert!(sim.evaluate(19, &low));
+    }
+
+    #[test]
+    fn reset_overrides_init() {
+        let source = "GAL22V10\nReset\n\
+                      Clock I0 I1 NC NC NC NC NC NC NC NC GND\n\
+                      NC NC NC NC NC NC NC NC NC NC Q0 VCC\n\
+                      Q0.R = Q0 ; INIT=1\n\
+                      AR = I0\nSP = I1\n";
+        let compiled = crate::compile(source).unwrap();
+        let mut sim = Simulator::new(&compiled.gal, &compiled.blueprint);
+        let inputs = |i0: bool, i1: bool| move |pin: usize| (pin == 2 && i0) || (pin == 3 && i1);
+
+        assert!(sim.evaluate(23, &inputs(false, false)));
+        assert!(!sim.evaluate(23, &inputs(true, false)));
+        assert!(!sim.evaluate(23, &inputs(true, true)));
+        // A reset while clocking leaves the register cleared.
+        sim.step(&inputs(true, false));
+        assert!(!sim.evaluate(23, &inputs(false, false)));
+        // SP is synchronous, so does nothing until the clock.
+        assert!(!sim.evaluate(23, &inputs(false, true)));
+        sim.step(&inputs(false, true));
+        assert!(sim.evaluate(23, &inputs(false, false)));
+    }
+}
@@ -0,0 +1,90 @@
+//
+// wasm.rs: WebAssembly bindings
+//
+// Exposes the in-memory assembler to JavaScript, for running galette
+// in a browser. Built with the "wasm" feature.
+//
+
+use wasm_bindgen::prelude::*;
+
+use crate::{errors, parser, writer, Config};
+
+// Errors are flattened to a line number and message that a front end
+// can display directly. Line 0 means the error isn't tied to a line.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct AssembleError {
+    line: usize,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl AssembleError {
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl From<errors::Error> for AssembleError {
+    fn from(err: errors::Error) -> Self {
+        AssembleError {
+            line: err.line,
+            message: err.code.to_string(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn assemble_to_jedec(
+    source: &str,
+    secure: bool,
+    require_description: bool,
+) -> Result<String, AssembleError> {
+    let config = Config {
+        parser: parser::Config {
+            require_description,
+            ..parser::Config::default()
+        },
+        writer: writer::Config {
+            jedec_sec_bit: secure,
+            ..writer::Config::default()
+        },
+        ..Config::default()
+    };
+    Ok(crate::assemble_to_jedec(source, &config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "GAL16V8\nWasm\n\
+                          I0 I1 NC NC NC NC NC NC NC GND\n\
+                          NC O0 NC NC NC NC NC NC NC VCC\n\
+                          O0 = I0 * I1\n";
+
+    #[test]
+    fn round_trip() {
+        let jedec = assemble_to_jedec(SOURCE, true, false).unwrap();
+        assert!(jedec.contains("*G1\n"));
+        let expected = crate::compile(SOURCE).unwrap();
+        let config = writer::Config {
+            jedec_sec_bit: true,
+            ..writer::Config::default()
+        };
+        assert_eq!(jedec, writer::make_jedec(&config, &expected.gal));
+    }
+
+    #[test]
+    fn error() {
+        let err = assemble_to_jedec(&SOURCE.replace("I1\n", "I9\n"), false, false).unwrap_err();
+        assert_eq!(err.line(), 5);
+        assert_eq!(err.message(), "unknown pinname 'I9'");
+    }
+}
@@ -7,6 +7,7 @@
 
 use itertools::Itertools;
 use std::{
+    collections::BTreeMap,
     fmt::Write as Write2,
     fs::File,
     io::{Error, Write},
@@ -14,50 +15,197 @@ use std::{
 };
 
 use crate::{
-    blueprint::OLMC,
-    chips::Chip,
-    gal::{Mode, GAL},
+    blueprint::{Active, Blueprint, PinMode, OLMC},
+    chips::{Chip, CHIPS},
+    errors,
+    gal::{self, Mode, Term, GAL},
+    parser::{Content, Suffix, LHS},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Config {
     pub gen_fuse: bool,
     pub gen_chip: bool,
     pub gen_pin: bool,
     pub jedec_sec_bit: bool,
+    pub gen_manifest: bool,
+    pub gen_blif: bool,
+    // Comment each table in the .blif file with the source line of
+    // the equation it came from.
+    pub blif_line_comments: bool,
+    pub gen_logisim: bool,
+    pub gen_cupl: bool,
+    // Characters for set and clear fuses in the .fus file.
+    pub fuse_glyphs: (char, char),
+    // Also number each OLMC's rows in the .fus file from zero, which
+    // helps with the GAL22V10's variable-sized OLMCs.
+    pub fuse_offsets: bool,
+    // List every row in the JEDEC file, not just those with set
+    // fuses, so that diffs line up.
+    pub jedec_full_rows: bool,
+    // Who the JEDEC file says assembled it.
+    pub jedec_header: JedecHeader,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JedecHeader {
+    Galette,
+    // GALasm 2.1's header, for tools that look for it, so that the
+    // whole file matches GALasm's.
+    GALasm,
+    // A 'GAL-Assembler:' line naming the given assembler.
+    Custom(String),
+}
+
+// The defaults match the command-line defaults.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            gen_fuse: true,
+            gen_chip: true,
+            gen_pin: true,
+            jedec_sec_bit: false,
+            gen_manifest: false,
+            gen_blif: false,
+            blif_line_comments: false,
+            gen_logisim: false,
+            gen_cupl: false,
+            fuse_glyphs: ('-', 'x'),
+            fuse_offsets: false,
+            jedec_full_rows: false,
+            jedec_header: JedecHeader::Galette,
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////
-// Main entry point for writing all the files is 'write_files'.
+// Output goes to a 'Sink', so that it can be kept in memory where
+// there's no filesystem (tests, wasm).
 //
 
-fn write_file(base: &Path, ext: &str, buf: &str) -> Result<(), Error> {
-    let mut file = File::create(base.with_extension(ext).to_str().unwrap())?;
-    file.write_all(buf.as_bytes())?;
-    Ok(())
+pub trait Sink {
+    // Write the output for the given input file name and extension,
+    // returning the name of the output.
+    fn write(&mut self, name: &str, ext: &str, bytes: &[u8]) -> Result<String, Error>;
+}
+
+// The output name is the input with its extension replaced.
+fn output_name(name: &str, ext: &str) -> String {
+    Path::new(name)
+        .with_extension(ext)
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+pub struct FileSink;
+
+impl Sink for FileSink {
+    fn write(&mut self, name: &str, ext: &str, bytes: &[u8]) -> Result<String, Error> {
+        let file_name = output_name(name, ext);
+        write_atomically(&file_name, |file| file.write_all(bytes))?;
+        Ok(file_name)
+    }
+}
+
+// Like 'FileSink', but putting the outputs in the given directory.
+pub struct DirSink {
+    pub dir: PathBuf,
+}
+
+impl Sink for DirSink {
+    fn write(&mut self, name: &str, ext: &str, bytes: &[u8]) -> Result<String, Error> {
+        let output = output_name(name, ext);
+        let file_name = self.dir.join(Path::new(&output).file_name().unwrap());
+        let file_name = file_name.to_str().unwrap().to_string();
+        write_atomically(&file_name, |file| file.write_all(bytes))?;
+        Ok(file_name)
+    }
+}
+
+// Write via a temporary file that's renamed into place, so that a
+// failed or interrupted write never leaves a truncated output (such
+// as a .jed file that a programmer might consume).
+pub(crate) fn write_atomically<F>(file_name: &str, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut File) -> Result<(), Error>,
+{
+    let temp_name = format!("{}.tmp", file_name);
+    let result = File::create(&temp_name).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    let result = result.and_then(|_| std::fs::rename(&temp_name, file_name));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_name);
+    }
+    result
+}
+
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub files: BTreeMap<String, Vec<u8>>,
 }
 
+impl Sink for MemorySink {
+    fn write(&mut self, name: &str, ext: &str, bytes: &[u8]) -> Result<String, Error> {
+        let file_name = output_name(name, ext);
+        self.files.insert(file_name.clone(), bytes.to_vec());
+        Ok(file_name)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////
+// Main entry point for writing all the files is 'write_files'.
+//
+
 pub fn write_files(
+    sink: &mut dyn Sink,
     file_name: &str,
     config: &Config,
-    pin_names: &[String],
-    olmcs: &[OLMC],
+    blueprint: &Blueprint,
     gal: &GAL,
 ) -> Result<(), Error> {
-    let base = PathBuf::from(file_name);
+    let pin_names = &blueprint.pins;
+    let olmcs = &blueprint.olmcs;
+    let mut outputs = Vec::new();
+    let mut write_file = |ext: &str, buf: &str| -> Result<(), Error> {
+        outputs.push(sink.write(file_name, ext, buf.as_bytes())?);
+        Ok(())
+    };
 
-    write_file(&base, "jed", &make_jedec(config, gal))?;
+    let jedec = make_jedec_with_checksums(config, gal);
+    write_file("jed", &jedec.jedec)?;
 
     if config.gen_fuse {
-        write_file(&base, "fus", &make_fuse(pin_names, gal))?;
+        let fuse = make_fuse(config.fuse_glyphs, config.fuse_offsets, pin_names, gal);
+        write_file("fus", &fuse)?;
     }
 
     if config.gen_pin {
-        write_file(&base, "pin", &make_pin(gal, pin_names, olmcs))?;
+        write_file("pin", &make_pin(gal, pin_names, olmcs))?;
     }
 
     if config.gen_chip {
-        write_file(&base, "chp", &make_chip(gal.chip, pin_names))?;
+        write_file("chp", &make_chip(gal.chip, pin_names))?;
+    }
+
+    if config.gen_blif {
+        let blif = make_blif(config.blif_line_comments, gal, pin_names, olmcs);
+        write_file("blif", &blif)?;
+    }
+
+    if config.gen_logisim {
+        write_file("circ", &make_logisim(gal, pin_names, olmcs))?;
+    }
+
+    if config.gen_cupl {
+        write_file("cupl", &make_cupl(blueprint))?;
+    }
+
+    if config.gen_manifest {
+        let manifest = make_manifest(file_name, gal.chip, &outputs, &jedec);
+        sink.write(file_name, "manifest.json", manifest.as_bytes())?;
     }
 
     Ok(())
@@ -110,7 +258,7 @@ struct FuseBuilder<'a> {
 }
 
 impl<'a> FuseBuilder<'a> {
-    fn new(buf: &mut String) -> FuseBuilder {
+    fn new(buf: &mut String) -> FuseBuilder<'_> {
         FuseBuilder {
             buf,
             checksum: CheckSummer::new(),
@@ -156,6 +304,20 @@ impl<'a> FuseBuilder<'a> {
 //
 // It's galasm-compatible.
 pub fn make_jedec(config: &Config, gal: &GAL) -> String {
+    make_jedec_with_checksums(config, gal).jedec
+}
+
+// A JEDEC file along with the checksums embedded in it, so that
+// tools needn't scrape them back out of the text.
+pub struct Jedec {
+    pub jedec: String,
+    // The '*C' fuse checksum.
+    pub fuse_checksum: u16,
+    // The checksum trailing the file.
+    pub file_checksum: u16,
+}
+
+pub fn make_jedec_with_checksums(config: &Config, gal: &GAL) -> Jedec {
     let chip = gal.chip;
     let row_len = chip.num_cols();
 
@@ -163,7 +325,18 @@ pub fn make_jedec(config: &Config, gal: &GAL) -> String {
 
     buf.push_str("\x02\n");
 
-    let _ = writeln!(buf, "GAL-Assembler:  Galette {}", env!("CARGO_PKG_VERSION"));
+    match &config.jedec_header {
+        JedecHeader::Galette => {
+            let _ = writeln!(buf, "GAL-Assembler:  Galette {}", env!("CARGO_PKG_VERSION"));
+        }
+        JedecHeader::GALasm => {
+            buf.push_str("Used Program:   GALasm 2.1\n");
+            buf.push_str("GAL-Assembler:  GALasm 2.1\n");
+        }
+        JedecHeader::Custom(name) => {
+            let _ = writeln!(buf, "GAL-Assembler:  {}", name);
+        }
+    }
     let _ = writeln!(buf, "Device:         {}\n", chip.name());
     // Default value of gal_fuses
     buf.push_str("*F0\n");
@@ -178,20 +351,18 @@ pub fn make_jedec(config: &Config, gal: &GAL) -> String {
     // Number of fuses.
     let _ = writeln!(buf, "*QF{}", chip.total_size());
 
-    {
+    let fuse_checksum = {
         // Construct fuse matrix.
         let mut fuse_builder = FuseBuilder::new(&mut buf);
 
         // Break the fuse map into chunks representing rows.
-        for row in &gal.fuses.iter().chunks(row_len) {
-            let (mut check_iter, print_iter) = row.tee();
-
-            // Only write out non-zero bits.
-            if check_iter.any(|x| *x) {
-                fuse_builder.add_iter(print_iter);
+        for (i, row) in gal.fuses.iter().chunks(row_len).into_iter().enumerate() {
+            // Only write out non-zero bits, unless asked for everything.
+            if config.jedec_full_rows || !gal.is_default_row(i) {
+                fuse_builder.add_iter(row);
             } else {
                 // Process the bits without writing.
-                fuse_builder.skip_iter(print_iter);
+                fuse_builder.skip_iter(row);
             }
         }
 
@@ -213,17 +384,26 @@ pub fn make_jedec(config: &Config, gal: &GAL) -> String {
             fuse_builder.add(&[gal.ac0]);
         }
 
+        // Catch any mismatch between the GAL layout and '*QF'.
+        debug_assert_eq!(fuse_builder.idx, chip.total_size());
+
         // Fuse checksum.
         fuse_builder.checksum();
-    }
+        fuse_builder.checksum.get()
+    };
 
     buf.push_str("*\n");
     buf.push('\x03');
 
     // File checksum.
-    let _ = writeln!(buf, "{:04x}", file_checksum(buf.as_bytes()));
+    let file_checksum = file_checksum(buf.as_bytes());
+    let _ = writeln!(buf, "{:04x}", file_checksum);
 
-    buf
+    Jedec {
+        jedec: buf,
+        fuse_checksum,
+        file_checksum,
+    }
 }
 
 fn file_checksum(data: &[u8]) -> u16 {
@@ -232,11 +412,99 @@ fn file_checksum(data: &[u8]) -> u16 {
     })
 }
 
+////////////////////////////////////////////////////////////////////////
+// 'make_manifest' records what was built, for provenance.
+//
+
+fn make_manifest(file_name: &str, chip: Chip, outputs: &[String], jedec: &Jedec) -> String {
+    let outputs = outputs
+        .iter()
+        .map(|output| json_string(output))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut buf = String::new();
+    buf.push_str("{\n");
+    let _ = writeln!(buf, "  \"chip\": {},", json_string(chip.name()));
+    let _ = writeln!(buf, "  \"input\": {},", json_string(file_name));
+    let _ = writeln!(buf, "  \"outputs\": [{}],", outputs);
+    let _ = writeln!(buf, "  \"fuse_checksum\": \"{:04x}\",", jedec.fuse_checksum);
+    let _ = writeln!(buf, "  \"file_checksum\": \"{:04x}\"", jedec.file_checksum);
+    buf.push_str("}\n");
+    buf
+}
+
+// Quote a string for JSON output.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut buf = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_diagnostics' turns the outcome of building a source into a
+// JSON array of Language Server Protocol diagnostics, for editors.
+// Errors and warnings only carry a line number, so each diagnostic
+// covers its whole line. LSP counts lines from zero.
+//
+
+pub fn make_diagnostics(
+    source: &str,
+    result: &Result<Vec<errors::Warning>, errors::Error>,
+) -> String {
+    const ERROR: u8 = 1;
+    const WARNING: u8 = 2;
+
+    let mut diagnostics = Vec::new();
+    match result {
+        Ok(warnings) => {
+            for warning in warnings.iter() {
+                diagnostics.push((warning.line, WARNING, warning.code.to_string()));
+            }
+        }
+        Err(e) => diagnostics.push((e.line, ERROR, e.code.to_string())),
+    }
+
+    let lines = source.lines().collect::<Vec<_>>();
+    let diagnostics = diagnostics
+        .iter()
+        .map(|(line, severity, message)| {
+            let line = line.max(&1) - 1;
+            let end = lines.get(line).map_or(0, |text| text.chars().count());
+            format!(
+                "  {{\"range\": {{\"start\": {{\"line\": {line}, \"character\": 0}}, \
+                 \"end\": {{\"line\": {line}, \"character\": {end}}}}}, \
+                 \"severity\": {severity}, \"source\": \"galette\", \"message\": {message}}}",
+                line = line,
+                end = end,
+                severity = severity,
+                message = json_string(message)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if diagnostics.is_empty() {
+        return String::from("[]\n");
+    }
+    format!("[\n{}\n]\n", diagnostics.join(",\n"))
+}
+
 ////////////////////////////////////////////////////////////////////////
 // 'make_chip' draws out the chip with pin assignments.
 //
 
-fn make_chip(chip: Chip, pin_names: &[String]) -> String {
+pub(crate) fn make_chip(chip: Chip, pin_names: &[String]) -> String {
     let num_of_pins = pin_names.len();
     let mut buf = String::new();
 
@@ -266,11 +534,45 @@ fn make_chip(chip: Chip, pin_names: &[String]) -> String {
     buf
 }
 
+////////////////////////////////////////////////////////////////////////
+// 'make_chip_list' describes all the supported chips.
+//
+
+pub fn make_chip_list() -> String {
+    let mut buf = String::new();
+    buf.push_str("Chip       Pins  Array   Fuses  OLMCs  Features\n");
+
+    for chip in CHIPS.iter() {
+        let _ = writeln!(
+            buf,
+            "{:<9}  {:>4}  {:<6}  {:>5}  {:>5}  {}",
+            chip.name(),
+            chip.num_pins(),
+            format!("{}x{}", chip.num_rows(), chip.num_cols()),
+            chip.total_size(),
+            chip.num_olmcs(),
+            chip.features()
+        );
+    }
+
+    buf
+}
+
 ////////////////////////////////////////////////////////////////////////
 // 'make_pin' lists the pin assignments.
 //
 
-fn pin_type(gal: &GAL, olmcs: &[OLMC], i: usize) -> &'static str {
+fn pin_type(gal: &GAL, pin_names: &[String], olmcs: &[OLMC], i: usize) -> &'static str {
+    // Pins declared as NC are never read, so trust the declaration
+    // over them looking like unused inputs. Pins with a reserved
+    // function keep it, whatever they're called.
+    match physical_pin_type(gal, olmcs, i) {
+        "Input" | "Unused" if pin_names[i - 1] == "NC" => "NC",
+        pin_type => pin_type,
+    }
+}
+
+fn physical_pin_type(gal: &GAL, olmcs: &[OLMC], i: usize) -> &'static str {
     let chip = gal.chip;
     let num_pins = chip.num_pins();
 
@@ -279,7 +581,8 @@ fn pin_type(gal: &GAL, olmcs: &[OLMC], i: usize) -> &'static str {
         if olmc.output.is_some() {
             "Output"
         } else if !olmc.feedback {
-            "NC"
+            // Named, but neither driven nor read.
+            "Unused"
         } else {
             "Input"
         }
@@ -295,12 +598,15 @@ fn pin_type(gal: &GAL, olmcs: &[OLMC], i: usize) -> &'static str {
             Chip::GAL16V8 if gal.get_mode() == Mode::Registered && i == 11 => "/OE",
             Chip::GAL20V8 if gal.get_mode() == Mode::Registered && i == 13 => "/OE",
             Chip::GAL22V10 if i == 1 => "Clock/Input",
+            // Reserved pins, matching 'ReservedInputGAL20RA10'.
+            Chip::GAL20RA10 if i == 1 => "/PL",
+            Chip::GAL20RA10 if i == 13 => "/OE",
             _ => "Input",
         }
     }
 }
 
-fn make_pin(gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
+pub(crate) fn make_pin(gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
     let mut buf = String::new();
     buf.push_str("\n\n");
     buf.push_str(" Pin # | Name     | Pin Type\n");
@@ -312,7 +618,7 @@ fn make_pin(gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
             "  {:>2}   | {:<8} | {}",
             i,
             name,
-            pin_type(gal, olmcs, i)
+            pin_type(gal, pin_names, olmcs, i)
         );
     }
     buf.push('\n');
@@ -320,12 +626,859 @@ fn make_pin(gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
     buf
 }
 
+////////////////////////////////////////////////////////////////////////
+// 'make_timing_stub' classifies each output's path, for setting up
+// timing analysis elsewhere. We know nothing about actual delays.
+//
+
+pub fn make_timing_stub(gal: &GAL, blueprint: &Blueprint) -> String {
+    let chip = gal.chip;
+    let pin_names = &blueprint.pins;
+    let olmcs = &blueprint.olmcs;
+
+    let mut buf = String::new();
+    buf.push_str("\n\n");
+    buf.push_str(
+        " Pin # | Name     | Path          | Clock          | Sync controls  | Async controls\n",
+    );
+    buf.push_str(
+        "--------------------------------------------------------------------------------------\n",
+    );
+
+    for (name, i) in pin_names.iter().zip(1..) {
+        let olmc = match chip.pin_to_olmc(i) {
+            Some(olmc) => &olmcs[olmc],
+            None => continue,
+        };
+        let mode = match &olmc.output {
+            Some((mode, _)) => mode,
+            None => continue,
+        };
+
+        let (path, clock, sync, controls) = if *mode == PinMode::Registered {
+            // Only the GAL20RA10 has per-output clocks and async
+            // controls. The others clock every register from pin 1,
+            // and the GAL22V10 has a global async AR term and a
+            // global sync SP term, which are only listed if they're
+            // used.
+            let mut sync = String::from("-");
+            let (clock, controls) = match chip {
+                Chip::GAL20RA10 => {
+                    let mut controls = Vec::new();
+                    if let Some(term) = &olmc.arst {
+                        controls.push(format!("ARST = {}", term_to_string(pin_names, term)));
+                    }
+                    if let Some(term) = &olmc.aprst {
+                        controls.push(format!("APRST = {}", term_to_string(pin_names, term)));
+                    }
+                    let clock = olmc.clock.as_ref().unwrap();
+                    (term_to_string(pin_names, clock), controls)
+                }
+                Chip::GAL22V10 => {
+                    let mut controls = Vec::new();
+                    if let Some(term) = &blueprint.ar {
+                        controls.push(format!("AR = {}", term_to_string(pin_names, term)));
+                    }
+                    if let Some(term) = &blueprint.sp {
+                        sync = format!("SP = {}", term_to_string(pin_names, term));
+                    }
+                    (format!("{} (pin 1)", pin_names[0]), controls)
+                }
+                _ => (format!("{} (pin 1)", pin_names[0]), Vec::new()),
+            };
+            let controls = if controls.is_empty() {
+                String::from("-")
+            } else {
+                controls.join(", ")
+            };
+            ("registered", clock, sync, controls)
+        } else {
+            let none = || String::from("-");
+            ("combinatorial", none(), none(), none())
+        };
+
+        let _ = writeln!(
+            buf,
+            "  {:>2}   | {:<8} | {:<13} | {:<14} | {:<14} | {}",
+            i, name, path, clock, sync, controls
+        );
+    }
+    buf.push('\n');
+
+    buf
+}
+
+// Render a term in equation syntax, using the physical pins' names.
+fn term_to_string(pin_names: &[String], term: &Term) -> String {
+    if term.pins.is_empty() {
+        return String::from("GND");
+    }
+
+    term.pins
+        .iter()
+        .map(|ands| {
+            if ands.is_empty() {
+                return String::from("VCC");
+            }
+            ands.iter()
+                .map(|pin| {
+                    let name = pin_names[pin.pin - 1].trim_start_matches('/');
+                    if pin.neg {
+                        format!("/{}", name)
+                    } else {
+                        name.to_string()
+                    }
+                })
+                .join(" * ")
+        })
+        .join(" + ")
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_stats' summarises the assembled design, for quick sanity
+// checks and comparing revisions. Product terms are counted from the
+// fuse array, so output enables and the like are included.
+//
+
+pub fn make_stats(gal: &GAL, olmcs: &[OLMC]) -> String {
+    let chip = gal.chip;
+
+    let mut counts = [0; 3];
+    for olmc in olmcs.iter() {
+        match &olmc.output {
+            Some((PinMode::Combinatorial, _)) => counts[0] += 1,
+            Some((PinMode::Registered, _)) => counts[1] += 1,
+            Some((PinMode::Tristate, _)) => counts[2] += 1,
+            None => (),
+        }
+    }
+
+    let rows_used = gal
+        .fuses
+        .chunks(chip.num_cols())
+        .enumerate()
+        .filter(|(i, row)| gal.row_enabled(*i) && row.iter().any(|fuse| *fuse))
+        .count();
+    let fuses_set = gal.fuses.iter().filter(|fuse| **fuse).count();
+
+    let mut buf = String::new();
+    let _ = writeln!(buf, "Chip:          {}", chip.name());
+    if chip == Chip::GAL16V8 || chip == Chip::GAL20V8 {
+        let _ = writeln!(buf, "Mode:          {:?}", gal.get_mode());
+    }
+    let _ = writeln!(
+        buf,
+        "Outputs:       {} ({} combinatorial, {} registered, {} tristate)",
+        counts.iter().sum::<usize>(),
+        counts[0],
+        counts[1],
+        counts[2]
+    );
+    let _ = writeln!(buf, "Product terms: {} of {}", rows_used, chip.num_rows());
+    let _ = writeln!(buf, "Fuses set:     {} of {}", fuses_set, gal.fuses.len());
+    let _ = writeln!(buf, "Signature:     {}", gal::decode_sig(gal));
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_xref' cross-references the equations: for each pin read by an
+// equation, the equations that read it, and for each equation, the
+// pins it reads. Outputs read as feedback appear in both lists.
+//
+
+pub fn make_xref(content: &Content) -> String {
+    let num_pins = content.chip.num_pins();
+    let name = |pin: usize| content.pins[pin - 1].trim_start_matches('/');
+
+    // Each equation's name and inputs, in order and without repeats.
+    // The power pins, as constants, aren't inputs.
+    let eqns = content
+        .eqns
+        .iter()
+        .map(|eqn| {
+            let lhs = match &eqn.lhs {
+                LHS::Pin((pin, Suffix::None)) => name(pin.pin).to_string(),
+                LHS::Pin((pin, suffix)) => format!("{}.{}", name(pin.pin), suffix),
+                LHS::Ar => String::from("AR"),
+                LHS::Sp => String::from("SP"),
+            };
+            let inputs = eqn
+                .rhs
+                .iter()
+                .map(|input| input.pin)
+                .filter(|pin| *pin != num_pins && *pin != num_pins / 2)
+                .unique()
+                .collect::<Vec<_>>();
+            (lhs, eqn.line_num, inputs)
+        })
+        .collect::<Vec<_>>();
+
+    let mut users = BTreeMap::new();
+    for (lhs, _, inputs) in eqns.iter() {
+        for pin in inputs.iter() {
+            users
+                .entry(*pin)
+                .or_insert_with(Vec::new)
+                .push(lhs.as_str());
+        }
+    }
+
+    let mut buf = String::new();
+    buf.push_str("Pins read by equations:\n");
+    for (pin, lhss) in users.iter() {
+        let _ = writeln!(buf, "  {} (pin {}): {}", name(*pin), pin, lhss.join(", "));
+    }
+    buf.push_str("Equations:\n");
+    for (lhs, line_num, inputs) in eqns.iter() {
+        let inputs = if inputs.is_empty() {
+            String::from("(none)")
+        } else {
+            inputs.iter().map(|pin| name(*pin)).join(", ")
+        };
+        let _ = writeln!(buf, "  {} (line {}): {}", lhs, line_num, inputs);
+    }
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_blif' writes the equations as a BLIF netlist, for formal and
+// equivalence-checking tools. BLIF has no tristates, so each output
+// enable is written as an extra output, "<name>_oe". Asynchronous
+// resets and presets aren't represented. With 'line_comments', each
+// table is preceded by a comment giving the source line it came from.
+//
+
+pub fn make_blif(line_comments: bool, gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
+    let chip = gal.chip;
+    let name = |pin: usize| pin_names[pin - 1].trim_start_matches('/').to_string();
+    let cover = |buf: &mut String, term: &Term, out: &str, active_high: bool| {
+        if line_comments {
+            let _ = writeln!(buf, "# {} from line {}", out, term.line_num);
+        }
+        write_cover(buf, &name, term, out, active_high);
+    };
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut body = String::new();
+    for i in 1..=chip.num_pins() {
+        match pin_type(gal, pin_names, olmcs, i) {
+            "Output" => (),
+            "Input" | "Clock" | "Clock/Input" => {
+                inputs.push(name(i));
+                continue;
+            }
+            _ => continue,
+        }
+
+        let olmc = &olmcs[chip.pin_to_olmc(i).unwrap()];
+        let (mode, term) = olmc.output.as_ref().unwrap();
+        let out = name(i);
+        let active_high = olmc.get_active() == Active::High;
+
+        if *mode == PinMode::Registered {
+            let next = format!("{}_next", out);
+            cover(&mut body, term, &next, active_high);
+            let clock = match chip {
+                Chip::GAL20RA10 => {
+                    let clock = format!("{}_clk", out);
+                    cover(&mut body, olmc.clock.as_ref().unwrap(), &clock, true);
+                    clock
+                }
+                _ => name(1),
+            };
+            // Rising edge, with an unknown initial state.
+            let _ = writeln!(body, ".latch {} {} re {} 3", next, out, clock);
+        } else {
+            cover(&mut body, term, &out, active_high);
+        }
+        outputs.push(out.clone());
+
+        if let Some(term) = &olmc.tri_con {
+            let enable = format!("{}_oe", out);
+            cover(&mut body, term, &enable, true);
+            outputs.push(enable);
+        }
+    }
+
+    let model = gal::decode_sig(gal)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let model = if model.is_empty() {
+        String::from("galette")
+    } else {
+        model
+    };
+
+    let mut buf = String::new();
+    let _ = writeln!(buf, ".model {}", model);
+    let _ = writeln!(buf, ".inputs {}", inputs.join(" "));
+    let _ = writeln!(buf, ".outputs {}", outputs.join(" "));
+    buf.push_str(&body);
+    buf.push_str(".end\n");
+
+    buf
+}
+
+// Write a '.names' table computing 'out' from a term. Active-low
+// outputs list the cubes where the output is 0.
+fn write_cover(
+    buf: &mut String,
+    name: &dyn Fn(usize) -> String,
+    term: &Term,
+    out: &str,
+    active_high: bool,
+) {
+    let mut signals = term
+        .pins
+        .iter()
+        .flatten()
+        .map(|pin| pin.pin)
+        .collect::<Vec<_>>();
+    signals.sort_unstable();
+    signals.dedup();
+
+    let mut cubes = Vec::new();
+    'products: for ands in term.pins.iter() {
+        let mut cube = vec!['-'; signals.len()];
+        for pin in ands.iter() {
+            let idx = signals.binary_search(&pin.pin).unwrap();
+            let bit = if pin.neg { '0' } else { '1' };
+            if cube[idx] != '-' && cube[idx] != bit {
+                // An input ANDed with its negation is never true.
+                continue 'products;
+            }
+            cube[idx] = bit;
+        }
+        cubes.push(cube.into_iter().collect::<String>());
+    }
+
+    let mut names = signals.into_iter().map(name).collect::<Vec<_>>();
+    names.push(out.to_string());
+    let _ = writeln!(buf, ".names {}", names.join(" "));
+
+    // An empty table is constant 0, so an inverted false term needs
+    // an explicit row.
+    let (cubes, bit) = match (cubes.is_empty(), active_high) {
+        (true, false) => (vec!["-".repeat(names.len() - 1)], '1'),
+        (_, true) => (cubes, '1'),
+        (_, false) => (cubes, '0'),
+    };
+    for cube in cubes.iter() {
+        if cube.is_empty() {
+            let _ = writeln!(buf, "{}", bit);
+        } else {
+            let _ = writeln!(buf, "{} {}", cube, bit);
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_equations' lists the design's equations in a normalised,
+// ABEL-like syntax: one equation per output and control term, in pin
+// order, negating with '!', ANDing with '&' and ORing with '#'. The
+// parser accepts this syntax, so the listing can be assembled again
+// in place of the original equations. Pins are named and negated by
+// their physical levels, as if no pin had been declared negated.
+//
+
+// Write a term as a sum of products with '!', '&' and '#', using the
+// given constants for an empty sum and an empty product.
+fn term_to_sop<'a>(
+    term: &Term,
+    name: &dyn Fn(usize) -> &'a str,
+    (zero, one): (&str, &str),
+) -> String {
+    if term.pins.is_empty() {
+        return String::from(zero);
+    }
+    term.pins
+        .iter()
+        .map(|ands| {
+            if ands.is_empty() {
+                return String::from(one);
+            }
+            ands.iter()
+                .map(|pin| {
+                    let neg = if pin.neg { "!" } else { "" };
+                    format!("{}{}", neg, name(pin.pin))
+                })
+                .join(" & ")
+        })
+        .join(" # ")
+}
+
+pub fn make_equations(blueprint: &Blueprint) -> String {
+    let chip = blueprint.chip;
+    let name = |pin: usize| blueprint.pins[pin - 1].trim_start_matches('/');
+    let term_to_abel = |term: &Term| term_to_sop(term, &name, ("GND", "VCC"));
+
+    let mut buf = String::new();
+    for pin in 1..=chip.num_pins() {
+        let olmc = match chip.pin_to_olmc(pin) {
+            Some(i) => &blueprint.olmcs[i],
+            None => continue,
+        };
+
+        if let Some((mode, term)) = &olmc.output {
+            let neg = if olmc.get_active() == Active::Low {
+                "!"
+            } else {
+                ""
+            };
+            let suffix = match mode {
+                PinMode::Combinatorial => "",
+                PinMode::Tristate => ".T",
+                PinMode::Registered => ".R",
+            };
+            let _ = writeln!(
+                buf,
+                "{}{}{} = {}",
+                neg,
+                name(pin),
+                suffix,
+                term_to_abel(term)
+            );
+        } else if let Some(term) = &olmc.polarity {
+            let _ = writeln!(buf, "{}.POL = {}", name(pin), term_to_abel(term));
+        }
+
+        let controls = [
+            ("E", &olmc.tri_con),
+            ("CLK", &olmc.clock),
+            ("ARST", &olmc.arst),
+            ("APRST", &olmc.aprst),
+        ];
+        for (suffix, term) in controls.iter() {
+            if let Some(term) = term {
+                let _ = writeln!(buf, "{}.{} = {}", name(pin), suffix, term_to_abel(term));
+            }
+        }
+    }
+
+    if let Some(term) = &blueprint.ar {
+        let _ = writeln!(buf, "AR = {}", term_to_abel(term));
+    }
+    if let Some(term) = &blueprint.sp {
+        let _ = writeln!(buf, "SP = {}", term_to_abel(term));
+    }
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_cupl' exports the design as a CUPL source, for tools that
+// take CUPL rather than GALasm syntax. The equations are written as
+// in 'make_equations', with CUPL's constants and extensions ('.d' for
+// registers, '.oe', '.ck', '.ar' and '.ap' for control terms, and '.sp'
+// for the GAL22V10's synchronous preset). The terms are sums of
+// products, so CUPL's '$' (XOR) isn't needed.
+//
+// Not everything maps cleanly: the GAL22V10's AR and SP are single
+// terms shared by every register, but CUPL attaches them to each
+// registered output, so they're repeated on each one. An inverted
+// output with no equation ('.POL') has no CUPL equivalent, so it's
+// left as a comment.
+//
+
+// The header fields galette has nothing to fill in with.
+const CUPL_HEADER: &str = "\
+PartNo   00;
+Date     ;
+Revision 01;
+Designer ;
+Company  ;
+Assembly ;
+Location ;
+";
+
+fn cupl_device(chip: Chip) -> &'static str {
+    match chip {
+        Chip::GAL16V8 => "g16v8",
+        Chip::GAL20V8 => "g20v8",
+        Chip::GAL22V10 => "g22v10",
+        Chip::GAL20RA10 => "g20ra10",
+    }
+}
+
+pub fn make_cupl(blueprint: &Blueprint) -> String {
+    let chip = blueprint.chip;
+    let num_pins = chip.num_pins();
+    let name = |pin: usize| blueprint.pins[pin - 1].trim_start_matches('/');
+    let term_to_cupl = |term: &Term| term_to_sop(term, &name, ("'b'0", "'b'1"));
+
+    // CUPL's name must be an identifier, so the signature is cleaned
+    // up to make one.
+    let sig = String::from_utf8_lossy(&blueprint.sig);
+    let mut design = sig
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if design.is_empty() {
+        design = String::from("galette");
+    }
+
+    let mut buf = String::new();
+    let _ = writeln!(buf, "Name     {};", design);
+    buf.push_str(CUPL_HEADER);
+    let _ = writeln!(buf, "Device   {};", cupl_device(chip));
+
+    buf.push_str("\n/* Pins */\n\n");
+    for pin in 1..=num_pins {
+        let name = name(pin);
+        if name != "NC" && pin != num_pins && pin != num_pins / 2 {
+            let _ = writeln!(buf, "PIN {:<2} = {};", pin, name);
+        }
+    }
+
+    buf.push_str("\n/* Equations */\n\n");
+    for pin in 1..=num_pins {
+        let olmc = match chip.pin_to_olmc(pin) {
+            Some(i) => &blueprint.olmcs[i],
+            None => continue,
+        };
+
+        if let Some((mode, term)) = &olmc.output {
+            let neg = if olmc.get_active() == Active::Low {
+                "!"
+            } else {
+                ""
+            };
+            let ext = if *mode == PinMode::Registered {
+                ".d"
+            } else {
+                ""
+            };
+            let _ = writeln!(buf, "{}{}{} = {};", neg, name(pin), ext, term_to_cupl(term));
+
+            if *mode == PinMode::Registered {
+                let global = [("ar", &blueprint.ar), ("sp", &blueprint.sp)];
+                for (ext, term) in global.iter() {
+                    if let Some(term) = term {
+                        let _ = writeln!(buf, "{}.{} = {};", name(pin), ext, term_to_cupl(term));
+                    }
+                }
+            }
+        } else if let Some(term) = &olmc.polarity {
+            let _ = writeln!(
+                buf,
+                "/* {}.POL = {} has no CUPL equivalent */",
+                name(pin),
+                term_to_cupl(term)
+            );
+        }
+
+        let controls = [
+            ("oe", &olmc.tri_con),
+            ("ck", &olmc.clock),
+            ("ar", &olmc.arst),
+            ("ap", &olmc.aprst),
+        ];
+        for (ext, term) in controls.iter() {
+            if let Some(term) = term {
+                let _ = writeln!(buf, "{}.{} = {};", name(pin), ext, term_to_cupl(term));
+            }
+        }
+    }
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////
+// 'make_logisim' writes the equations as a Logisim circuit, so that
+// students can see and simulate the design. Signals are connected by
+// named tunnels rather than wires, so the layout only has to put a
+// tunnel on each port, and components are simply stacked down the
+// page. Asynchronous resets and presets aren't represented.
+//
+
+// Logisim library numbers, as declared in the file header.
+const LIB_WIRING: u32 = 0;
+const LIB_GATES: u32 = 1;
+const LIB_MEMORY: u32 = 4;
+
+struct Circuit {
+    buf: String,
+    // Where the next component goes.
+    y: i32,
+}
+
+impl Circuit {
+    fn comp(&mut self, lib: u32, name: &str, (x, y): (i32, i32), attrs: &[(&str, &str)]) {
+        let _ = writeln!(
+            self.buf,
+            "    <comp lib=\"{}\" loc=\"({},{})\" name=\"{}\">",
+            lib, x, y, name
+        );
+        for (attr, val) in attrs.iter() {
+            let _ = writeln!(
+                self.buf,
+                "      <a name=\"{}\" val=\"{}\"/>",
+                attr,
+                xml_escape(val)
+            );
+        }
+        self.buf.push_str("    </comp>\n");
+    }
+
+    fn tunnel(&mut self, label: &str, loc: (i32, i32)) {
+        self.comp(LIB_WIRING, "Tunnel", loc, &[("label", label)]);
+    }
+
+    fn constant(&mut self, value: bool, out: &str) {
+        let loc = (300, self.y);
+        self.comp(
+            LIB_WIRING,
+            "Constant",
+            loc,
+            &[("value", if value { "0x1" } else { "0x0" })],
+        );
+        self.tunnel(out, loc);
+        self.y += 30;
+    }
+
+    // A gate of the default size, with its output at x = 300.
+    fn gate(&mut self, name: &str, inputs: &[String], out: &str) {
+        // Logisim won't make a one-input AND or OR.
+        let inputs = if inputs.len() == 1 {
+            vec![inputs[0].clone(), inputs[0].clone()]
+        } else {
+            inputs.to_vec()
+        };
+        let half_height = gate_input_dy(inputs.len(), inputs.len() - 1);
+        self.y += half_height;
+        let count = inputs.len().to_string();
+        self.comp(LIB_GATES, name, (300, self.y), &[("inputs", &count)]);
+        for (input, i) in inputs.iter().zip(0..) {
+            self.tunnel(input, (250, self.y + gate_input_dy(inputs.len(), i)));
+        }
+        self.tunnel(out, (300, self.y));
+        self.y += half_height + 30;
+    }
+
+    fn not(&mut self, input: &str, out: &str) {
+        self.comp(LIB_GATES, "NOT Gate", (300, self.y), &[]);
+        self.tunnel(input, (270, self.y));
+        self.tunnel(out, (300, self.y));
+        self.y += 30;
+    }
+
+    // Sum of products, optionally inverted.
+    fn term(
+        &mut self,
+        pin_name: &dyn Fn(&gal::Pin) -> String,
+        term: &Term,
+        out: &str,
+        invert: bool,
+    ) {
+        if term.pins.is_empty() {
+            self.constant(invert, out);
+            return;
+        }
+
+        let mut products = Vec::new();
+        for ands in term.pins.iter() {
+            let product = format!("{}.p{}", out, products.len());
+            if ands.is_empty() {
+                self.constant(true, &product);
+            } else {
+                let inputs = ands.iter().map(pin_name).collect::<Vec<_>>();
+                self.gate("AND Gate", &inputs, &product);
+            }
+            products.push(product);
+        }
+
+        if invert {
+            let sum = format!("{}.sum", out);
+            self.gate("OR Gate", &products, &sum);
+            self.not(&sum, out);
+        } else {
+            self.gate("OR Gate", &products, out);
+        }
+    }
+}
+
+// Vertical offset of a gate input from the output, following
+// Logisim's layout for gates of the default size.
+fn gate_input_dy(inputs: usize, index: usize) -> i32 {
+    let (start, dist, lower_even) = if inputs <= 3 {
+        (-10, 20, 20)
+    } else {
+        (-5, 10, 10)
+    };
+    let (n, i) = (inputs as i32, index as i32);
+    if n % 2 == 1 {
+        start * (n - 1) + dist * i
+    } else if i >= n / 2 {
+        start * n + dist * i + lower_even
+    } else {
+        start * n + dist * i
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn make_logisim(gal: &GAL, pin_names: &[String], olmcs: &[OLMC]) -> String {
+    let chip = gal.chip;
+    let name = |pin: usize| pin_names[pin - 1].trim_start_matches('/').to_string();
+    let pin_name = |pin: &gal::Pin| {
+        if pin.neg {
+            format!("/{}", name(pin.pin))
+        } else {
+            name(pin.pin)
+        }
+    };
+
+    let mut circuit = Circuit {
+        buf: String::new(),
+        y: 40,
+    };
+    let mut negated = Vec::new();
+    for i in 1..=chip.num_pins() {
+        match pin_type(gal, pin_names, olmcs, i) {
+            "Input" | "Clock" | "Clock/Input" => {
+                let loc = (100, circuit.y);
+                circuit.comp(
+                    LIB_WIRING,
+                    "Pin",
+                    loc,
+                    &[("tristate", "false"), ("label", &name(i))],
+                );
+                circuit.tunnel(&name(i), loc);
+                circuit.y += 30;
+            }
+            "Output" => (),
+            _ => continue,
+        }
+
+        // Every negated input gets a NOT gate to drive it.
+        for olmc in olmcs.iter() {
+            let terms = [
+                olmc.output.as_ref().map(|(_, term)| term),
+                olmc.tri_con.as_ref(),
+                olmc.clock.as_ref(),
+            ];
+            let used_negated = terms.iter().flatten().any(|term| {
+                term.pins
+                    .iter()
+                    .flatten()
+                    .any(|pin| pin.pin == i && pin.neg)
+            });
+            if used_negated && !negated.contains(&i) {
+                negated.push(i);
+            }
+        }
+    }
+    for i in negated.iter() {
+        circuit.not(&name(*i), &format!("/{}", name(*i)));
+    }
+
+    for i in 1..=chip.num_pins() {
+        if pin_type(gal, pin_names, olmcs, i) != "Output" {
+            continue;
+        }
+        let olmc = &olmcs[chip.pin_to_olmc(i).unwrap()];
+        let (mode, term) = olmc.output.as_ref().unwrap();
+        let out = name(i);
+        let invert = olmc.get_active() == Active::Low;
+
+        let value = if olmc.tri_con.is_some() {
+            format!("{}.v", out)
+        } else {
+            out.clone()
+        };
+
+        if *mode == PinMode::Registered {
+            let clock = match chip {
+                Chip::GAL20RA10 => {
+                    let clock = format!("{}.clk", out);
+                    circuit.term(&pin_name, olmc.clock.as_ref().unwrap(), &clock, false);
+                    clock
+                }
+                _ => name(1),
+            };
+            let d = format!("{}.d", out);
+            circuit.term(&pin_name, term, &d, invert);
+
+            let y = circuit.y;
+            circuit.comp(LIB_MEMORY, "D Flip-Flop", (300, y), &[]);
+            circuit.tunnel(&d, (260, y));
+            circuit.tunnel(&clock, (260, y + 20));
+            circuit.tunnel(&value, (300, y));
+            circuit.y += 50;
+        } else {
+            circuit.term(&pin_name, term, &value, invert);
+        }
+
+        if let Some(enable) = &olmc.tri_con {
+            let enable_net = format!("{}.oe", out);
+            circuit.term(&pin_name, enable, &enable_net, false);
+            let y = circuit.y;
+            circuit.comp(LIB_GATES, "Controlled Buffer", (300, y), &[]);
+            circuit.tunnel(&value, (280, y));
+            circuit.tunnel(&enable_net, (290, y + 10));
+            circuit.tunnel(&out, (300, y));
+            circuit.y += 40;
+        }
+
+        let loc = (500, circuit.y);
+        circuit.comp(
+            LIB_WIRING,
+            "Pin",
+            loc,
+            &[
+                ("facing", "west"),
+                ("output", "true"),
+                ("label", &out),
+                ("labelloc", "east"),
+            ],
+        );
+        circuit.tunnel(&out, loc);
+        circuit.y += 40;
+    }
+
+    let mut buf = String::new();
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    buf.push_str("<project source=\"2.7.1\" version=\"1.0\">\n");
+    let _ = writeln!(buf, "  <lib desc=\"#Wiring\" name=\"{}\"/>", LIB_WIRING);
+    let _ = writeln!(buf, "  <lib desc=\"#Gates\" name=\"{}\"/>", LIB_GATES);
+    let _ = writeln!(buf, "  <lib desc=\"#Memory\" name=\"{}\"/>", LIB_MEMORY);
+    buf.push_str("  <main name=\"main\"/>\n");
+    buf.push_str("  <circuit name=\"main\">\n");
+    buf.push_str(&circuit.buf);
+    buf.push_str("  </circuit>\n");
+    buf.push_str("</project>\n");
+
+    buf
+}
+
 ////////////////////////////////////////////////////////////////////////
 // 'make_fuse' writes out a fuse map.
 //
 
-fn make_row(buf: &mut String, row: &mut usize, num_of_col: usize, data: &[bool]) {
+// Write out a row of fuses, numbered by its row in the whole array,
+// and optionally its row within the OLMC. GALasm groups the columns
+// in fours whatever the chip, so we do too.
+fn make_row(
+    buf: &mut String,
+    (on, off): (char, char),
+    row: &mut usize,
+    offset: Option<usize>,
+    num_of_col: usize,
+    data: &[bool],
+) {
     let _ = write!(buf, "\n{:>3} ", row);
+    if let Some(offset) = offset {
+        let _ = write!(buf, "[{:>2}] ", offset);
+    }
 
     for col in 0..num_of_col {
         if col % 4 == 0 {
@@ -333,9 +1486,9 @@ fn make_row(buf: &mut String, row: &mut usize, num_of_col: usize, data: &[bool])
         }
 
         buf.push(if data[*row * num_of_col + col] {
-            '-'
+            on
         } else {
-            'x'
+            off
         });
     }
 
@@ -350,7 +1503,7 @@ fn to_bit(bit: bool) -> char {
     }
 }
 
-fn make_fuse(pin_names: &[String], gal: &GAL) -> String {
+pub fn make_fuse(glyphs: (char, char), offsets: bool, pin_names: &[String], gal: &GAL) -> String {
     // This function relies on detailed knowledge of the ordering of
     // rows in the fuse map vs. OLMCs vs. pins. It's brittle, but
     // no-one's changing the hardware layout. :)
@@ -366,7 +1519,8 @@ fn make_fuse(pin_names: &[String], gal: &GAL) -> String {
     // AR for the 22V10
     if chip == Chip::GAL22V10 {
         buf.push_str("\n\nAR");
-        make_row(&mut buf, &mut row, row_len, &gal.fuses);
+        let offset = offsets.then_some(0);
+        make_row(&mut buf, glyphs, &mut row, offset, row_len, &gal.fuses);
     }
 
     let last_olmc = chip.last_olmc();
@@ -387,9 +1541,10 @@ fn make_fuse(pin_names: &[String], gal: &GAL) -> String {
             &flags
         );
 
-        for _ in 0..chip.num_rows_for_olmc(olmc) {
+        for offset in 0..chip.num_rows_for_olmc(olmc) {
             // Print all fuses of an OLMC
-            make_row(&mut buf, &mut row, row_len, &gal.fuses);
+            let offset = offsets.then_some(offset);
+            make_row(&mut buf, glyphs, &mut row, offset, row_len, &gal.fuses);
         }
 
         pin -= 1;
@@ -398,7 +1553,8 @@ fn make_fuse(pin_names: &[String], gal: &GAL) -> String {
     // SP for the 22V10
     if chip == Chip::GAL22V10 {
         buf.push_str("\n\nSP");
-        make_row(&mut buf, &mut row, row_len, &gal.fuses);
+        let offset = offsets.then_some(0);
+        make_row(&mut buf, glyphs, &mut row, offset, row_len, &gal.fuses);
     }
 
     buf.push_str("\n\n");
@@ -409,6 +1565,541 @@ fn make_fuse(pin_names: &[String], gal: &GAL) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn chip_list_22v10() {
+        let list = make_chip_list();
+        assert!(list.contains("GAL22V10     24  132x44   5892     10  AR/SP terms"));
+    }
+
+    #[test]
+    fn write_to_memory() {
+        let source = "GAL16V8\nMemory\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0 * I1\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let config = Config {
+            gen_fuse: false,
+            ..Config::default()
+        };
+
+        let mut sink = MemorySink::default();
+        write_files(
+            &mut sink,
+            "dir/memory.pld",
+            &config,
+            blueprint,
+            &compiled.gal,
+        )
+        .unwrap();
+
+        let names = sink.files.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["dir/memory.chp", "dir/memory.jed", "dir/memory.pin"]
+        );
+        assert_eq!(
+            sink.files["dir/memory.jed"],
+            make_jedec(&config, &compiled.gal).into_bytes()
+        );
+    }
+
+    #[test]
+    fn single_jedec_path() {
+        // The file output and the in-memory entry point must agree, as
+        // both go through 'make_jedec_with_checksums'.
+        let source = "GAL22V10\nOnePath\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1\nO1 = I2\n";
+        let config = crate::Config::default();
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+
+        let mut sink = MemorySink::default();
+        write_files(
+            &mut sink,
+            "one.pld",
+            &config.writer,
+            blueprint,
+            &compiled.gal,
+        )
+        .unwrap();
+
+        let jedec = crate::assemble_to_jedec(source, &config).unwrap();
+        assert_eq!(sink.files["one.jed"], jedec.into_bytes());
+    }
+
+    #[test]
+    fn pin_type_declared_nc() {
+        let source = "GAL16V8\nNC\n\
+                      I0 NC I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 NC O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let pin_type = |i| pin_type(&compiled.gal, &blueprint.pins, &blueprint.olmcs, i);
+        // Declared NC, on both a dedicated input and an OLMC.
+        assert_eq!(pin_type(2), "NC");
+        assert_eq!(pin_type(13), "NC");
+        // Declared but unused.
+        assert_eq!(pin_type(3), "Input");
+        assert_eq!(pin_type(14), "Unused");
+        assert_eq!(pin_type(12), "Output");
+    }
+
+    #[test]
+    fn timing_stub_mixed() {
+        let source = "GAL22V10\nTiming\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1\nO1 = I2 + I3\nO2.R = I4\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let stub = make_timing_stub(&compiled.gal, blueprint);
+        assert!(stub
+            .contains("  14   | O0       | registered    | Clock (pin 1)  | -              | -\n"));
+        assert!(stub
+            .contains("  15   | O1       | combinatorial | -              | -              | -\n"));
+        assert!(stub
+            .contains("  16   | O2       | registered    | Clock (pin 1)  | -              | -\n"));
+        assert!(!stub.contains("  17 "));
+
+        // Only the controls that are programmed are listed, and SP is
+        // synchronous.
+        let with_ar = format!("{}AR = I5 * I6\n", source);
+        let compiled = crate::compile(&with_ar).unwrap();
+        let stub = make_timing_stub(&compiled.gal, &compiled.blueprint);
+        assert!(stub.contains(
+            "  14   | O0       | registered    | Clock (pin 1)  | -              | AR = I5 * I6\n"
+        ));
+        let with_both = format!("{}SP = /I7\n", with_ar);
+        let compiled = crate::compile(&with_both).unwrap();
+        let stub = make_timing_stub(&compiled.gal, &compiled.blueprint);
+        assert!(stub.contains(
+            "  16   | O2       | registered    | Clock (pin 1)  | SP = /I7       | AR = I5 * I6\n"
+        ));
+    }
+
+    #[test]
+    fn timing_stub_clock_terms() {
+        let source = "GAL20RA10\nTiming\n\
+                      /PL I0 I1 I2 I3 I4 I5 I6 I7 I8 Clock GND\n\
+                      /OE O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0\nO0.CLK = Clock * O1\nO0.ARST = I1\n\
+                      O1 = I2\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let stub = make_timing_stub(&compiled.gal, blueprint);
+        assert!(stub.contains(
+            "  14   | O0       | registered    | Clock * O1     | -              | ARST = I1\n"
+        ));
+        assert!(stub
+            .contains("  15   | O1       | combinatorial | -              | -              | -\n"));
+    }
+
+    #[test]
+    fn stats_mixed() {
+        let source = "GAL16V8\nStats\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC GND\n\
+                      /OE O0 O1 O2 NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1 + I2\nO1.T = I2 + I3\nO1.E = I0\nO2.R = /I3\n";
+        let compiled = crate::compile(source).unwrap();
+        let stats = make_stats(&compiled.gal, &compiled.blueprint.olmcs);
+        assert_eq!(
+            stats,
+            "Chip:          GAL16V8\n\
+             Mode:          Registered\n\
+             Outputs:       3 (0 combinatorial, 2 registered, 1 tristate)\n\
+             Product terms: 6 of 64\n\
+             Fuses set:     185 of 2048\n\
+             Signature:     Stats\n"
+        );
+    }
+
+    #[test]
+    fn blif() {
+        let source = "GAL22V10\nBLIF 1\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC NC NC GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1 + O0 * /I2\n\
+                      /O1 = I2 + I3\n\
+                      O2.T = I0\nO2.E = I1\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let blif = make_blif(false, &compiled.gal, &blueprint.pins, &blueprint.olmcs);
+
+        let lines = blif.lines().collect::<Vec<_>>();
+        let count = |prefix: &str| lines.iter().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(lines[0], ".model BLIF_1");
+        assert_eq!(count(".model "), 1);
+        assert_eq!(count(".end"), 1);
+        assert_eq!(lines.last(), Some(&".end"));
+        assert_eq!(lines[1], ".inputs Clock I0 I1 I2 I3");
+        assert_eq!(lines[2], ".outputs O0 O1 O2 O2_oe");
+        assert_eq!(count(".latch "), 1);
+        assert!(blif.contains(".latch O0_next O0 re Clock 3\n"));
+
+        // Every row of each table has one column per input.
+        let mut width = None;
+        for line in lines[3..].iter() {
+            if let Some(names) = line.strip_prefix(".names ") {
+                width = Some(names.split_whitespace().count() - 1);
+            } else if line.starts_with('.') {
+                width = None;
+            } else {
+                let (cube, bit) = line.split_at(line.len() - 1);
+                assert_eq!(cube.trim_end().len(), width.unwrap(), "{}", line);
+                assert!(bit == "0" || bit == "1");
+            }
+        }
+
+        assert!(blif.contains(".names I0 I1 I2 O0 O0_next\n11-- 1\n--01 1\n"));
+        assert!(blif.contains(".names I2 I3 O1\n1- 0\n-1 0\n"));
+        assert!(!blif.contains('#'));
+
+        // Each table can be traced back to its equation.
+        let blif = make_blif(true, &compiled.gal, &blueprint.pins, &blueprint.olmcs);
+        assert!(blif.contains("# O0_next from line 5\n.names I0 I1 I2 O0 O0_next\n"));
+        assert!(blif.contains("# O1 from line 6\n.names I2 I3 O1\n"));
+        assert!(blif.contains("# O2 from line 7\n"));
+        assert!(blif.contains("# O2_oe from line 8\n"));
+    }
+
+    #[test]
+    fn equations_round_trip() {
+        let header = "GAL22V10\nABEL\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC NC NC GND\n\
+                      NC O0 O1 O2 O3 NC NC NC NC NC NC VCC\n";
+        let source = format!(
+            "{}O0.R = I0 * I1 + O0 * /I2\n\
+             /O1 = I2 + I3\n\
+             O2.T = I0\nO2.E = /I1 * I3\n\
+             O3 = VCC\n\
+             AR = I3\nSP = GND\n",
+            header
+        );
+        let compiled = crate::compile(&source).unwrap();
+        let equations = make_equations(&compiled.blueprint);
+        assert_eq!(
+            equations,
+            "O0.R = I0 & I1 # O0 & !I2\n\
+             !O1 = I2 # I3\n\
+             O2.T = I0\n\
+             O2.E = !I1 & I3\n\
+             O3 = VCC\n\
+             AR = I3\n\
+             SP = GND\n"
+        );
+
+        // Assembling the listing gives the same fuses.
+        let relisted = crate::compile(&format!("{}{}", header, equations)).unwrap();
+        assert_eq!(compiled.gal.fuses, relisted.gal.fuses);
+        assert_eq!(compiled.gal.xor, relisted.gal.xor);
+        assert_eq!(compiled.gal.ac1, relisted.gal.ac1);
+        assert_eq!(make_equations(&relisted.blueprint), equations);
+    }
+
+    #[test]
+    fn cupl_export() {
+        let source = "GAL22V10\nExport 1\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC NC NC GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1 + O0 * /I2\n\
+                      /O1 = I2 + I3\n\
+                      O2.T = I0\nO2.E = /I1 * I3\n\
+                      AR = I3\nSP = GND\n";
+        let compiled = crate::compile(source).unwrap();
+        let cupl = make_cupl(&compiled.blueprint);
+        assert!(cupl.starts_with("Name     Export_1;\n"), "{}", cupl);
+        assert!(cupl.contains("Device   g22v10;\n"), "{}", cupl);
+        assert!(
+            cupl.contains(
+                "/* Pins */\n\n\
+                 PIN 1  = Clock;\n\
+                 PIN 2  = I0;\n\
+                 PIN 3  = I1;\n\
+                 PIN 4  = I2;\n\
+                 PIN 5  = I3;\n\
+                 PIN 14 = O0;\n\
+                 PIN 15 = O1;\n\
+                 PIN 16 = O2;\n"
+            ),
+            "{}",
+            cupl
+        );
+        assert!(
+            cupl.ends_with(
+                "/* Equations */\n\n\
+                 O0.d = I0 & I1 # O0 & !I2;\n\
+                 O0.ar = I3;\n\
+                 O0.sp = 'b'0;\n\
+                 !O1 = I2 # I3;\n\
+                 O2 = I0;\n\
+                 O2.oe = !I1 & I3;\n"
+            ),
+            "{}",
+            cupl
+        );
+
+        // The GAL20RA10's controls are all asynchronous.
+        let source = "GAL20RA10\nExport 2\n\
+                      /PL Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      /OE O0 NC NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0\nO0.CLK = Clock\nO0.ARST = I1\nO0.APRST = I2\n";
+        let compiled = crate::compile(source).unwrap();
+        let cupl = make_cupl(&compiled.blueprint);
+        assert!(
+            cupl.ends_with(
+                "O0.d = I0;\n\
+                 O0.ck = Clock;\n\
+                 O0.ar = I1;\n\
+                 O0.ap = I2;\n"
+            ),
+            "{}",
+            cupl
+        );
+    }
+
+    #[test]
+    fn padded_signature() {
+        let source = |sig: &str| {
+            format!(
+                "GAL16V8\n{}\n\
+                 I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                 I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                 O0 = I0\n",
+                sig
+            )
+        };
+        let config = crate::Config {
+            pad_sig: Some(b'.'),
+            ..crate::Config::default()
+        };
+        let content = crate::parser::parse_str(&source("ABC"), &config.parser).unwrap();
+        let gal = crate::build_stages(content, &config).unwrap().gal;
+        assert_eq!(gal::decode_sig(&gal), "ABC.....");
+        // '.' is 0x2e, stored MSB first.
+        let dot = [false, false, true, false, true, true, true, false];
+        for byte in gal.sig[24..].chunks(8) {
+            assert_eq!(byte, dot);
+        }
+
+        // The JEDEC file, checksums included, matches a signature
+        // written out in full.
+        let padded = crate::assemble_to_jedec(&source("ABC"), &config).unwrap();
+        let full =
+            crate::assemble_to_jedec(&source("ABC....."), &crate::Config::default()).unwrap();
+        let short = crate::assemble_to_jedec(&source("ABC"), &crate::Config::default()).unwrap();
+        assert_eq!(padded, full);
+        assert_ne!(padded, short);
+    }
+
+    #[test]
+    fn atomic_write() {
+        let dir = std::env::temp_dir().join(format!("galette_atomic_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("out.jed");
+        let target_name = target.to_str().unwrap();
+
+        let failed = write_atomically(target_name, |file| {
+            file.write_all(b"partial")?;
+            Err(Error::other("simulated failure"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        write_atomically(target_name, |file| file.write_all(b"complete")).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"complete");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logisim() {
+        let source = "GAL22V10\nLogisim\n\
+                      Clock I0 I1 I2 I3 NC NC NC NC NC NC GND\n\
+                      NC O0 O1 O2 NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1 + O0 * /I2\n\
+                      /O1 = I2 + /I3\n\
+                      O2.T = I0\nO2.E = I1 * I2\n";
+        let compiled = crate::compile(source).unwrap();
+        let blueprint = &compiled.blueprint;
+        let circ = make_logisim(&compiled.gal, &blueprint.pins, &blueprint.olmcs);
+
+        // Well-formed: after the declaration, every tag is closed in
+        // order.
+        let mut stack = Vec::new();
+        for tag in circ.split('<').skip(2) {
+            let tag = tag.split('>').next().unwrap();
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(stack.pop(), Some(name.to_string()), "{}", tag);
+            } else if !tag.ends_with('/') {
+                stack.push(tag.split_whitespace().next().unwrap().to_string());
+            }
+        }
+        assert!(stack.is_empty(), "{:?}", stack);
+
+        let count = |name: &str| circ.matches(&format!("name=\"{}\">", name)).count();
+        // Clock, I0-I3 and three outputs.
+        assert_eq!(count("Pin"), 8);
+        // Negated I2 and I3, and the active-low O1.
+        assert_eq!(count("NOT Gate"), 3);
+        // One per product, even single-literal ones.
+        assert_eq!(count("AND Gate"), 6);
+        // One per output, plus O2's enable.
+        assert_eq!(count("OR Gate"), 4);
+        assert_eq!(count("D Flip-Flop"), 1);
+        assert_eq!(count("Controlled Buffer"), 1);
+    }
+
+    #[test]
+    fn xref() {
+        let source = "GAL16V8\nXref\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0 * I1 + I0 * /I2\n\
+                      O1.T = O0 * I3\n\
+                      O1.E = I1\n\
+                      O2 = VCC\n";
+        let content = crate::parser::parse_str(source, &Default::default()).unwrap();
+        assert_eq!(
+            make_xref(&content),
+            "Pins read by equations:\n  \
+               I0 (pin 1): O0\n  \
+               I1 (pin 2): O0, O1.E\n  \
+               I2 (pin 3): O0\n  \
+               I3 (pin 4): O1.T\n  \
+               O0 (pin 12): O1.T\n\
+             Equations:\n  \
+               O0 (line 5): I0, I1, I2\n  \
+               O1.T (line 6): O0, I3\n  \
+               O1.E (line 7): I1\n  \
+               O2 (line 8): (none)\n"
+        );
+    }
+
+    #[test]
+    fn fuse_glyphs() {
+        let source = "GAL16V8\nGlyphs\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n";
+        let compiled = crate::compile(source).unwrap();
+        let pins = &compiled.blueprint.pins;
+
+        let default = make_fuse(Config::default().fuse_glyphs, false, pins, &compiled.gal);
+        assert!(default.contains("\n 56  --x- ---- ----"));
+
+        let swapped = make_fuse(('1', '0'), false, pins, &compiled.gal);
+        assert!(swapped.contains("\n 56  1101 1111 1111"));
+        assert_eq!(swapped, default.replace('-', "1").replace('x', "0"));
+    }
+
+    #[test]
+    fn jedec_full_rows() {
+        let source = "GAL16V8\nFull\n\
+                      I0 I1 I2 I3 I4 I5 I6 I7 I8 GND\n\
+                      I9 O0 O1 O2 O3 O4 O5 O6 O7 VCC\n\
+                      O0 = I0\n";
+        let gal = crate::compile(source).unwrap().gal;
+        let compact = make_jedec(&Config::default(), &gal);
+        let full_config = Config {
+            jedec_full_rows: true,
+            ..Config::default()
+        };
+        let full = make_jedec(&full_config, &gal);
+
+        let count_rows = |jedec: &str| jedec.lines().filter(|l| l.starts_with("*L")).count();
+        assert!(count_rows(&compact) < count_rows(&full));
+        // One line per row, plus XOR, signature, AC1, PT, SYN and AC0.
+        assert_eq!(count_rows(&full), 64 + 6);
+
+        let compact_sums = make_jedec_with_checksums(&Config::default(), &gal);
+        let full_sums = make_jedec_with_checksums(&full_config, &gal);
+        assert_eq!(compact_sums.fuse_checksum, full_sums.fuse_checksum);
+        let fuse_checksum = |jedec: &str| {
+            jedec
+                .lines()
+                .find(|l| l.starts_with("*C"))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(fuse_checksum(&compact), fuse_checksum(&full));
+    }
+
+    // Pin down the '*L' layout, so that it's guaranteed by the tests
+    // rather than by how itertools chunks and interleaves.
+    #[test]
+    fn jedec_offsets() {
+        let full_config = Config {
+            jedec_full_rows: true,
+            ..Config::default()
+        };
+        for chip in CHIPS.iter() {
+            let gal = GAL::new(*chip);
+            let jedec = make_jedec(&full_config, &gal);
+            let offsets = jedec
+                .lines()
+                .filter_map(|l| l.strip_prefix("*L"))
+                .map(|l| l.split(' ').next().unwrap().parse::<usize>().unwrap())
+                .collect::<Vec<_>>();
+
+            let mut expected = (0..chip.num_rows())
+                .map(|row| row * chip.num_cols())
+                .collect::<Vec<_>>();
+            let num_olmcs = chip.num_olmcs();
+            let sig = chip.ues_size() * 8;
+            let tail = match chip {
+                // XOR, signature, AC1, PT, SYN and AC0.
+                Chip::GAL16V8 | Chip::GAL20V8 => vec![num_olmcs, sig, num_olmcs, 64, 1, 1],
+                // Interleaved XOR and S1, then signature.
+                Chip::GAL22V10 => vec![2 * num_olmcs, sig],
+                Chip::GAL20RA10 => vec![num_olmcs, sig],
+            };
+            let mut offset = chip.logic_size();
+            for len in tail {
+                expected.push(offset);
+                offset += len;
+            }
+            assert_eq!(offset, chip.total_size(), "{}", chip.name());
+            assert_eq!(offsets, expected, "{}", chip.name());
+        }
+
+        // On the GAL22V10, each OLMC's XOR bit comes before its S1 bit,
+        // in OLMC order.
+        let mut gal = GAL::new(Chip::GAL22V10);
+        gal.xor = vec![
+            true, false, true, false, false, false, false, false, false, false,
+        ];
+        gal.ac1 = vec![
+            false, true, true, false, false, false, false, false, false, true,
+        ];
+        let jedec = make_jedec(&Config::default(), &gal);
+        assert!(jedec.contains("\n*L5808 10011100000000000001\n"));
+    }
+
+    #[test]
+    fn returned_checksums() {
+        let source = "GAL22V10\nSums\n\
+                      Clock I0 I1 I2 I3 I4 I5 I6 I7 I8 I9 GND\n\
+                      NC O0 O1 NC NC NC NC NC NC NC NC VCC\n\
+                      O0.R = I0 * I1\nO1 = I2\n";
+        let gal = crate::compile(source).unwrap().gal;
+        let jedec = make_jedec_with_checksums(&Config::default(), &gal);
+
+        let embedded = jedec
+            .jedec
+            .lines()
+            .find_map(|l| l.strip_prefix("*C"))
+            .unwrap();
+        assert_eq!(embedded, format!("{:04x}", jedec.fuse_checksum));
+        let trailing = jedec.jedec.rsplit('\x03').next().unwrap();
+        assert_eq!(trailing, format!("{:04x}\n", jedec.file_checksum));
+    }
+
     #[test]
     fn file_checksum_wraps() {
         let input = &[0xFF; 0x101];
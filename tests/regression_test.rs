@@ -71,7 +71,7 @@ fn check_invocation_failed(name: &str, messages: &HashMap<&str, &str>, res: std:
             name,
             *messages
                 .get(name)
-                .expect(&format!("No known error message for '{}'", name))
+                .unwrap_or_else(|| panic!("No known error message for '{}'", name))
         ),
         "'{:?}' produced unexpected output to stderr",
         name
@@ -90,7 +90,7 @@ fn ensure_contains(
     }
 
     let mut missing_names = contained_names
-        .difference(&containing_names)
+        .difference(containing_names)
         .collect::<Vec<_>>();
     missing_names.sort();
 
@@ -182,18 +182,448 @@ fn test_security_bit() -> Result<()> {
     Ok(())
 }
 
-const FAILURE_MESSAGES: [(&str, &str); 82] = [
+#[test]
+fn test_ues() -> Result<()> {
+    ensure_dir_exists("test_temp_ues")?;
+
+    std::fs::copy("testcases/ues/ues.pld", "test_temp_ues/ues.pld")?;
+
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_ues")
+        .args(["--ues", "0123456789ABCDEF", "ues.pld"])
+        .output()?;
+    check_invocation_succeeded("ues.pld", results);
+
+    check_output_matches("testcases/ues", "test_temp_ues")?;
+
+    // The UES is an option, so its errors aren't blamed on a source line.
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_ues")
+        .args(["--ues", "0123456789ABCDEF01", "ues.pld"])
+        .output()?;
+    assert!(!results.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&results.stderr).trim(),
+        "ues.pld: Error: too much user electronic signature data (max: 8 bytes, saw: 9)"
+    );
+
+    remove_dir_all("test_temp_ues")?;
+    Ok(())
+}
+
+#[test]
+fn test_fuse_offsets() -> Result<()> {
+    ensure_dir_exists("test_temp_fuse_offsets")?;
+
+    std::fs::copy(
+        "testcases/fuse_offsets/fuse_offsets.pld",
+        "test_temp_fuse_offsets/fuse_offsets.pld",
+    )?;
+
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_fuse_offsets")
+        .args(["--fuse-offsets", "--nochip", "--nopin", "fuse_offsets.pld"])
+        .output()?;
+    check_invocation_succeeded("fuse_offsets.pld", results);
+
+    check_output_matches("testcases/fuse_offsets", "test_temp_fuse_offsets")?;
+
+    remove_dir_all("test_temp_fuse_offsets")?;
+    Ok(())
+}
+
+#[test]
+fn test_jedec_header() -> Result<()> {
+    ensure_dir_exists("test_temp_jedec_header")?;
+
+    let name = "GAL16V8_reg.pld";
+    std::fs::copy(
+        format!("testcases/header_galasm/{}", name),
+        format!("test_temp_jedec_header/{}", name),
+    )?;
+    let assemble = |args: &[&str]| -> Result<String> {
+        let results = get_test_bin("galette")
+            .current_dir("test_temp_jedec_header")
+            .args(["--nofuse", "--nochip", "--nopin"])
+            .args(args)
+            .arg(name)
+            .output()?;
+        check_invocation_succeeded(name, results);
+        Ok(read_to_string("test_temp_jedec_header/GAL16V8_reg.jed")?)
+    };
+
+    // The same source gives GALasm's JEDEC file, or ours.
+    assert_eq!(
+        assemble(&["--header", "galasm"])?,
+        read_to_string("testcases/header_galasm/GAL16V8_reg.jed")?
+    );
+    assert_eq!(
+        assemble(&["--header", "galette"])?,
+        read_to_string("testcases/success/GAL16V8_reg.jed")?
+    );
+
+    let custom = assemble(&["--header", "custom", "--header-text", "Other 1.0"])?;
+    assert!(custom.starts_with("\x02\nGAL-Assembler:  Other 1.0\nDevice:"));
+
+    remove_dir_all("test_temp_jedec_header")?;
+    Ok(())
+}
+
+// Opt-in warnings, as (file name, flag, expected stderr).
+const WARNING_MESSAGES: [(&str, &str, &str); 5] = [
+    ("duplicates.pld", "--warn-duplicates", "duplicates.pld: Warning in line 9: O1 has the same equation as O0 (line 7)\n\
+     duplicates.pld: Warning in line 14: O4 has the same equation as O3 (line 13)\n"),
+    ("emptysig.pld", "--warn-signature", "emptysig.pld: Warning in line 2: the signature is blank; consider a label such as the design's name and revision\n"),
+    ("feedback.pld", "--warn-feedback", "feedback.pld: Warning in line 9: O0 is an output, so using it as an input reads the register's state via feedback, not the external pin\n\
+     feedback.pld: Warning in line 14: O2 is an output, so using it as an input reads its own value via feedback while enabled, and an external signal only while disabled\n\
+     feedback.pld: Warning in line 14: O1 is an output, so using it as an input reads its own value via feedback, not an external signal\n"),
+    ("redundant.pld", "--warn-redundant", "redundant.pld: Warning in line 7: O0 has product 'I0 * I1 * I2', which is covered by 'I0 * I1' and can be removed\n\
+     redundant.pld: Warning in line 9: O1 has product '/I2 * I3', which is covered by 'I3' and can be removed\n\
+     redundant.pld: Warning in line 13: O3 has product 'I0 * I1 * I4', which is covered by 'I0 * I1' and can be removed\n"),
+    ("unused.pld", "--warn-unused-olmcs", "unused.pld: Warning in line 5: Spare (pin 15) is an output pin with no equation, and isn't used as an input; name it NC if it's meant to be unused\n"),
+];
+
+#[test]
+fn test_warnings() -> Result<()> {
+    ensure_dir_exists("test_temp_warning")?;
+
+    for (name, flag, expected) in WARNING_MESSAGES.iter() {
+        std::fs::copy(
+            format!("testcases/warning/{}", name),
+            format!("test_temp_warning/{}", name),
+        )?;
+
+        // Without the flag, there should be no warnings.
+        let results = get_test_bin("galette")
+            .current_dir("test_temp_warning")
+            .arg(name)
+            .output()?;
+        check_invocation_succeeded(name, results);
+
+        let results = get_test_bin("galette")
+            .current_dir("test_temp_warning")
+            .args([flag, name])
+            .output()?;
+        assert!(results.status.success(), "'{:?}' did not succeed", name);
+        assert_eq!(
+            std::str::from_utf8(&results.stderr).unwrap(),
+            *expected,
+            "'{:?}' produced unexpected warnings",
+            name
+        );
+    }
+
+    remove_dir_all("test_temp_warning")?;
+    Ok(())
+}
+
+// Files that only fail when given an option, as (file name, option,
+// expected error).
+const OPTION_FAILURE_MESSAGES: [(&str, &str, &str); 1] = [(
+    "nodescription.pld",
+    "--require-description",
+    "Error in line 9: expected DESCRIPTION section, found end of file\n",
+)];
+
+#[test]
+fn test_option_failures() -> Result<()> {
+    ensure_dir_exists("test_temp_option_failure")?;
+
+    for (name, option, message) in OPTION_FAILURE_MESSAGES.iter() {
+        std::fs::copy(
+            format!("testcases/option_failure/{}", name),
+            format!("test_temp_option_failure/{}", name),
+        )?;
+
+        // Without the option, the file should assemble.
+        let results = get_test_bin("galette")
+            .current_dir("test_temp_option_failure")
+            .arg(name)
+            .output()?;
+        check_invocation_succeeded(name, results);
+
+        let results = get_test_bin("galette")
+            .current_dir("test_temp_option_failure")
+            .args([option, name])
+            .output()?;
+        let messages = HashMap::from([(*name, *message)]);
+        check_invocation_failed(name, &messages, results);
+    }
+
+    remove_dir_all("test_temp_option_failure")?;
+    Ok(())
+}
+
+#[test]
+fn test_require_description() -> Result<()> {
+    ensure_dir_exists("test_temp_require_description")?;
+
+    let name = "GAL16V8_combinatorial.pld";
+    std::fs::copy(
+        format!("testcases/success/{}", name),
+        format!("test_temp_require_description/{}", name),
+    )?;
+
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_require_description")
+        .args(["--require-description", name])
+        .output()?;
+    check_invocation_succeeded(name, results);
+
+    remove_dir_all("test_temp_require_description")?;
+    Ok(())
+}
+
+#[test]
+fn test_dry_run() -> Result<()> {
+    ensure_dir_exists("test_temp_dry_run")?;
+
+    let name = "GAL16V8_combinatorial.pld";
+    std::fs::copy(
+        format!("testcases/success/{}", name),
+        format!("test_temp_dry_run/{}", name),
+    )?;
+
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_dry_run")
+        .args(["--dry-run", "--nochip", name])
+        .output()?;
+    assert!(results.status.success(), "'{:?}' did not succeed", name);
+    assert!(results.stderr.is_empty());
+
+    let stdout = std::str::from_utf8(&results.stdout).unwrap();
+    let listed = stdout.lines().collect::<Vec<_>>();
+    let mut expected = Vec::new();
+    for ext in ["fus", "jed", "pin"] {
+        let golden = read_to_string(format!("testcases/success/GAL16V8_combinatorial.{}", ext))?;
+        expected.push(format!(
+            "GAL16V8_combinatorial.{} ({} bytes)",
+            ext,
+            golden.len()
+        ));
+    }
+    assert_eq!(listed, expected);
+
+    // Nothing but the input should be on disk.
+    let files = fs::read_dir("test_temp_dry_run")?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(files, [name]);
+
+    remove_dir_all("test_temp_dry_run")?;
+    Ok(())
+}
+
+#[test]
+fn test_batch() -> Result<()> {
+    ensure_dir_exists("test_temp_batch")?;
+
+    let names = ["GAL16V8_combinatorial", "GAL22V10_reg"];
+    for name in names.iter() {
+        std::fs::copy(
+            format!("testcases/success/{}.pld", name),
+            format!("test_temp_batch/{}.pld", name),
+        )?;
+    }
+    // This copy relies on the manifest for its chip type.
+    let source = read_to_string("testcases/success/GAL16V8_combinatorial.pld")?;
+    std::fs::write(
+        "test_temp_batch/chipless.pld",
+        source.strip_prefix("GAL16V8\n").unwrap(),
+    )?;
+    std::fs::write(
+        "test_temp_batch/build.toml",
+        "[[file]]\n\
+         input = \"GAL16V8_combinatorial.pld\"\n\
+         secure = true\n\
+         \n\
+         [[file]]\n\
+         input = \"GAL22V10_reg.pld\"\n\
+         signature = \"BATCH\"\n\
+         output_dir = \"out\"\n\
+         \n\
+         [[file]]\n\
+         input = \"chipless.pld\"\n\
+         chip = \"GAL16V8\"\n",
+    )?;
+
+    let results = get_test_bin("galette")
+        .args([
+            "--batch",
+            "test_temp_batch/build.toml",
+            "--nochip",
+            "--nofuse",
+            "--nopin",
+        ])
+        .output()?;
+    assert!(results.stderr.is_empty(), "{:?}", results);
+    assert!(results.status.success());
+    assert_eq!(
+        std::str::from_utf8(&results.stdout).unwrap(),
+        "3 of 3 files assembled\n"
+    );
+
+    let secure = read_to_string("test_temp_batch/GAL16V8_combinatorial.jed")?;
+    assert!(secure.contains("*G1\n"));
+    let signed = read_to_string("test_temp_batch/out/GAL22V10_reg.jed")?;
+    let golden = read_to_string("testcases/success/GAL22V10_reg.jed")?;
+    assert!(signed.contains("*G0\n"));
+    assert_ne!(signed, golden);
+    assert!(!Path::new("test_temp_batch/GAL22V10_reg.jed").exists());
+    let chipless = read_to_string("test_temp_batch/chipless.jed")?;
+    let golden = read_to_string("testcases/success/GAL16V8_combinatorial.jed")?;
+    assert_eq!(chipless, golden);
+
+    // A manifest's chip must match the source's chip line.
+    std::fs::write(
+        "test_temp_batch/mismatch.toml",
+        "[[file]]\n\
+         input = \"GAL16V8_combinatorial.pld\"\n\
+         chip = \"GAL22V10\"\n",
+    )?;
+    let results = get_test_bin("galette")
+        .args(["--batch", "test_temp_batch/mismatch.toml"])
+        .output()?;
+    assert_eq!(results.status.code(), Some(1));
+    assert_eq!(
+        std::str::from_utf8(&results.stderr).unwrap(),
+        "test_temp_batch/GAL16V8_combinatorial.pld: Error in line 1: \
+         the source is for the GAL16V8, but the GAL22V10 was expected\n"
+    );
+
+    // A missing manifest is an error, not a panic.
+    let results = get_test_bin("galette")
+        .args(["--batch", "test_temp_batch/missing.toml"])
+        .output()?;
+    assert_eq!(results.status.code(), Some(1));
+    assert!(std::str::from_utf8(&results.stderr)
+        .unwrap()
+        .starts_with("test_temp_batch/missing.toml: Error: unable to read the file: "));
+
+    remove_dir_all("test_temp_batch")?;
+    Ok(())
+}
+
+#[test]
+fn test_watch_conflicts() -> Result<()> {
+    // These only make sense for a single build, so are rejected
+    // before any watching starts.
+    for flag in [
+        "--stats",
+        "--xref",
+        "--fuse-hex",
+        "--profile",
+        "--auto-signature",
+    ] {
+        let results = get_test_bin("galette")
+            .args([
+                "--watch",
+                flag,
+                "testcases/success/GAL16V8_combinatorial.pld",
+            ])
+            .output()?;
+        assert!(!results.status.success(), "{}", flag);
+        let stderr = String::from_utf8_lossy(&results.stderr);
+        assert!(
+            stderr.contains("cannot be used with"),
+            "{}: {}",
+            flag,
+            stderr
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_profile() -> Result<()> {
+    ensure_dir_exists("test_temp_profile")?;
+
+    std::fs::copy(
+        "testcases/success/GAL16V8_combinatorial.pld",
+        "test_temp_profile/GAL16V8_combinatorial.pld",
+    )?;
+    std::fs::write(
+        "test_temp_profile/build.toml",
+        "[[file]]\n\
+         input = \"GAL16V8_combinatorial.pld\"\n\
+         \n\
+         [[file]]\n\
+         input = \"GAL16V8_combinatorial.pld\"\n\
+         output_dir = \"out\"\n",
+    )?;
+
+    let results = get_test_bin("galette")
+        .args(["--batch", "test_temp_profile/build.toml", "--profile"])
+        .output()?;
+    assert!(results.status.success(), "{:?}", results);
+    let stdout = std::str::from_utf8(&results.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("2 of 2 files assembled"));
+    assert_eq!(lines.next(), Some("Profile of 2 file(s):"));
+    let labels = lines
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["parse", "blueprint", "build", "write", "total"]);
+
+    remove_dir_all("test_temp_profile")?;
+    Ok(())
+}
+
+#[test]
+fn test_manifest() -> Result<()> {
+    ensure_dir_exists("test_temp_manifest")?;
+
+    let name = "GAL22V10_reg.pld";
+    std::fs::copy(
+        format!("testcases/success/{}", name),
+        format!("test_temp_manifest/{}", name),
+    )?;
+
+    let results = get_test_bin("galette")
+        .current_dir("test_temp_manifest")
+        .args(["--manifest", name])
+        .output()?;
+    check_invocation_succeeded(name, results);
+
+    // The manifest's checksums should be the ones in the JEDEC file.
+    let jedec = read_to_string("test_temp_manifest/GAL22V10_reg.jed")?;
+    let manifest = read_to_string("test_temp_manifest/GAL22V10_reg.manifest.json")?;
+
+    let fuse_checksum = jedec
+        .lines()
+        .find_map(|line| line.strip_prefix("*C"))
+        .unwrap();
+    let file_checksum = jedec.rsplit('\x03').next().unwrap().trim_end();
+
+    assert!(
+        manifest.contains(&format!("\"fuse_checksum\": \"{}\"", fuse_checksum)),
+        "{}",
+        manifest
+    );
+    assert!(
+        manifest.contains(&format!("\"file_checksum\": \"{}\"", file_checksum)),
+        "{}",
+        manifest
+    );
+    assert!(manifest.contains("\"chip\": \"GAL22V10\""), "{}", manifest);
+    assert!(manifest.contains("\"GAL22V10_reg.pin\""), "{}", manifest);
+
+    remove_dir_all("test_temp_manifest")?;
+    Ok(())
+}
+
+const FAILURE_MESSAGES: [(&str, &str); 97] = [
     ("GAL16V8_badname.pld", "Error in line 1: unexpected GAL type found: 'GAL16V8x'\n"),
-    ("GAL16V8_complex_12.pld", "Error in line 9: pin 12 can't be used as input in complex mode\n"),
-    ("GAL16V8_complex_19.pld", "Error in line 9: pin 19 can't be used as input in complex mode\n"),
+    ("GAL16V8_complex_12.pld", "Error in line 9: pin 12 can't be used as input in complex mode, which is selected by tristate outputs or outputs used as inputs; only simple and registered modes allow it\n"),
+    ("GAL16V8_complex_19.pld", "Error in line 9: pin 19 can't be used as input in complex mode, which is selected by tristate outputs or outputs used as inputs; only simple and registered modes allow it\n"),
     ("GAL16V8_reg_1.pld", "Error in line 7: pin 1 is reserved for 'Clock' in registered mode\n"),
     ("GAL16V8_reg_11.pld", "Error in line 7: pin 11 is reserved for '/OE' in registered mode\n"),
     ("GAL20RA10_badname.pld", "Error in line 1: unexpected GAL type found: 'GAL20RA10x'\n"),
     ("GAL20RA10_pin1.pld", "Error in line 7: pin 1 is reserved for '/PL' on GAL20RA10 devices and can't be used in equations\n"),
     ("GAL20RA10_pin13.pld", "Error in line 7: pin 13 is reserved for '/OE' on GAL20RA10 devices and can't be used in equations\n"),
     ("GAL20V8_badname.pld", "Error in line 1: unexpected GAL type found: 'GAL20V8x'\n"),
-    ("GAL20V8_complex_15.pld", "Error in line 9: pin 15 can't be used as input in complex mode\n"),
-    ("GAL20V8_complex_22.pld", "Error in line 9: pin 22 can't be used as input in complex mode\n"),
+    ("GAL20V8_complex_15.pld", "Error in line 9: pin 15 can't be used as input in complex mode, which is selected by tristate outputs or outputs used as inputs; only simple and registered modes allow it\n"),
+    ("GAL20V8_complex_22.pld", "Error in line 9: pin 22 can't be used as input in complex mode, which is selected by tristate outputs or outputs used as inputs; only simple and registered modes allow it\n"),
     ("GAL20V8_complex_in.pld", "Error in line 5: pinname I8 is defined twice\n"),
     ("GAL20V8_reg_1.pld", "Error in line 7: pin 1 is reserved for 'Clock' in registered mode\n"),
     ("GAL20V8_reg_13.pld", "Error in line 7: pin 13 is reserved for '/OE' in registered mode\n"),
@@ -201,26 +631,32 @@ const FAILURE_MESSAGES: [(&str, &str); 82] = [
     ("arbad.pld", "Error in line 5: GAL22V10: AR is not allowed as pinname\n"),
     ("badarext.pld", "Error in line 23: no suffix is allowed for AR\n"),
     ("badarusage.pld", "Error in line 21: use of AR is not allowed in equations\n"),
-    ("badclk.pld", "Error in line 7: .CLK is not allowed when this type of GAL is used\n"),
-    ("badgnd.pld", "Error in line 4: pin 8 cannot be named GND, because the name is reserved for pin 10\n"),
+    ("badclk.pld", "Error in line 7: .CLK is not allowed on the GAL16V8, as registers are always clocked by pin 1 (a registered output may only give pin 1 as its .CLK, to document this)\n"),
+    ("badclk22v10.pld", "Error in line 11: .CLK is not allowed on the GAL22V10, as registers are always clocked by pin 1 (a registered output may only give pin 1 as its .CLK, to document this)\n"),
+    ("badgnd.pld", "Error in line 4: GND is a reserved name for the power pin, pin 10, so pin 8 can't use it as a signal name\n"),
     ("badname.pld", "Error in line 1: unexpected GAL type found: 'GAL42V13'\n"),
     ("badpinstart.pld", "Error in line 4: expected pin, found other token\n"),
     ("badprst.pld", "Error in line 7: .APRST is not allowed when this type of GAL is used\n"),
     ("badrst.pld", "Error in line 7: .ARST is not allowed when this type of GAL is used\n"),
     ("badspext.pld", "Error in line 23: no suffix is allowed for SP\n"),
     ("badspusage.pld", "Error in line 21: use of SP is not allowed in equations\n"),
-    ("badvcc.pld", "Error in line 4: pin 8 cannot be named VCC, because the name is reserved for pin 20\n"),
+    ("badvcc.pld", "Error in line 4: VCC is a reserved name for the power pin, pin 20, so pin 8 can't use it as a signal name\n"),
     ("continuation_bad.pld", "Error in line 12: expected pin, found other token\n"),
+    ("continuation_desc.pld", "Error in line 9: expected continuation of equation after trailing operator, found end of file\n"),
+    ("continuation_equals_twice.pld", "Error in line 8: expected +, #, * or &, found other token\n"),
+    ("continuation_eof.pld", "Error in line 9: expected continuation of equation after trailing operator, found end of file\n"),
+    ("eqnbeforepins.pld", "Error in line 4: expected pin definitions, found an equation; the two rows of pin names must come before any equations\n"),
     ("inputonly.pld", "Error in line 7: this pin can't be used as output\n"),
     ("logicgnd.pld", "Error in line 7: use of VCC and GND is not allowed in equations\n"),
     ("logicvcc.pld", "Error in line 7: use of VCC and GND is not allowed in equations\n"),
-    ("longext.pld", "Error in line 7: unknown suffix found: 'TOOLONGEXTENSION'\n"),
-    ("multiar.pld", "Error in line 23: only one product term allowed (no OR)\n"),
-    ("multiclk.pld", "Error in line 22: only one product term allowed (no OR)\n"),
-    ("multiena.pld", "Error in line 15: only one product term allowed (no OR)\n"),
-    ("multiprst.pld", "Error in line 22: only one product term allowed (no OR)\n"),
-    ("multirst.pld", "Error in line 22: only one product term allowed (no OR)\n"),
-    ("multisp.pld", "Error in line 23: only one product term allowed (no OR)\n"),
+    ("longext.pld", "Error in line 7: unknown suffix found: 'TOOLONGEXTENSION'; the valid suffixes are .T, .R, .E and .POL\n"),
+    ("midvcc.pld", "Error in line 5: VCC is a reserved name for the power pin, pin 20, so pin 15 can't use it as a signal name\n"),
+    ("multiar.pld", "Error in line 23: AR must be a single product term, so can't use OR ('+' or '#')\n"),
+    ("multiclk.pld", "Error in line 22: .CLK must be a single product term, so can't use OR ('+' or '#')\n"),
+    ("multiena.pld", "Error in line 15: .E must be a single product term, so can't use OR ('+' or '#')\n"),
+    ("multiprst.pld", "Error in line 22: .APRST must be a single product term, so can't use OR ('+' or '#')\n"),
+    ("multirst.pld", "Error in line 22: .ARST must be a single product term, so can't use OR ('+' or '#')\n"),
+    ("multisp.pld", "Error in line 23: SP must be a single product term, so can't use OR ('+' or '#')\n"),
     ("nclhs.pld", "Error in line 17: NC (Not Connected) is not allowed in logic equations\n"),
     ("ncpin.pld", "Error in line 9: NC (Not Connected) is not allowed in logic equations\n"),
     ("negaprst.pld", "Error in line 25: negation of .APRST is not allowed\n"),
@@ -246,20 +682,29 @@ const FAILURE_MESSAGES: [(&str, &str); 82] = [
     ("pinbadneg.pld", "Error in line 4: pin name expected after '/', found non-alphabetic character ' '\n"),
     ("pinrepeated.pld", "Error in line 4: pinname I5 is defined twice\n"),
     ("plaintri.pld", "Error in line 8: tristate control without previous '.T'\n"),
+    ("pol_inverted.pld", "Error in line 10: negation of .POL is not allowed\n"),
+    ("pol_nonconst.pld", "Error in line 10: polarity must be VCC (active high) or GND (active low)\n"),
+    ("pol_undefined.pld", "Error in line 9: the output must be defined to use .POL\n"),
+    ("regequals_ar.pld", "Error in line 23: ':=' can only be used to assign output pins\n"),
+    ("regequals_suffix.pld", "Error in line 9: ':=' already means registered, so the pin can't also have a suffix\n"),
     ("regtri.pld", "Error in line 8: GAL16V8/20V8: tri. control for reg. output is not allowed\n"),
     ("repar.pld", "Error in line 25: AR is defined twice\n"),
     ("reparst.pld", "Error in line 26: multiple .APRST definitions for the same output\n"),
     ("repclk.pld", "Error in line 9: multiple .CLK definitions for the same output\n"),
     ("repena.pld", "Error in line 19: multiple .E definitions for the same output\n"),
-    ("reppin.pld", "Error in line 17: output O4 is defined multiple times\n"),
+    ("reppin.pld", "Error in line 17: output O4 is defined as tristate (.T), but line 15 defines it as combinatorial\n"),
     ("reprst.pld", "Error in line 26: multiple .ARST definitions for the same output\n"),
     ("repsp.pld", "Error in line 25: SP is defined twice\n"),
     ("spbad.pld", "Error in line 5: GAL22V10: SP is not allowed as pinname\n"),
+    ("swappedpower.pld", "Error in line 4: pin 10 must be named GND, not VCC; are VCC and GND swapped?\n"),
+    ("tab_eqn_name.pld", "Error in line 7: tab found inside pin name (tabs always separate pin names)\n"),
+    ("tab_eqn_split.pld", "Error in line 7: expected +, #, * or & before 'I2' (tabs and spaces always separate pin names)\n"),
+    ("tab_pin_name.pld", "Error in line 5: wrong number of pins on pin definition line - expected 10, found 11 (tabs separate pin names, so check for a tab inside a name)\n"),
     ("threeline.pld", "Error in line 2: expected pin definitions, found end of file\n"),
     ("toofewpins.pld", "Error in line 5: wrong number of pins on pin definition line - expected 10, found 9\n"),
     ("toomanyterms_io.pld", "Error in line 7: too many product terms in sum for pin (max: 7, saw: 8)\n"),
     ("twoline.pld", "Error in line 2: expected pin definitions, found end of file\n"),
-    ("unkext.pld", "Error in line 7: unknown suffix found: 'UNK'\n"),
+    ("unkext.pld", "Error in line 7: unknown suffix found: 'UNK'; the valid suffixes are .T, .R, .E and .POL\n"),
     ("unklhs.pld", "Error in line 17: unknown pinname 'DUNNO'\n"),
     ("unkpin.pld", "Error in line 9: unknown pinname 'Unknown'\n"),
     ("unregclk.pld", "Error in line 11: use of .CLK is only allowed for registered outputs\n"),
@@ -288,3 +733,330 @@ fn test_failing_generation() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_colour() -> Result<()> {
+    let failure_messages = HashMap::from(FAILURE_MESSAGES);
+
+    // Without colour, the messages are the usual ones.
+    for name in get_plds("testcases/failure")?.iter() {
+        let results = get_test_bin("galette")
+            .current_dir("testcases/failure")
+            .args(["--color", "never", name])
+            .output()?;
+        check_invocation_failed(name, &failure_messages, results);
+    }
+
+    let results = get_test_bin("galette")
+        .current_dir("testcases/failure")
+        .args(["--color", "always", "ncpin.pld"])
+        .output()?;
+    assert!(!results.status.success());
+    assert_eq!(
+        std::str::from_utf8(&results.stderr).unwrap(),
+        "ncpin.pld: \x1b[1;31mError in line 9:\x1b[0m NC (Not Connected) is not allowed in logic equations\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lint() -> Result<()> {
+    ensure_dir_exists("test_temp_lint")?;
+
+    std::fs::write(
+        "test_temp_lint/lint.pld",
+        "GAL16V8\n\
+         \n\
+         ; 1  2  3  4  5  6  7  8  9\n\
+         I0 I1 I2 NC NC NC NC NC NC GND\n\
+         NC NC O0 O1 O2 Spare NC NC NC VCC\n\
+         O0 = I0 * I1 + I0 * I1 * I2\n\
+         O1 = I0 * I1 + I0 * I1 * I2\n\
+         O2 = O0 * I2\n",
+    )?;
+
+    let results = get_test_bin("galette")
+        .args(["lint", "test_temp_lint/lint.pld"])
+        .output()?;
+    assert!(results.status.success(), "{:?}", results);
+    let stdout = std::str::from_utf8(&results.stdout).unwrap();
+    let headings = stdout
+        .lines()
+        .filter(|line| line.ends_with(':'))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headings,
+        [
+            "Source:",
+            "Unused pins:",
+            "Feedback:",
+            "Duplicate outputs:",
+            "Redundant terms:",
+            "Signature:"
+        ]
+    );
+    assert_eq!(stdout.lines().last(), Some("7 warning(s)"));
+
+    // Only the source is left.
+    let files = std::fs::read_dir("test_temp_lint")?.count();
+    assert_eq!(files, 1);
+
+    remove_dir_all("test_temp_lint")?;
+    Ok(())
+}
+
+// Comparing against GALasm needs a GALasm binary, given by the GALASM
+// environment variable. Without one, only the error is checked.
+#[test]
+fn test_compare_galasm() -> Result<()> {
+    let results = get_test_bin("galette")
+        .args([
+            "compare",
+            "--galasm",
+            "testcases/no_such_galasm",
+            "testcases/success/GAL16V8_reg.pld",
+        ])
+        .output()?;
+    assert!(!results.status.success());
+    assert!(results.stdout.is_empty(), "{:?}", results);
+    let stderr = std::str::from_utf8(&results.stderr).unwrap();
+    assert!(
+        stderr.starts_with("unable to run GALasm 'testcases/no_such_galasm': "),
+        "{}",
+        stderr
+    );
+    assert_eq!(stderr.lines().count(), 1);
+
+    let galasm = match std::env::var("GALASM") {
+        Ok(galasm) => galasm,
+        Err(_) => {
+            eprintln!("GALASM not set, skipping comparison");
+            return Ok(());
+        }
+    };
+    // The GALasm examples, rather than the tests of galette's
+    // extensions.
+    for name in get_plds("testcases/success")?.iter() {
+        if !name.starts_with("GAL") {
+            continue;
+        }
+        let results = get_test_bin("galette")
+            .args(["compare", "--galasm", &galasm])
+            .arg(format!("testcases/success/{}", name))
+            .output()?;
+        assert!(results.status.success(), "{}: {:?}", name, results);
+    }
+    Ok(())
+}
+
+fn run_lsp(source: &str) -> Result<String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = get_test_bin("galette")
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(source.as_bytes())?;
+    let results = child.wait_with_output()?;
+    assert!(results.status.success(), "{:?}", results);
+    assert!(results.stderr.is_empty(), "{:?}", results);
+    Ok(String::from_utf8(results.stdout)?)
+}
+
+#[test]
+fn test_lsp() -> Result<()> {
+    let header = "GAL16V8\n\
+                  LSP\n\
+                  I0 I1 I2 NC NC NC NC NC NC GND\n\
+                  NC NC NC NC NC NC NC O1 O0 VCC\n";
+
+    let diagnostics = run_lsp(&format!("{}O0 = I0 * NC\n", header))?;
+    assert_eq!(
+        diagnostics,
+        "[\n  {\"range\": {\"start\": {\"line\": 4, \"character\": 0}, \
+         \"end\": {\"line\": 4, \"character\": 12}}, \"severity\": 1, \"source\": \"galette\", \
+         \"message\": \"NC (Not Connected) is not allowed in logic equations\"}\n]\n"
+    );
+
+    // Lint warnings are included, as severity 2.
+    let diagnostics = run_lsp(&format!("{}O0 = I0\nO1 = I0\n", header))?;
+    assert_eq!(diagnostics.lines().count(), 3);
+    assert!(diagnostics.contains("\"line\": 4,"));
+    assert!(diagnostics.contains("\"severity\": 2, \"source\": \"galette\", \"message\": \"O0 has the same equation as O1 (line 6)\""));
+
+    assert_eq!(run_lsp(&format!("{}O0 = I0\nO1 = I1\n", header))?, "[]\n");
+    Ok(())
+}
+
+#[test]
+fn test_options_lines() -> Result<()> {
+    ensure_dir_exists("test_temp_options")?;
+    std::fs::write(
+        "test_temp_options/options.pld",
+        "GAL16V8\n\
+         Options\n\
+         OPTIONS secure nochip\n\
+         I0 I1 NC NC NC NC NC NC NC GND\n\
+         NC NC NC NC NC NC NC NC O0 VCC\n\
+         O0 = I0 * I1\n",
+    )?;
+
+    let results = get_test_bin("galette")
+        .arg("test_temp_options/options.pld")
+        .output()?;
+    check_invocation_succeeded("options.pld", results);
+    let jedec = read_to_string("test_temp_options/options.jed")?;
+    assert!(jedec.contains("\n*G1\n"), "{}", jedec);
+    assert!(Path::new("test_temp_options/options.fus").exists());
+    assert!(!Path::new("test_temp_options/options.chp").exists());
+
+    // The command line can add to the options, or replace them.
+    let results = get_test_bin("galette")
+        .args(["--nofuse", "test_temp_options/options.pld"])
+        .output()?;
+    check_invocation_succeeded("options.pld", results);
+    assert!(!Path::new("test_temp_options/options.chp").exists());
+
+    fs::remove_file("test_temp_options/options.fus")?;
+    let results = get_test_bin("galette")
+        .args([
+            "--ignore-options",
+            "--nofuse",
+            "test_temp_options/options.pld",
+        ])
+        .output()?;
+    check_invocation_succeeded("options.pld", results);
+    let jedec = read_to_string("test_temp_options/options.jed")?;
+    assert!(jedec.contains("\n*G0\n"), "{}", jedec);
+    assert!(Path::new("test_temp_options/options.chp").exists());
+    assert!(!Path::new("test_temp_options/options.fus").exists());
+
+    remove_dir_all("test_temp_options")?;
+    Ok(())
+}
+
+#[test]
+fn test_describe() -> Result<()> {
+    ensure_dir_exists("test_temp_describe")?;
+
+    // Regenerating the .pin and .chp files from each .jed, with the
+    // pin names from the original, gives the original files.
+    for pld in get_plds("testcases/success")?.iter() {
+        let pld = Path::new("testcases/success").join(pld);
+        let name = pld.file_stem().unwrap().to_str().unwrap();
+        let jed = format!("test_temp_describe/{}.jed", name);
+        fs::copy(pld.with_extension("jed"), &jed)?;
+        let pin = read_to_string(pld.with_extension("pin"))?;
+        let pin_names = pin
+            .lines()
+            .skip(4)
+            .filter_map(|line| line.split('|').nth(1))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let results = get_test_bin("galette")
+            .args(["describe", "--pins", &pin_names, &jed])
+            .output()?;
+        check_invocation_succeeded(&jed, results);
+        for ext in ["pin", "chp"].iter() {
+            let expected = read_to_string(pld.with_extension(ext))?;
+            let actual = read_to_string(Path::new(&jed).with_extension(ext))?;
+            assert_eq!(actual, expected, "{}.{}", name, ext);
+        }
+    }
+
+    // Without names, the pins are numbered.
+    let results = get_test_bin("galette")
+        .args(["describe", "test_temp_describe/GAL16V8_reg.jed"])
+        .output()?;
+    check_invocation_succeeded("GAL16V8_reg.jed", results);
+    let pin = read_to_string("test_temp_describe/GAL16V8_reg.pin")?;
+    assert!(pin.contains("   1   | P1       | Clock\n"), "{}", pin);
+    assert!(pin.contains("  10   | GND      | GND\n"), "{}", pin);
+
+    let results = get_test_bin("galette")
+        .args([
+            "describe",
+            "--pins",
+            "A,B",
+            "test_temp_describe/GAL16V8_reg.jed",
+        ])
+        .output()?;
+    assert!(!results.status.success());
+    assert!(std::str::from_utf8(&results.stderr)?
+        .contains("expected 20 pin names for the GAL16V8, found 2"));
+
+    // A missing file is an error, not a panic.
+    let results = get_test_bin("galette")
+        .args(["describe", "test_temp_describe/missing.jed"])
+        .output()?;
+    assert_eq!(results.status.code(), Some(1));
+    assert!(std::str::from_utf8(&results.stderr)?
+        .starts_with("test_temp_describe/missing.jed: Error: unable to read the file: "));
+
+    remove_dir_all("test_temp_describe")?;
+    Ok(())
+}
+
+#[test]
+fn test_format_missing_file() -> Result<()> {
+    let results = get_test_bin("galette")
+        .args(["--format", "testcases/missing.pld"])
+        .output()?;
+    assert_eq!(results.status.code(), Some(1));
+    assert!(std::str::from_utf8(&results.stderr)?
+        .starts_with("testcases/missing.pld: Error: unable to read the file: "));
+    Ok(())
+}
+
+#[test]
+fn test_require_signature() -> Result<()> {
+    ensure_dir_exists("test_temp_require_signature")?;
+    let pins = "I0 I1 NC NC NC NC NC NC NC GND\n\
+                NC NC NC NC NC NC NC NC O0 VCC\n\
+                O0 = I0 * I1\n";
+    std::fs::write(
+        "test_temp_require_signature/blank.pld",
+        format!("GAL16V8\n   \n{}", pins),
+    )?;
+    std::fs::write(
+        "test_temp_require_signature/short.pld",
+        format!("GAL16V8\nA\n{}", pins),
+    )?;
+
+    // A blank signature is only an error when one is required.
+    let results = get_test_bin("galette")
+        .arg("test_temp_require_signature/blank.pld")
+        .output()?;
+    check_invocation_succeeded("blank.pld", results);
+
+    let results = get_test_bin("galette")
+        .args([
+            "--require-signature",
+            "test_temp_require_signature/blank.pld",
+        ])
+        .output()?;
+    assert!(!results.status.success());
+    assert_eq!(
+        std::str::from_utf8(&results.stderr)?,
+        "test_temp_require_signature/blank.pld: Error in line 2: \
+         the signature is blank, but a signature is required\n"
+    );
+
+    // A short one will do.
+    let results = get_test_bin("galette")
+        .args([
+            "--require-signature",
+            "test_temp_require_signature/short.pld",
+        ])
+        .output()?;
+    check_invocation_succeeded("short.pld", results);
+
+    remove_dir_all("test_temp_require_signature")?;
+    Ok(())
+}